@@ -0,0 +1,87 @@
+use std::time::{Duration, Instant};
+
+use compiler::EbnfParser;
+
+const GRAMMAR: &str = include_str!("minicpu.ebnf");
+
+//a MiniCPU program with `functions` functions, each with a loop, a branch and some arithmetic
+fn source(functions: usize) -> String {
+    let mut source = String::from("counter: int = 0;\n");
+    for i in 0..functions {
+        source += &format!(
+            "function f{i}(a: int, b: int) -> int {{\n    \
+                 c: int = a * {i} + b;\n    \
+                 while (c > 0) {{\n        \
+                     c -= 1;\n        \
+                     if (c % 2 == 0) {{ counter += c; }} elif (c == 1) {{ counter = 0x1f; }} else {{ print(c); }}\n    \
+                 }}\n    \
+                 for (i = 0; i < b; i += 1) {{ c = c.max(i) + -a; }}\n    \
+                 return c;\n\
+             }}\n"
+        );
+    }
+    source + "f0(1, 2);\n"
+}
+
+//best of `runs` to keep the noise of other processes out
+fn measure(runs: usize, mut run: impl FnMut()) -> Duration {
+    (0..runs)
+        .map(|_| {
+            let start = Instant::now();
+            run();
+            start.elapsed()
+        })
+        .min()
+        .unwrap_or_default()
+}
+
+fn main() {
+    let parser = EbnfParser::from_str(GRAMMAR).expect("benchmark grammar");
+    //the parse does the same work for every byte, its user time grows linearly. the MB/s
+    //still drop with the size because the chart, about 1.8KB per byte of input, is memory the
+    //kernel has to hand out first, a large input spends a growing share of its time faulting
+    //those pages in
+    for functions in [10, 100, 400] {
+        let source = source(functions);
+        let runs = if functions > 100 { 3 } else { 10 };
+        let earley = measure(runs, || {
+            parser.parse(&source, "codebody").expect("benchmark source");
+        });
+        let streamed = measure(runs, || {
+            parser
+                .parsereader(source.as_bytes(), "codebody")
+                .expect("benchmark source");
+        });
+        let megabytes = source.len() as f64 / 1e6;
+        println!(
+            "{:>4} functions, {:>7} bytes: earley {:>9.2?} ({:.2} MB/s), streamed {:>9.2?}",
+            functions,
+            source.len(),
+            earley,
+            megabytes / earley.as_secs_f64(),
+            streamed,
+        );
+    }
+
+    let source = source(100);
+    let full = measure(10, || {
+        parser.parse(&source, "codebody").expect("benchmark source");
+    });
+    let mut parse = parser.parseincremental(&source, "codebody");
+    //the multiplier of a function in the middle and of the last one
+    let middle = source[..source.len() / 2]
+        .rfind("a * ")
+        .expect("a function")
+        + 4;
+    let end = source.rfind("a * ").expect("a function") + 4;
+    for (place, edit) in [("the middle", middle), ("the end", end)] {
+        let incremental = measure(10, || {
+            let previous = std::mem::replace(&mut parse, parser.parseincremental("", "codebody"));
+            parse = parser.reparse(previous, edit..edit + 1, "7");
+            assert!(parse.result.is_ok());
+        });
+        println!(
+            "incremental edit near {place} of 100 functions: {incremental:>9.2?}, full parse {full:>9.2?}"
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as Tokens;
+use quote::quote;
+use syn::{Attribute, Data, DeriveInput, Expr, Fields, Ident, Lit, Meta, parse_macro_input};
+
+//structs are read from a node of their rule and their fields from its children, enums from
+//a node of any of their variants' rules. The rule is the lowercased name unless it is given
+//with #[rule = "name"]
+#[proc_macro_derive(FromParseTree, attributes(rule))]
+pub fn derivefromparsetree(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let derived = match &input.data {
+        Data::Struct(data) => rulename(&input.attrs, &input.ident)
+            .map(|rule| derivestruct(&input, &rule, &data.fields)),
+        Data::Enum(data) => derivenum(&input, data.variants.iter().collect()),
+        Data::Union(_) => Err(syn::Error::new_spanned(
+            &input.ident,
+            "FromParseTree can not be derived for unions",
+        )),
+    };
+    match derived {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn rulename(attrs: &[Attribute], ident: &Ident) -> syn::Result<String> {
+    for attr in attrs {
+        if !attr.path().is_ident("rule") {
+            continue;
+        }
+        if let Meta::NameValue(value) = &attr.meta
+            && let Expr::Lit(literal) = &value.value
+            && let Lit::Str(rule) = &literal.lit
+        {
+            return Ok(rule.value());
+        }
+        return Err(syn::Error::new_spanned(attr, "expected #[rule = \"name\"]"));
+    }
+    Ok(ident.to_string().to_lowercase())
+}
+
+//`children` only has to be mutable if there are fields reading from it
+fn binding(fields: &Fields) -> Tokens {
+    match fields.is_empty() {
+        true => quote!(children),
+        false => quote!(mut children),
+    }
+}
+
+//reads the fields from `children` in order, `path` is the struct or variant
+fn construct(path: Tokens, fields: &Fields) -> Tokens {
+    match fields {
+        Fields::Named(named) => {
+            let names = named.named.iter().map(|field| &field.ident);
+            quote!(#path { #(#names: children.read()?),* })
+        }
+        Fields::Unnamed(unnamed) => {
+            let reads = unnamed.unnamed.iter().map(|_| quote!(children.read()?));
+            quote!(#path(#(#reads),*))
+        }
+        Fields::Unit => path,
+    }
+}
+
+//compares the fields with the rule and checks the field types
+fn checkfields(name: &str, rule: &str, fields: &Fields) -> Tokens {
+    let types: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+    quote! {
+        ::compiler::checkfields(
+            parser,
+            #name,
+            #rule,
+            &[#(<#types as ::compiler::FromChildren>::count()),*],
+        )?;
+        #(<#types as ::compiler::FromChildren>::checkgrammar(parser, checked)?;)*
+    }
+}
+
+fn derivestruct(input: &DeriveInput, rule: &str, fields: &Fields) -> Tokens {
+    let ident = &input.ident;
+    let (implgenerics, typegenerics, whereclause) = input.generics.split_for_impl();
+    let value = construct(quote!(#ident), fields);
+    let children = binding(fields);
+    let check = checkfields(&ident.to_string(), rule, fields);
+    quote! {
+        impl #implgenerics ::compiler::FromParseTree for #ident #typegenerics #whereclause {
+            fn rulename() -> Option<&'static str> {
+                Some(#rule)
+            }
+
+            fn accepts(parser: &::compiler::EbnfParser, tree: &::compiler::EbnfTree) -> bool {
+                ::compiler::treenode(parser, tree).is_some_and(|(name, _)| name == #rule)
+            }
+
+            fn fromtree(
+                parser: &::compiler::EbnfParser,
+                tree: &::compiler::EbnfTree,
+            ) -> Result<Self, ::compiler::FromTreeError> {
+                let #children = ::compiler::EbnfChildren::new(parser, tree, #rule)?;
+                let value = #value;
+                children.end()?;
+                Ok(value)
+            }
+
+            fn checkgrammar(
+                parser: &::compiler::EbnfParser,
+                checked: &mut ::std::collections::HashSet<&'static str>,
+            ) -> Result<(), ::compiler::FromTreeError> {
+                if !::compiler::firstcheck::<Self>(checked) {
+                    return Ok(());
+                }
+                #check
+                Ok(())
+            }
+        }
+    }
+}
+
+fn derivenum(input: &DeriveInput, variants: Vec<&syn::Variant>) -> syn::Result<Tokens> {
+    let ident = &input.ident;
+    let (implgenerics, typegenerics, whereclause) = input.generics.split_for_impl();
+    let mut rules = Vec::new();
+    let mut reads = Vec::new();
+    let mut checks = Vec::new();
+    for variant in variants {
+        let rule = rulename(&variant.attrs, &variant.ident)?;
+        let name = &variant.ident;
+        let value = construct(quote!(Self::#name), &variant.fields);
+        let children = binding(&variant.fields);
+        let check = checkfields(&format!("{ident}::{name}"), &rule, &variant.fields);
+        //a single field of the variant's own rule is read from the node itself
+        let (delegateread, delegatecheck) = match &variant.fields {
+            Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => {
+                let field = &unnamed.unnamed[0].ty;
+                let owned = quote!(<#field as ::compiler::FromChildren>::rulename() == Some(#rule));
+                (
+                    quote! {
+                        if #owned {
+                            let mut children = ::compiler::EbnfChildren::whole(parser, tree, #rule);
+                            return Ok(Self::#name(children.read()?));
+                        }
+                    },
+                    quote! {
+                        if #owned {
+                            <#field as ::compiler::FromChildren>::checkgrammar(parser, checked)?;
+                        } else {
+                            #check
+                        }
+                    },
+                )
+            }
+            _ => (quote!(), check),
+        };
+        reads.push(quote! {
+            #rule => {
+                #delegateread
+                let #children = ::compiler::EbnfChildren::new(parser, tree, #rule)?;
+                let value = #value;
+                children.end()?;
+                Ok(value)
+            }
+        });
+        checks.push(delegatecheck);
+        rules.push(rule);
+    }
+    let expected = match rules.len() {
+        1 => rules[0].clone(),
+        _ => format!("one of {}", rules.join(", ")),
+    };
+    Ok(quote! {
+        impl #implgenerics ::compiler::FromParseTree for #ident #typegenerics #whereclause {
+            fn accepts(parser: &::compiler::EbnfParser, tree: &::compiler::EbnfTree) -> bool {
+                match ::compiler::treenode(parser, tree) {
+                    Some((name, _)) => [#(#rules),*].contains(&name),
+                    None => false,
+                }
+            }
+
+            fn fromtree(
+                parser: &::compiler::EbnfParser,
+                tree: &::compiler::EbnfTree,
+            ) -> Result<Self, ::compiler::FromTreeError> {
+                let Some((name, _)) = ::compiler::treenode(parser, tree) else {
+                    return Err(::compiler::unexpectednode(parser, tree, #expected));
+                };
+                match name {
+                    #(#reads)*
+                    _ => Err(::compiler::unexpectednode(parser, tree, #expected)),
+                }
+            }
+
+            fn checkgrammar(
+                parser: &::compiler::EbnfParser,
+                checked: &mut ::std::collections::HashSet<&'static str>,
+            ) -> Result<(), ::compiler::FromTreeError> {
+                if !::compiler::firstcheck::<Self>(checked) {
+                    return Ok(());
+                }
+                #(#checks)*
+                Ok(())
+            }
+        }
+    })
+}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+
+use crate::grammar::EbnfStatement;
+use crate::input::EbnfInput;
+use crate::parser::{EbnfChart, EbnfParser, ParseCodeError, ParseCodeErrorType};
+
+type EbnfTokenAction<'f, T> = Box<dyn FnMut(&str, usize, usize) -> T + 'f>;
+type EbnfRuleAction<'f, T> = Box<dyn FnMut(Vec<T>) -> T + 'f>;
+
+//a step of `reduce`: the values of a partial, or the action of a rule taking the values since
+//`offset`
+enum EbnfReduce<'a> {
+    Partial {
+        index: u32,
+        keep: bool,
+    },
+    Rule {
+        rulename: &'a str,
+        body: u32,
+        offset: usize,
+    },
+}
+
+//closures by rule or `-> alias` name that build values straight from the chart, without a tree.
+//they run once the parse has finished, the chart may hold more than one derivation until then
+pub struct EbnfActions<'f, T> {
+    token: EbnfTokenAction<'f, T>,
+    actions: HashMap<String, EbnfRuleAction<'f, T>>,
+}
+
+impl<'f, T> EbnfActions<'f, T> {
+    //`token` gets regex tokens, and string tokens of !rules
+    pub fn new(token: impl FnMut(&str, usize, usize) -> T + 'f) -> Self {
+        EbnfActions {
+            token: Box::new(token),
+            actions: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, name: &str, action: impl FnMut(Vec<T>) -> T + 'f) -> &mut Self {
+        self.actions.insert(name.to_owned(), Box::new(action));
+        self
+    }
+}
+
+impl<'a> EbnfParser<'a> {
+    //the children are filtered the way `transform` does it. a rule or alias without an action
+    //passes on its only child, with no children or several it is an UnknownAction error. the
+    //actions run after the whole input is parsed, so none runs for an input with a syntax error
+    pub fn parsewithactions<T>(
+        &self,
+        input: &str,
+        startrule: &str,
+        actions: &mut EbnfActions<T>,
+    ) -> Result<T, ParseCodeError> {
+        let input = EbnfInput::new(input);
+        let (rulename, body) = self.startrule(startrule)?;
+        let mut chart = self.startchart(&input, body)?;
+        for position in chart.start()..=input.end() as u32 {
+            self.process(&mut chart, &input, position)?;
+        }
+        let root = chart.accepted(&input)?;
+        self.reduce(&chart, &input, rulename, root, actions)
+    }
+
+    //the value of the start rule from the partial of its body. repetitions nest as deep as
+    //they are long, so the partials are walked with a stack like `buildnode` does
+    fn reduce<T>(
+        &self,
+        chart: &EbnfChart,
+        input: &EbnfInput,
+        rulename: &str,
+        index: u32,
+        actions: &mut EbnfActions<T>,
+    ) -> Result<T, ParseCodeError> {
+        let mut values = Vec::new();
+        let mut stack = vec![
+            EbnfReduce::Rule {
+                rulename,
+                body: index,
+                offset: 0,
+            },
+            EbnfReduce::Partial {
+                index,
+                keep: self.keeptokens.contains(rulename),
+            },
+        ];
+        while let Some(step) = stack.pop() {
+            match step {
+                EbnfReduce::Partial { index, keep } => {
+                    self.values(chart, index, keep, actions, &mut values, &mut stack)
+                }
+                EbnfReduce::Rule {
+                    rulename,
+                    body,
+                    offset,
+                } => {
+                    let children = values.split_off(offset);
+                    values.push(self.action(chart, input, rulename, body, children, actions)?);
+                }
+            }
+        }
+        Ok(values.pop().expect("the start rule value"))
+    }
+
+    //the value of a rule from the values of its children
+    fn action<T>(
+        &self,
+        chart: &EbnfChart,
+        input: &EbnfInput,
+        rulename: &str,
+        body: u32,
+        mut values: Vec<T>,
+        actions: &mut EbnfActions<T>,
+    ) -> Result<T, ParseCodeError> {
+        let alternative = chart.alternative(body);
+        let alias = self.aliases.get(&(rulename.to_owned(), alternative));
+        if alias.is_none() && self.inline.contains(rulename) && values.len() == 1 {
+            return Ok(values.pop().expect("one child"));
+        }
+        let name = alias.map_or(rulename, String::as_str);
+        match actions.actions.get_mut(name) {
+            Some(action) => Ok(action(values)),
+            None if values.len() == 1 => Ok(values.pop().expect("one child")),
+            None => Err(input.error(
+                chart.partial(body).starttoken as i64,
+                ParseCodeErrorType::UnknownAction(name.to_owned()),
+            )),
+        }
+    }
+
+    //tokens give values, defined rules give a value once their children have, every other
+    //statement is flattened into its rule
+    fn values<'c, T>(
+        &self,
+        chart: &'c EbnfChart,
+        index: u32,
+        keep: bool,
+        actions: &mut EbnfActions<T>,
+        values: &mut Vec<T>,
+        stack: &mut Vec<EbnfReduce<'c>>,
+    ) {
+        let partial = chart.partial(index);
+        let statement = chart.statement(index);
+        if partial.isterminal {
+            if keep || !matches!(statement, EbnfStatement::StringTerminal { .. }) {
+                let (start, end) = (partial.starttoken as usize, partial.currentchar as usize);
+                values.push((actions.token)(chart.text(index), start, end));
+            }
+        } else if let EbnfStatement::DefinedRule { rulename } = statement {
+            //the only child of a rule partial is the partial of its body
+            if let Some(&body) = chart.children(index).first() {
+                stack.push(EbnfReduce::Rule {
+                    rulename,
+                    body,
+                    offset: values.len(),
+                });
+                stack.push(EbnfReduce::Partial {
+                    index: body,
+                    keep: self.keeptokens.contains(*rulename),
+                });
+            }
+        } else {
+            let children = chart.children(index).into_iter().rev();
+            stack.extend(children.map(|index| EbnfReduce::Partial { index, keep }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALCULATOR: &str = r#"
+?sum: sum "+" product -> binop_add | sum "-" product -> binop_sub | product;
+?product: product "*" atom -> binop_mul | atom;
+?atom: number | "(" sum ")";
+number: /[0-9]+/;
+list: "[" (sum ("," sum)*)? "]";
+%ignore /[ ]+/;
+"#;
+
+    fn calculator<'f>() -> EbnfActions<'f, i64> {
+        let mut actions = EbnfActions::new(|text, _, _| text.parse().expect("number"));
+        actions
+            .add("binop_add", |values| values[0] + values[1])
+            .add("binop_sub", |values| values[0] - values[1])
+            .add("binop_mul", |values| values[0] * values[1]);
+        actions
+    }
+
+    #[test]
+    fn aliasesnametheactions() {
+        let parser = EbnfParser::from_str(CALCULATOR).expect("grammar");
+        let mut actions = calculator();
+        let value = parser.parsewithactions("1 + 2 * (3 - 10) - 4", "sum", &mut actions);
+        assert_eq!(value.ok(), Some(-17));
+    }
+
+    #[test]
+    fn rulesneedanactionunlesstheyhaveonechild() {
+        let parser = EbnfParser::from_str(CALCULATOR).expect("grammar");
+        let mut actions = calculator();
+        let single = parser.parsewithactions("[2 * 3]", "list", &mut actions);
+        assert_eq!(single.ok(), Some(6));
+        for input in ["[1, 2]", "[]"] {
+            let err = parser
+                .parsewithactions(input, "list", &mut actions)
+                .expect_err("no action for list");
+            assert!(
+                matches!(err.errtype, ParseCodeErrorType::UnknownAction(name) if name == "list")
+            );
+        }
+
+        actions.add("list", |values| values.iter().sum());
+        assert_eq!(
+            parser
+                .parsewithactions("[1, 2+3]", "list", &mut actions)
+                .ok(),
+            Some(6)
+        );
+        assert_eq!(
+            parser.parsewithactions("[]", "list", &mut actions).ok(),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn syntaxerrorscomebeforeanyaction() {
+        let parser = EbnfParser::from_str(CALCULATOR).expect("grammar");
+        let mut calls = 0;
+        let mut actions = EbnfActions::new(|_, _, _| {
+            calls += 1;
+        });
+        assert!(
+            parser
+                .parsewithactions("1 + ", "sum", &mut actions)
+                .is_err()
+        );
+        drop(actions);
+        assert_eq!(calls, 0);
+    }
+
+    #[test]
+    fn longrepetitionsreduce() {
+        let parser = EbnfParser::from_str(CALCULATOR).expect("grammar");
+        let mut actions = calculator();
+        actions.add("list", |values| values.len() as i64);
+        let input = format!("[{}1]", "1, ".repeat(100_000));
+        let value = parser.parsewithactions(&input, "list", &mut actions);
+        assert_eq!(value.ok(), Some(100_001));
+    }
+}
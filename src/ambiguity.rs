@@ -0,0 +1,310 @@
+use std::{collections::HashMap, fmt};
+
+use crate::bnf::{BnfGrammar, BnfSymbol, BnfTerminal};
+use crate::parser::{EbnfParser, ParseCodeError};
+
+//a string of terminals with two derivations that already differ at the top of `rule`
+pub struct EbnfAmbiguity {
+    pub rule: String,
+    pub example: String,
+    pub tokens: usize,
+    //the rules the two derivations start with below `rule`
+    pub rules: Vec<String>,
+    pub derivations: [String; 2],
+}
+
+//how a nonterminal derived a string: the production and the lengths of its symbols' parts
+type BnfDerivation = (usize, Vec<usize>);
+
+//per length and nonterminal the strings of terminals it derives, with up to two derivations
+struct BnfYields<'g, 'a> {
+    grammar: &'g BnfGrammar<'a>,
+    parser: &'g EbnfParser<'a>,
+    yields: Vec<Vec<HashMap<Vec<usize>, Vec<BnfDerivation>>>>,
+}
+
+impl fmt::Display for EbnfAmbiguity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let example = match self.tokens {
+            0 => "the empty input",
+            _ => &self.example,
+        };
+        writeln!(
+            f,
+            "{} is ambiguous ({}), {} tokens: {example}",
+            self.rule,
+            self.rules.join(" or "),
+            self.tokens,
+        )?;
+        for derivation in &self.derivations {
+            writeln!(f, "    {derivation}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> EbnfParser<'a> {
+    //searches the strings of up to `maxlength` terminals the start rule can reach for ones with
+    //two derivations, the shortest example of every ambiguous rule comes first
+    pub fn ambiguities(
+        &self,
+        startrule: &str,
+        maxlength: usize,
+    ) -> Result<Vec<EbnfAmbiguity>, ParseCodeError> {
+        let grammar = BnfGrammar::new(&self.rules, startrule)?;
+        let mut yields = BnfYields {
+            grammar: &grammar,
+            parser: self,
+            yields: Vec::new(),
+        };
+        let mut found: Vec<EbnfAmbiguity> = Vec::new();
+        for length in 0..=maxlength {
+            yields.grow(length);
+            let mut ambiguous: Vec<(usize, &Vec<usize>)> = yields.yields[length]
+                .iter()
+                .enumerate()
+                .flat_map(|(nonterminal, strings)| {
+                    strings
+                        .iter()
+                        .filter(|(_, derivations)| derivations.len() > 1)
+                        .map(move |(string, _)| (nonterminal, string))
+                })
+                .collect();
+            //the same example every time
+            ambiguous.sort();
+            for (nonterminal, string) in ambiguous {
+                let ambiguity = yields.ambiguity(nonterminal, string);
+                if !found.iter().any(|known| known.rule == ambiguity.rule) {
+                    found.push(ambiguity);
+                }
+            }
+        }
+        Ok(found)
+    }
+}
+
+impl<'g, 'a> BnfYields<'g, 'a> {
+    //adds the strings of `length` terminals, repeated while unit and empty productions find more
+    fn grow(&mut self, length: usize) {
+        let grammar = self.grammar;
+        self.yields
+            .push(vec![HashMap::new(); grammar.nonterminals.len()]);
+        //only productions using a nonterminal that got new strings can find more
+        let mut changed: Option<Vec<bool>> = None;
+        while changed
+            .as_ref()
+            .is_none_or(|changed| changed.contains(&true))
+        {
+            let previous = changed.replace(vec![false; grammar.nonterminals.len()]);
+            for (index, production) in grammar.productions.iter().enumerate() {
+                if let Some(previous) = &previous
+                    && !production.rhs.iter().any(|symbol| {
+                        matches!(symbol, BnfSymbol::Nonterminal(nonterminal) if previous[*nonterminal])
+                    })
+                {
+                    continue;
+                }
+                let mut strings = Vec::new();
+                self.combine(
+                    &production.rhs,
+                    length,
+                    &mut Vec::new(),
+                    &mut Vec::new(),
+                    &mut strings,
+                );
+                let known = &mut self.yields[length][production.lhs];
+                for (string, lengths) in strings {
+                    let derivations = known.entry(string).or_default();
+                    let derivation = (index, lengths);
+                    if derivations.len() < 2 && !derivations.contains(&derivation) {
+                        derivations.push(derivation);
+                        if let Some(changed) = &mut changed {
+                            changed[production.lhs] = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    //every way the symbols derive `length` terminals
+    fn combine(
+        &self,
+        symbols: &[BnfSymbol],
+        length: usize,
+        string: &mut Vec<usize>,
+        lengths: &mut Vec<usize>,
+        out: &mut Vec<(Vec<usize>, Vec<usize>)>,
+    ) {
+        let Some((&symbol, rest)) = symbols.split_first() else {
+            if length == 0 {
+                out.push((string.clone(), lengths.clone()));
+            }
+            return;
+        };
+        match symbol {
+            BnfSymbol::Terminal(terminal) => {
+                if length > 0 {
+                    string.push(terminal);
+                    lengths.push(1);
+                    self.combine(rest, length - 1, string, lengths, out);
+                    lengths.pop();
+                    string.pop();
+                }
+            }
+            BnfSymbol::Nonterminal(nonterminal) => {
+                for part in 0..=length.min(self.yields.len() - 1) {
+                    for derived in self.yields[part][nonterminal].keys() {
+                        string.extend(derived);
+                        lengths.push(part);
+                        self.combine(rest, length - part, string, lengths, out);
+                        lengths.pop();
+                        string.truncate(string.len() - part);
+                    }
+                }
+            }
+        }
+    }
+
+    fn ambiguity(&self, nonterminal: usize, string: &[usize]) -> EbnfAmbiguity {
+        let derivations = &self.yields[string.len()][nonterminal][string];
+        let grammar = self.grammar;
+        let owner = self.owner(nonterminal);
+        let tree = |derivation: &BnfDerivation| {
+            let mut out = vec![owner.clone()];
+            self.children(derivation, string, &mut out);
+            format!("({})", out.join(" "))
+        };
+        //the rule names right below the ambiguous one, or its productions for synthetic ones
+        let mut rules: Vec<String> = derivations
+            .iter()
+            .map(|(production, _)| {
+                let rhs = &grammar.productions[*production].rhs;
+                match rhs.as_slice() {
+                    [BnfSymbol::Nonterminal(child)] => self.owner(*child),
+                    _ => grammar
+                        .production(*production)
+                        .replace(&grammar.nonterminals[nonterminal].name, &owner),
+                }
+            })
+            .collect();
+        rules.dedup();
+        EbnfAmbiguity {
+            rule: owner.clone(),
+            example: string
+                .iter()
+                .map(|&terminal| match grammar.terminals[terminal] {
+                    BnfTerminal::String(_) => grammar.terminals[terminal].to_string(),
+                    BnfTerminal::Regex(..) => grammar.describe(terminal),
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+            tokens: string.len(),
+            rules,
+            derivations: [tree(&derivations[0]), tree(&derivations[1])],
+        }
+    }
+
+    //the rule a nonterminal belongs to, groups and repetitions are named after their rule
+    fn owner(&self, nonterminal: usize) -> String {
+        let name = &self.grammar.nonterminals[nonterminal].name;
+        if self.grammar.nonterminals[nonterminal].synthetic {
+            name.rsplit_once("__")
+                .map_or(name.as_str(), |(owner, _)| owner)
+                .to_owned()
+        } else {
+            name.clone()
+        }
+    }
+
+    //the children of a derivation as S-expressions, synthetic nonterminals are flattened into
+    //their parent, ?rules with one child replaced by it and rules of a single terminal named
+    fn children(&self, derivation: &BnfDerivation, string: &[usize], out: &mut Vec<String>) {
+        let grammar = self.grammar;
+        let (production, lengths) = derivation;
+        let mut start = 0;
+        for (&symbol, &length) in grammar.productions[*production].rhs.iter().zip(lengths) {
+            let part = &string[start..start + length];
+            start += length;
+            let BnfSymbol::Nonterminal(nonterminal) = symbol else {
+                out.push(grammar.symbol(symbol));
+                continue;
+            };
+            let first = &self.yields[length][nonterminal][part][0];
+            let mut children = Vec::new();
+            self.children(first, part, &mut children);
+            let name = &grammar.nonterminals[nonterminal].name;
+            if grammar.nonterminals[nonterminal].synthetic {
+                out.extend(children);
+            } else if let [terminal] = part
+                && grammar.productions[first.0].rhs == [BnfSymbol::Terminal(*terminal)]
+                && grammar.describe(*terminal) == *name
+            {
+                out.push(name.clone());
+            } else if self.parser.inline.contains(name) && children.len() == 1 {
+                out.extend(children);
+            } else {
+                children.insert(0, name.clone());
+                out.push(format!("({})", children.join(" ")));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn theshortestexampleofeveryruleisfound() {
+        let parser = EbnfParser::from_str(
+            r#"expression: expression "-" expression | number; number: "1" | "1" "1"?;"#,
+        )
+        .expect("grammar");
+        let found: Vec<String> = parser
+            .ambiguities("expression", 5)
+            .expect("search")
+            .iter()
+            .map(|ambiguity| ambiguity.to_string())
+            .collect();
+        assert_eq!(
+            found,
+            [
+                concat!(
+                    "number is ambiguous (number -> \"1\" or number -> \"1\" number__2), 1 tokens: \"1\"\n",
+                    "    (number \"1\")\n",
+                    "    (number \"1\")\n",
+                ),
+                concat!(
+                    "expression is ambiguous (expression -> expression \"-\" expression), 5 tokens: ",
+                    "\"1\" \"-\" \"1\" \"-\" \"1\"\n",
+                    "    (expression (expression number) \"-\" (expression (expression number) \"-\" (expression number)))\n",
+                    "    (expression (expression (expression number) \"-\" (expression number)) \"-\" (expression number))\n",
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn emptyderivationscount() {
+        let parser = EbnfParser::from_str(r#"list: item*; item: "a"?;"#).expect("grammar");
+        let found = parser.ambiguities("list", 2).expect("search");
+        assert_eq!(
+            found[0].to_string(),
+            "list is ambiguous (list -> § or list -> item list), 0 tokens: the empty input\n    (list)\n    (list (item))\n"
+        );
+    }
+
+    #[test]
+    fn unambiguousgrammarsfindnothing() {
+        let parser = EbnfParser::from_str(r#"expression: number ("-" number)*; number: /[0-9]+/;"#)
+            .expect("grammar");
+        assert!(
+            parser
+                .ambiguities("expression", 6)
+                .expect("search")
+                .is_empty()
+        );
+        assert!(parser.ambiguities("missing", 6).is_err());
+    }
+}
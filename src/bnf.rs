@@ -0,0 +1,419 @@
+use regex::Regex;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+};
+
+use crate::grammar::EbnfStatement;
+use crate::parser::{ParseCodeError, ParseCodeErrorType, wholeword};
+use crate::table::alternatives;
+use crate::tree::EbnfTree;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum BnfSymbol {
+    Terminal(usize),
+    Nonterminal(usize),
+}
+
+pub enum BnfTerminal<'a> {
+    String(&'a str),
+    Regex(&'a str, Regex),
+}
+
+pub struct BnfNonterminal {
+    pub name: String,
+    //nonterminals introduced for groups and repetitions, flattened into their parent in trees
+    pub synthetic: bool,
+}
+
+pub struct BnfProduction {
+    pub lhs: usize,
+    pub rhs: Vec<BnfSymbol>,
+    //the top-level alternative of the rule the production expands, 0 for synthetic ones
+    pub alternative: usize,
+}
+
+//the rules reachable from a start rule, normalized to plain productions
+pub struct BnfGrammar<'a> {
+    pub terminals: Vec<BnfTerminal<'a>>,
+    pub nonterminals: Vec<BnfNonterminal>,
+    pub productions: Vec<BnfProduction>,
+    pub start: usize,
+}
+
+pub struct BnfSets {
+    pub nullable: Vec<bool>,
+    pub first: Vec<BTreeSet<usize>>,
+    //None stands for the end of the input
+    pub follow: Vec<BTreeSet<Option<usize>>>,
+}
+
+impl<'a> fmt::Display for BnfTerminal<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BnfTerminal::String(string) => write!(f, "\"{string}\""),
+            BnfTerminal::Regex(string, _) => write!(f, "/{string}/"),
+        }
+    }
+}
+
+impl<'a> BnfTerminal<'a> {
+    //length of the match at `position`, if any, `reserved` are the words from %reserved
+    pub fn matchat(&self, input: &str, position: usize, reserved: &[&str]) -> Option<usize> {
+        let tomatch = &input[position..];
+        match self {
+            BnfTerminal::String(string) => (tomatch.starts_with(string)
+                && wholeword(reserved, string, tomatch))
+            .then_some(string.len()),
+            BnfTerminal::Regex(_, exp) => exp
+                .find(tomatch)
+                .filter(|found| !reserved.contains(&found.as_str()))
+                .map(|found| found.end()),
+        }
+    }
+}
+
+fn grammarerror(errtype: ParseCodeErrorType) -> ParseCodeError {
+    ParseCodeError {
+        line: 0,
+        column: 0,
+        errtype,
+    }
+}
+
+impl<'a> BnfGrammar<'a> {
+    pub fn new(
+        rules: &HashMap<String, EbnfStatement<'a>>,
+        startrule: &str,
+    ) -> Result<Self, ParseCodeError> {
+        let mut builder = BnfBuilder {
+            grammar: BnfGrammar {
+                terminals: Vec::new(),
+                nonterminals: Vec::new(),
+                productions: Vec::new(),
+                start: 0,
+            },
+            rules,
+            rulesymbols: HashMap::new(),
+            pending: Vec::new(),
+        };
+        builder.grammar.start = builder.rule(startrule)?;
+        let mut i = 0;
+        while i < builder.pending.len() {
+            let (lhs, body) = builder.pending[i];
+            i += 1;
+            let name = builder.grammar.nonterminals[lhs].name.clone();
+            for (alternative, choice) in alternatives(body).into_iter().enumerate() {
+                for rhs in builder.expand(choice, &name)? {
+                    builder.grammar.productions.push(BnfProduction {
+                        lhs,
+                        rhs,
+                        alternative,
+                    });
+                }
+            }
+        }
+        Ok(builder.grammar)
+    }
+
+    pub fn symbol(&self, symbol: BnfSymbol) -> String {
+        match symbol {
+            BnfSymbol::Terminal(terminal) => self.terminals[terminal].to_string(),
+            BnfSymbol::Nonterminal(nonterminal) => self.nonterminals[nonterminal].name.clone(),
+        }
+    }
+
+    pub fn production(&self, index: usize) -> String {
+        let production = &self.productions[index];
+        let lhs = &self.nonterminals[production.lhs].name;
+        if production.rhs.is_empty() {
+            return format!("{lhs} -> §");
+        }
+        let rhs = production
+            .rhs
+            .iter()
+            .map(|&symbol| self.symbol(symbol))
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!("{lhs} -> {rhs}")
+    }
+
+    //terminals that make up a whole rule are described by the rule name, like in Earley errors
+    pub fn describe(&self, terminal: usize) -> String {
+        self.productions
+            .iter()
+            .find(|production| {
+                !self.nonterminals[production.lhs].synthetic
+                    && production.rhs == [BnfSymbol::Terminal(terminal)]
+            })
+            .map(|production| self.nonterminals[production.lhs].name.clone())
+            .unwrap_or_else(|| self.terminals[terminal].to_string())
+    }
+
+    pub fn describelookahead(&self, lookahead: Option<usize>) -> String {
+        match lookahead {
+            Some(terminal) => self.describe(terminal),
+            None => "end of input".to_owned(),
+        }
+    }
+
+    pub(crate) fn token(&self, terminal: usize, input: &str, start: usize, end: usize) -> EbnfTree {
+        EbnfTree::Token {
+            text: input[start..end].to_owned(),
+            string: matches!(self.terminals[terminal], BnfTerminal::String(_)),
+            start,
+            end,
+        }
+    }
+
+    //wraps the nodes built since `offset` into a rule node, the nodes of synthetic nonterminals
+    //stay where they are and become children of the enclosing rule. Each node is moved once, so
+    //repetitions cost linear time however they recurse
+    pub(crate) fn reduce(
+        &self,
+        production: usize,
+        nodes: &mut Vec<EbnfTree>,
+        offset: usize,
+        position: usize,
+    ) {
+        let production = &self.productions[production];
+        let nonterminal = &self.nonterminals[production.lhs];
+        if nonterminal.synthetic {
+            return;
+        }
+        let children = nodes.split_off(offset);
+        let (start, end) = match (children.first(), children.last()) {
+            (Some(first), Some(last)) => (first.start(), last.end()),
+            _ => (position, position),
+        };
+        nodes.push(EbnfTree::Rule {
+            name: nonterminal.name.clone(),
+            alternative: production.alternative,
+            children,
+            start,
+            end,
+        });
+    }
+
+    pub fn sets(&self) -> BnfSets {
+        let count = self.nonterminals.len();
+        let mut sets = BnfSets {
+            nullable: vec![false; count],
+            first: vec![BTreeSet::new(); count],
+            follow: vec![BTreeSet::new(); count],
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for production in &self.productions {
+                let (first, nullable) = sets.firstof(&production.rhs);
+                if nullable && !sets.nullable[production.lhs] {
+                    sets.nullable[production.lhs] = true;
+                    changed = true;
+                }
+                for terminal in first {
+                    changed |= sets.first[production.lhs].insert(terminal);
+                }
+            }
+        }
+
+        sets.follow[self.start].insert(None);
+        changed = true;
+        while changed {
+            changed = false;
+            for production in &self.productions {
+                for (i, symbol) in production.rhs.iter().enumerate() {
+                    let BnfSymbol::Nonterminal(nonterminal) = *symbol else {
+                        continue;
+                    };
+                    let (first, nullable) = sets.firstof(&production.rhs[i + 1..]);
+                    for terminal in first {
+                        changed |= sets.follow[nonterminal].insert(Some(terminal));
+                    }
+                    if nullable {
+                        for lookahead in sets.follow[production.lhs].clone() {
+                            changed |= sets.follow[nonterminal].insert(lookahead);
+                        }
+                    }
+                }
+            }
+        }
+        sets
+    }
+}
+
+impl BnfSets {
+    //the terminals a sequence of symbols can start with, and whether it can be empty
+    pub fn firstof(&self, symbols: &[BnfSymbol]) -> (BTreeSet<usize>, bool) {
+        let mut first = BTreeSet::new();
+        for symbol in symbols {
+            match *symbol {
+                BnfSymbol::Terminal(terminal) => {
+                    first.insert(terminal);
+                    return (first, false);
+                }
+                BnfSymbol::Nonterminal(nonterminal) => {
+                    first.extend(&self.first[nonterminal]);
+                    if !self.nullable[nonterminal] {
+                        return (first, false);
+                    }
+                }
+            }
+        }
+        (first, true)
+    }
+}
+
+struct BnfBuilder<'a, 'r> {
+    grammar: BnfGrammar<'a>,
+    rules: &'r HashMap<String, EbnfStatement<'a>>,
+    rulesymbols: HashMap<String, usize>,
+    pending: Vec<(usize, &'r EbnfStatement<'a>)>,
+}
+
+impl<'a, 'r> BnfBuilder<'a, 'r> {
+    fn nonterminal(&mut self, name: String, synthetic: bool) -> usize {
+        self.grammar
+            .nonterminals
+            .push(BnfNonterminal { name, synthetic });
+        self.grammar.nonterminals.len() - 1
+    }
+
+    fn rule(&mut self, rulename: &str) -> Result<usize, ParseCodeError> {
+        if let Some(&nonterminal) = self.rulesymbols.get(rulename) {
+            return Ok(nonterminal);
+        }
+        let body = self
+            .rules
+            .get(rulename)
+            .ok_or_else(|| grammarerror(ParseCodeErrorType::UnknownRule))?;
+        let nonterminal = self.nonterminal(rulename.to_owned(), false);
+        self.rulesymbols.insert(rulename.to_owned(), nonterminal);
+        self.pending.push((nonterminal, body));
+        Ok(nonterminal)
+    }
+
+    fn terminal(&mut self, statement: &EbnfStatement<'a>) -> Result<usize, ParseCodeError> {
+        let terminal = match *statement {
+            EbnfStatement::StringTerminal { string } => BnfTerminal::String(string),
+            EbnfStatement::RegexTerminal { string } => {
+                match Regex::new(format!("^(?:{string})").as_str()) {
+                    Ok(exp) => BnfTerminal::Regex(string, exp),
+                    Err(_) => return Err(grammarerror(ParseCodeErrorType::InvalidRegex)),
+                }
+            }
+            _ => unreachable!("only called for terminals"),
+        };
+        let existing = self.grammar.terminals.iter().position(|known| {
+            matches!(
+                (known, &terminal),
+                (BnfTerminal::String(a), BnfTerminal::String(b)) if a == b
+            ) || matches!(
+                (known, &terminal),
+                (BnfTerminal::Regex(a, _), BnfTerminal::Regex(b, _)) if a == b
+            )
+        });
+        Ok(existing.unwrap_or_else(|| {
+            self.grammar.terminals.push(terminal);
+            self.grammar.terminals.len() - 1
+        }))
+    }
+
+    //the alternatives a statement expands to
+    fn expand(
+        &mut self,
+        statement: &EbnfStatement<'a>,
+        owner: &str,
+    ) -> Result<Vec<Vec<BnfSymbol>>, ParseCodeError> {
+        Ok(match statement {
+            EbnfStatement::Or { left, right } => {
+                let mut alternatives = self.expand(left, owner)?;
+                alternatives.extend(self.expand(right, owner)?);
+                alternatives
+            }
+            EbnfStatement::Optional { rule } => {
+                let mut alternatives = vec![Vec::new()];
+                alternatives.extend(self.expand(rule, owner)?);
+                alternatives
+            }
+            _ => vec![self.sequence(statement, owner)?],
+        })
+    }
+
+    //a statement as a sequence of symbols, introducing synthetic nonterminals where needed
+    fn sequence(
+        &mut self,
+        statement: &EbnfStatement<'a>,
+        owner: &str,
+    ) -> Result<Vec<BnfSymbol>, ParseCodeError> {
+        Ok(match statement {
+            EbnfStatement::StringTerminal { .. } | EbnfStatement::RegexTerminal { .. } => {
+                vec![BnfSymbol::Terminal(self.terminal(statement)?)]
+            }
+            EbnfStatement::DefinedRule { rulename } => {
+                vec![BnfSymbol::Nonterminal(self.rule(rulename)?)]
+            }
+            EbnfStatement::Concatenation { rules } => {
+                let mut symbols = Vec::new();
+                for rule in rules {
+                    symbols.extend(self.sequence(rule, owner)?);
+                }
+                symbols
+            }
+            EbnfStatement::Empty => Vec::new(),
+            EbnfStatement::ZeroOrMore { rule } => {
+                vec![BnfSymbol::Nonterminal(self.repetition(rule, owner)?)]
+            }
+            //rule+ is rule rule*
+            EbnfStatement::OneOrMore { rule } => {
+                let mut symbols = self.sequence(rule, owner)?;
+                symbols.push(BnfSymbol::Nonterminal(self.repetition(rule, owner)?));
+                symbols
+            }
+            EbnfStatement::And { .. } | EbnfStatement::Not { .. } => {
+                return Err(grammarerror(ParseCodeErrorType::UnsupportedPredicate(
+                    statement.to_string(),
+                )));
+            }
+            EbnfStatement::Or { .. } | EbnfStatement::Optional { .. } => {
+                let lhs = self.synthetic(owner);
+                for rhs in self.expand(statement, owner)? {
+                    self.grammar.productions.push(BnfProduction {
+                        lhs,
+                        rhs,
+                        alternative: 0,
+                    });
+                }
+                vec![BnfSymbol::Nonterminal(lhs)]
+            }
+        })
+    }
+
+    //right recursive, so the result stays usable for predictive parsing
+    fn repetition(
+        &mut self,
+        rule: &EbnfStatement<'a>,
+        owner: &str,
+    ) -> Result<usize, ParseCodeError> {
+        let lhs = self.synthetic(owner);
+        let mut rhs = self.sequence(rule, owner)?;
+        rhs.push(BnfSymbol::Nonterminal(lhs));
+        self.grammar.productions.push(BnfProduction {
+            lhs,
+            rhs,
+            alternative: 0,
+        });
+        self.grammar.productions.push(BnfProduction {
+            lhs,
+            rhs: Vec::new(),
+            alternative: 0,
+        });
+        Ok(lhs)
+    }
+
+    fn synthetic(&mut self, owner: &str) -> usize {
+        let count = self.grammar.nonterminals.len();
+        self.nonterminal(format!("{owner}__{count}"), true)
+    }
+}
@@ -0,0 +1,560 @@
+use regex::Regex;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use crate::bnf::BnfTerminal;
+use crate::grammar::EbnfStatement;
+use crate::lalr::{LalrAction, LalrError};
+use crate::parser::{EbnfParser, ParseCodeError, ParseCodeErrorType};
+
+//the part of the generated module that does not depend on the grammar
+const DRIVER: &str = r#"
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Node {
+    Rule {
+        rule: Rule,
+        children: Vec<Node>,
+        start: usize,
+        end: usize,
+    },
+    Token {
+        text: String,
+        start: usize,
+        end: usize,
+    },
+}
+
+impl Node {
+    pub fn start(&self) -> usize {
+        match self {
+            Node::Rule { start, .. } | Node::Token { start, .. } => *start,
+        }
+    }
+
+    pub fn end(&self) -> usize {
+        match self {
+            Node::Rule { end, .. } | Node::Token { end, .. } => *end,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    //None at the end of the input
+    pub found: Option<String>,
+    pub expected: Vec<&'static str>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.found {
+            Some(found) => write!(f, "Unexpected '{found}'")?,
+            None => write!(f, "Unexpected end of input")?,
+        }
+        write!(
+            f,
+            " at line {}, column {}, expected one of: {}",
+            self.line,
+            self.column,
+            self.expected.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Clone, Copy)]
+enum Action {
+    Shift(usize),
+    Reduce(usize),
+    Accept,
+}
+
+fn regexes() -> &'static (Vec<Regex>, Vec<Regex>) {
+    static REGEXES: OnceLock<(Vec<Regex>, Vec<Regex>)> = OnceLock::new();
+    REGEXES.get_or_init(|| {
+        let compile = |pattern: &str| {
+            Regex::new(&format!("^(?:{pattern})")).expect("patterns are checked by the generator")
+        };
+        (
+            TERMINALS.iter().map(|(pattern, _, _)| compile(pattern)).collect(),
+            IGNORE.iter().map(|pattern| compile(pattern)).collect(),
+        )
+    })
+}
+
+fn skipignored(input: &str, position: usize) -> usize {
+    let mut position = position;
+    'skipping: loop {
+        for exp in &regexes().1 {
+            if let Some(found) = exp.find(&input[position..]).filter(|found| found.end() > 0) {
+                position += found.end();
+                continue 'skipping;
+            }
+        }
+        return position;
+    }
+}
+
+//reserved words only match as whole words, and never as a regex terminal
+fn reserved(text: &str, isstring: bool, rest: &str) -> bool {
+    let wordchar = |char: char| char.is_alphanumeric() || char == '_';
+    RESERVED.contains(&text)
+        && (!isstring || (text.ends_with(wordchar) && rest[text.len()..].starts_with(wordchar)))
+}
+
+//the longest matching terminal the state has an action for, literal strings win ties
+fn lookahead(state: usize, input: &str, position: usize) -> Option<(usize, usize)> {
+    let mut best: Option<(usize, bool, usize)> = None;
+    let rest = &input[position..];
+    for &(terminal, _) in ACTIONS[state] {
+        let Some(exp) = regexes().0.get(terminal) else {
+            continue;
+        };
+        let isstring = TERMINALS[terminal].1;
+        let Some(found) = exp.find(rest).filter(|found| !reserved(found.as_str(), isstring, rest)) else {
+            continue;
+        };
+        if best.is_none_or(|(length, beststring, _)| (found.end(), isstring) > (length, beststring)) {
+            best = Some((found.end(), isstring, terminal));
+        }
+    }
+    match best {
+        Some((length, _, terminal)) => Some((terminal, length)),
+        None if position == input.len() => Some((TERMINALS.len(), 0)),
+        None => None,
+    }
+}
+
+//wraps the nodes built since `offset` into the node of a rule, helpers leave them in place
+fn node(lhs: usize, nodes: &mut Vec<Node>, offset: usize, position: usize) {
+    let Some(rule) = NONTERMINALS[lhs] else {
+        return;
+    };
+    let children = nodes.split_off(offset);
+    let (start, end) = match (children.first(), children.last()) {
+        (Some(first), Some(last)) => (first.start(), last.end()),
+        _ => (position, position),
+    };
+    nodes.push(Node::Rule {
+        rule,
+        children,
+        start,
+        end,
+    });
+}
+
+fn error(input: &str, position: usize, state: usize) -> ParseError {
+    let rest = &input[position..];
+    let found = rest.chars().next().map(|first| {
+        let length = if first.is_alphanumeric() || first == '_' {
+            rest.find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len())
+        } else {
+            first.len_utf8()
+        };
+        rest[..length].to_owned()
+    });
+    let mut expected: Vec<&'static str> = ACTIONS[state]
+        .iter()
+        .map(|&(terminal, _)| TERMINALS.get(terminal).map_or("end of input", |terminal| terminal.2))
+        .collect();
+    expected.sort();
+    expected.dedup();
+    let before = &input[..position];
+    ParseError {
+        line: before.matches('\n').count() + 1,
+        column: match before.rfind('\n') {
+            Some(newline) => before[newline + 1..].chars().count() + 1,
+            None => before.chars().count() + 1,
+        },
+        found,
+        expected,
+    }
+}
+
+pub fn parse(input: &str) -> Result<Node, ParseError> {
+    let mut position = skipignored(input, 0);
+    let mut states = vec![0];
+    //the built nodes in input order, and per symbol on the stack where its nodes start
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut offsets: Vec<usize> = Vec::new();
+    loop {
+        let state = states[states.len() - 1];
+        let action = lookahead(state, input, position).and_then(|(terminal, length)| {
+            ACTIONS[state]
+                .iter()
+                .find(|&&(lookahead, _)| lookahead == terminal)
+                .map(|&(_, action)| (action, length))
+        });
+        match action {
+            None => return Err(error(input, position, state)),
+            Some((Action::Shift(target), length)) => {
+                offsets.push(nodes.len());
+                nodes.push(Node::Token {
+                    text: input[position..position + length].to_owned(),
+                    start: position,
+                    end: position + length,
+                });
+                position = skipignored(input, position + length);
+                states.push(target);
+            }
+            Some((Action::Reduce(production), _)) => {
+                let (lhs, count) = PRODUCTIONS[production];
+                let offset = offsets.drain(offsets.len() - count..).next().unwrap_or(nodes.len());
+                states.truncate(states.len() - count);
+                node(lhs, &mut nodes, offset, position);
+                offsets.push(offset);
+                let state = states[states.len() - 1];
+                let target = GOTOS[state]
+                    .iter()
+                    .find(|&&(nonterminal, _)| nonterminal == lhs)
+                    .map(|&(_, target)| target)
+                    .expect("every reduction has a goto");
+                states.push(target);
+            }
+            Some((Action::Accept, _)) => {
+                return Ok(nodes.pop().expect("the start rule always builds a node"));
+            }
+        }
+    }
+}
+"#;
+
+impl<'a> EbnfParser<'a> {
+    //Rust source of a module with a Rule enum, a Node tree and a parse function built from
+    //the LALR(1) tables of `startrule`, the module only depends on the regex crate.
+    //meant to be written to OUT_DIR from a build script and include!d
+    pub fn generate(&'a self, startrule: &str) -> Result<String, LalrError> {
+        let lalr = self.lalr(startrule)?;
+        let grammar = &lalr.grammar;
+        let ignore = self
+            .ignore
+            .iter()
+            .map(|statement| self.pattern(statement).and_then(checked))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(LalrError::InvalidGrammar)?;
+        let terminals = grammar
+            .terminals
+            .iter()
+            .map(|terminal| match terminal {
+                BnfTerminal::String(string) => Ok((regex::escape(string), true)),
+                BnfTerminal::Regex(string, _) => {
+                    checked(string.to_string()).map(|exp| (exp, false))
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(LalrError::InvalidGrammar)?;
+
+        let mut rules: Vec<&str> = grammar
+            .nonterminals
+            .iter()
+            .filter(|nonterminal| !nonterminal.synthetic)
+            .map(|nonterminal| nonterminal.name.as_str())
+            .collect();
+        rules.sort();
+        let variants = variants(&rules);
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "//generated from a grammar by the compiler crate, do not edit\n"
+        );
+        let _ = writeln!(out, "use regex::Regex;");
+        let _ = writeln!(out, "use std::{{fmt, sync::OnceLock}};\n");
+
+        let _ = writeln!(out, "#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]");
+        let _ = writeln!(out, "pub enum Rule {{");
+        for rule in &rules {
+            let _ = writeln!(out, "    {},", variants[rule]);
+        }
+        let _ = writeln!(out, "}}\n");
+        let _ = writeln!(out, "impl Rule {{");
+        let _ = writeln!(out, "    pub fn name(self) -> &'static str {{");
+        let _ = writeln!(out, "        match self {{");
+        for rule in &rules {
+            let _ = writeln!(out, "            Rule::{} => {rule:?},", variants[rule]);
+        }
+        let _ = writeln!(out, "        }}\n    }}\n}}");
+        out.push_str(DRIVER);
+
+        let _ = writeln!(
+            out,
+            "\n//pattern, whether it is a literal string, description"
+        );
+        let _ = writeln!(out, "const TERMINALS: &[(&str, bool, &str)] = &[");
+        for (index, (pattern, isstring)) in terminals.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "    ({pattern:?}, {isstring}, {:?}),",
+                grammar.describe(index)
+            );
+        }
+        let _ = writeln!(out, "];\n");
+
+        let _ = writeln!(out, "const IGNORE: &[&str] = &[");
+        for pattern in &ignore {
+            let _ = writeln!(out, "    {pattern:?},");
+        }
+        let _ = writeln!(out, "];\n");
+
+        let _ = writeln!(out, "const RESERVED: &[&str] = &[");
+        for word in &self.reserved {
+            let _ = writeln!(out, "    {word:?},");
+        }
+        let _ = writeln!(out, "];\n");
+
+        let _ = writeln!(
+            out,
+            "//rule node built by each nonterminal, None for flattened helpers"
+        );
+        let _ = writeln!(out, "const NONTERMINALS: &[Option<Rule>] = &[");
+        for nonterminal in &grammar.nonterminals {
+            if nonterminal.synthetic {
+                let _ = writeln!(out, "    None,");
+            } else {
+                let _ = writeln!(
+                    out,
+                    "    Some(Rule::{}),",
+                    variants[nonterminal.name.as_str()]
+                );
+            }
+        }
+        let _ = writeln!(out, "];\n");
+
+        let _ = writeln!(out, "//lhs and number of symbols");
+        let _ = writeln!(out, "const PRODUCTIONS: &[(usize, usize)] = &[");
+        for production in &grammar.productions {
+            let _ = writeln!(out, "    ({}, {}),", production.lhs, production.rhs.len());
+        }
+        let _ = writeln!(out, "];\n");
+
+        let _ = writeln!(
+            out,
+            "//per state, lookahead terminal (TERMINALS.len() is the end of the input) and action"
+        );
+        let _ = writeln!(out, "const ACTIONS: &[&[(usize, Action)]] = &[");
+        for actions in &lalr.actions {
+            let mut actions: Vec<_> = actions.iter().collect();
+            actions.sort();
+            let actions: Vec<String> = actions
+                .into_iter()
+                .map(|(lookahead, action)| match action {
+                    LalrAction::Shift(target) => format!("({lookahead}, Action::Shift({target}))"),
+                    LalrAction::Reduce(production) => {
+                        format!("({lookahead}, Action::Reduce({production}))")
+                    }
+                    LalrAction::Accept => format!("({lookahead}, Action::Accept)"),
+                })
+                .collect();
+            let _ = writeln!(out, "    &[{}],", actions.join(", "));
+        }
+        let _ = writeln!(out, "];\n");
+
+        let _ = writeln!(
+            out,
+            "//per state, nonterminal and the state after reducing it"
+        );
+        let _ = writeln!(out, "const GOTOS: &[&[(usize, usize)]] = &[");
+        for gotos in &lalr.gotos {
+            let mut gotos: Vec<_> = gotos.iter().collect();
+            gotos.sort();
+            let gotos: Vec<String> = gotos
+                .into_iter()
+                .map(|(nonterminal, target)| format!("({nonterminal}, {target})"))
+                .collect();
+            let _ = writeln!(out, "    &[{}],", gotos.join(", "));
+        }
+        let _ = writeln!(out, "];");
+        Ok(out)
+    }
+
+    //an ignored statement as one regex, the generated module has no rules to look up
+    fn pattern(&self, statement: &EbnfStatement) -> Result<String, ParseCodeError> {
+        Ok(match statement {
+            EbnfStatement::StringTerminal { string } => regex::escape(string),
+            EbnfStatement::RegexTerminal { string } => format!("(?:{string})"),
+            EbnfStatement::DefinedRule { rulename } => match self.rules.get(*rulename) {
+                Some(rule) => self.pattern(rule)?,
+                None => {
+                    return Err(ParseCodeError {
+                        line: 0,
+                        column: 0,
+                        errtype: ParseCodeErrorType::UnknownRule,
+                    });
+                }
+            },
+            EbnfStatement::Concatenation { rules } => rules
+                .iter()
+                .map(|rule| self.pattern(rule))
+                .collect::<Result<String, _>>()?,
+            EbnfStatement::Or { left, right } => {
+                format!("(?:{}|{})", self.pattern(left)?, self.pattern(right)?)
+            }
+            EbnfStatement::Optional { rule } => format!("(?:{})?", self.pattern(rule)?),
+            EbnfStatement::ZeroOrMore { rule } => format!("(?:{})*", self.pattern(rule)?),
+            EbnfStatement::OneOrMore { rule } => format!("(?:{})+", self.pattern(rule)?),
+            EbnfStatement::And { .. } | EbnfStatement::Not { .. } => {
+                return Err(ParseCodeError {
+                    line: 0,
+                    column: 0,
+                    errtype: ParseCodeErrorType::UnsupportedPredicate(statement.to_string()),
+                });
+            }
+            EbnfStatement::Empty => String::new(),
+        })
+    }
+}
+
+//the generated module compiles the patterns the same way and expects them to be valid
+fn checked(pattern: String) -> Result<String, ParseCodeError> {
+    match Regex::new(&format!("^(?:{pattern})")) {
+        Ok(_) => Ok(pattern),
+        Err(_) => Err(ParseCodeError {
+            line: 0,
+            column: 0,
+            errtype: ParseCodeErrorType::InvalidRegex,
+        }),
+    }
+}
+
+//the variant of every rule, numbered when names like foo_bar and fooBar would share one
+fn variants<'r>(rules: &[&'r str]) -> HashMap<&'r str, String> {
+    let mut taken = HashSet::new();
+    let mut variants = HashMap::new();
+    for &rule in rules {
+        let base = variant(rule);
+        let mut name = base.clone();
+        let mut number = 1;
+        while !taken.insert(name.clone()) {
+            number += 1;
+            name = format!("{base}{number}");
+        }
+        variants.insert(rule, name);
+    }
+    variants
+}
+
+//rule names as enum variants, some_rule becomes SomeRule
+fn variant(rulename: &str) -> String {
+    let mut name: String = rulename
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect();
+    if !name.starts_with(|c: char| c.is_alphabetic()) {
+        name.insert_str(0, "Rule");
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATEMENTS: &str = r#"
+codebody: statement*;
+statement: identifier "=" number ";" | "while" identifier ";";
+identifier: /[a-z]+/;
+number: /[0-9]+/;
+%ignore /[ ]+/;
+%reserved "while";
+"#;
+
+    //the module generate writes for STATEMENTS, compiled in so that the driver is checked too
+    mod statements {
+        include!("testdata/statements.rs");
+    }
+
+    #[test]
+    fn thecheckedinmoduleisuptodate() {
+        let parser = EbnfParser::from_str(STATEMENTS).expect("grammar");
+        let source = parser.generate("codebody").expect("generated");
+        //after changes to the generator, write the new source to src/testdata/statements.rs
+        assert!(source == include_str!("testdata/statements.rs"));
+    }
+
+    #[test]
+    fn thegeneratedmoduleparses() {
+        use statements::{Node, Rule, parse};
+        let tree = parse("a = 1; while b;").expect("parse");
+        let Node::Rule { rule, children, .. } = &tree else {
+            panic!("the start rule is a node");
+        };
+        assert_eq!((*rule, children.len()), (Rule::Codebody, 2));
+        let Node::Rule { children, end, .. } = &children[1] else {
+            panic!("statements are nodes");
+        };
+        assert_eq!(
+            children[0],
+            Node::Token {
+                text: "while".to_owned(),
+                start: 7,
+                end: 12
+            }
+        );
+        assert_eq!(*end, 15);
+        let error = parse("while = 1;").expect_err("while is reserved");
+        assert_eq!(
+            error.to_string(),
+            "Unexpected '=' at line 1, column 7, expected one of: identifier"
+        );
+    }
+
+    #[test]
+    fn thegeneratedmoduleparseslongrepetitions() {
+        let input = "a = 1; ".repeat(100_000);
+        let Ok(statements::Node::Rule { children, .. }) = statements::parse(&input) else {
+            panic!("the input parses");
+        };
+        assert_eq!(children.len(), 100_000);
+    }
+
+    #[test]
+    fn reservedwordsarewrittenout() {
+        let parser = EbnfParser::from_str(STATEMENTS).expect("grammar");
+        let source = parser.generate("codebody").expect("generated");
+        assert!(source.contains("const RESERVED: &[&str] = &[\n    \"while\",\n];"));
+        assert!(source.contains("fn reserved(text: &str, isstring: bool, rest: &str) -> bool"));
+    }
+
+    #[test]
+    fn invalidignoredpatternsarerejected() {
+        let mut parser = EbnfParser::from_str(STATEMENTS).expect("grammar");
+        parser
+            .ignore
+            .push(EbnfStatement::RegexTerminal { string: "[a-" });
+        match parser.generate("codebody") {
+            Err(LalrError::InvalidGrammar(err)) => {
+                assert!(matches!(err.errtype, ParseCodeErrorType::InvalidRegex))
+            }
+            _ => panic!("expected an invalid regex"),
+        }
+    }
+
+    #[test]
+    fn collidingrulenamesgetnumberedvariants() {
+        let variants = variants(&["_x", "fooBar", "foo_bar", "x", "x2"]);
+        assert_eq!(variants["_x"], "X");
+        assert_eq!(variants["fooBar"], "FooBar");
+        assert_eq!(variants["foo_bar"], "FooBar2");
+        assert_eq!(variants["x"], "X2");
+        assert_eq!(variants["x2"], "X22");
+    }
+}
@@ -0,0 +1,118 @@
+use crate::grammar::EbnfStatement;
+use crate::input::EbnfInput;
+use crate::parser::{EbnfParser, ParseCodeError};
+
+//what can come after a prefix of an input, for autocompletion
+pub struct EbnfCompletion {
+    //string terminals by their text
+    pub strings: Vec<String>,
+    //regex terminals by the rule that is only the regex, or as /regex/
+    pub patterns: Vec<String>,
+    //the prefix already is a whole input of the start rule
+    pub complete: bool,
+    //the rules the next token would be part of with where they started, outermost first
+    pub rules: Vec<(String, usize)>,
+}
+
+impl<'a> EbnfParser<'a> {
+    //the terminals that can follow the end of the input, an error if no input of the start rule
+    //starts with it. A word being typed at the end is a token of its own, the input before it
+    //gives the words it can be completed to
+    pub fn completions(
+        &self,
+        input: &str,
+        startrule: &str,
+    ) -> Result<EbnfCompletion, ParseCodeError> {
+        let text = EbnfInput::new(input);
+        let (rulename, body) = self.startrule(startrule)?;
+        let mut chart = self.startchart(&text, body)?;
+        let end = text.end() as u32;
+        for position in chart.start()..=end {
+            self.process(&mut chart, &text, position)?;
+        }
+        let finished = self.finish(&chart, &text, rulename);
+        let mut completion = EbnfCompletion {
+            strings: Vec::new(),
+            patterns: Vec::new(),
+            complete: finished.is_ok(),
+            rules: vec![(rulename.clone(), chart.start() as usize)],
+        };
+        for (terminal, wrapper) in chart.expected(end) {
+            let (list, item) = match terminal {
+                EbnfStatement::StringTerminal { string: "" } => continue,
+                EbnfStatement::StringTerminal { string } => {
+                    (&mut completion.strings, string.to_string())
+                }
+                _ => (
+                    &mut completion.patterns,
+                    wrapper.map_or_else(|| terminal.to_string(), str::to_owned),
+                ),
+            };
+            if !list.contains(&item) {
+                list.push(item);
+            }
+        }
+        if let Err(error) = finished
+            && completion.strings.is_empty()
+            && completion.patterns.is_empty()
+        {
+            return Err(error);
+        }
+        completion.strings.sort();
+        completion.patterns.sort();
+        for (rulename, start) in chart.enclosing(end) {
+            let rule = (rulename.to_owned(), start as usize);
+            if !completion.rules.contains(&rule) {
+                completion.rules.push(rule);
+            }
+        }
+        Ok(completion)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAMMAR: &str = r#"
+program: statement*;
+statement: "let" name "=" value ";" | "print" value ";";
+value: number | name;
+name: /[a-z]+/;
+number: /[0-9]+/;
+%ignore /[ ]+/;
+"#;
+
+    #[test]
+    fn theterminalsaftertheprefixarelisted() {
+        let parser = EbnfParser::from_str(GRAMMAR).expect("grammar");
+        let completion = parser
+            .completions("let a = ", "program")
+            .expect("completion");
+        assert!(completion.strings.is_empty());
+        assert_eq!(completion.patterns, ["name", "number"]);
+        assert!(!completion.complete);
+        assert_eq!(
+            completion.rules,
+            [("program".to_owned(), 0), ("statement".to_owned(), 0)]
+        );
+    }
+
+    #[test]
+    fn wholeinputsarecomplete() {
+        let parser = EbnfParser::from_str(GRAMMAR).expect("grammar");
+        let completion = parser
+            .completions("print 1;", "program")
+            .expect("completion");
+        assert_eq!(completion.strings, ["let", "print"]);
+        assert!(completion.complete);
+        assert_eq!(completion.rules, [("program".to_owned(), 0)]);
+    }
+
+    #[test]
+    fn prefixesofnoinputareerrors() {
+        let parser = EbnfParser::from_str(GRAMMAR).expect("grammar");
+        assert!(parser.completions("print ;", "program").is_err());
+        assert!(parser.completions("", "missing").is_err());
+    }
+}
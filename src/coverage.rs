@@ -0,0 +1,377 @@
+use std::{collections::HashMap, fmt::Write};
+
+use crate::grammar::{EbnfStatement, written};
+use crate::input::EbnfInput;
+use crate::parser::{EbnfChart, EbnfParser, ParseCodeError, ParseCodeErrorType};
+use crate::table::{address, choose};
+
+//which rules and branches the parse trees of a corpus went through
+pub struct EbnfCoverage<'a> {
+    parser: &'a EbnfParser<'a>,
+    startrule: &'a str,
+    //the rules reachable from the start rule in the order they are first used, then the rest
+    order: Vec<&'a str>,
+    pub inputs: usize,
+    //how often each rule was in a tree
+    pub rules: HashMap<&'a str, usize>,
+    //per statement address, how often each branch was taken: the left and right side of `|`,
+    //taken and skipped for `?`, and none, once and more for `*` and `+`
+    branches: HashMap<usize, [usize; 3]>,
+}
+
+impl<'a> EbnfParser<'a> {
+    pub fn coverage(&'a self, startrule: &str) -> Result<EbnfCoverage<'a>, ParseCodeError> {
+        let (startrule, _) = self.rules.get_key_value(startrule).ok_or(ParseCodeError {
+            line: 0,
+            column: 0,
+            errtype: ParseCodeErrorType::UnknownRule,
+        })?;
+        let mut order = Vec::new();
+        self.usedrules(startrule, &mut order);
+        let mut unreachable: Vec<&str> = self
+            .rules
+            .keys()
+            .map(String::as_str)
+            .filter(|rule| !order.contains(rule))
+            .collect();
+        unreachable.sort();
+        order.extend(unreachable);
+        Ok(EbnfCoverage {
+            parser: self,
+            startrule,
+            order,
+            inputs: 0,
+            rules: self.rules.keys().map(|rule| (rule.as_str(), 0)).collect(),
+            branches: HashMap::new(),
+        })
+    }
+
+    pub(crate) fn usedrules<'s>(&'s self, rulename: &str, order: &mut Vec<&'s str>) {
+        let Some((rulename, body)) = self.rules.get_key_value(rulename) else {
+            return;
+        };
+        if order.contains(&rulename.as_str()) {
+            return;
+        }
+        order.push(rulename);
+        let mut pending = vec![body];
+        while let Some(statement) = pending.pop() {
+            match statement {
+                EbnfStatement::DefinedRule { rulename } => self.usedrules(rulename, order),
+                EbnfStatement::Concatenation { rules } => pending.extend(rules.iter().rev()),
+                EbnfStatement::Or { left, right } => pending.extend([&**right, &**left]),
+                EbnfStatement::Optional { rule }
+                | EbnfStatement::OneOrMore { rule }
+                | EbnfStatement::ZeroOrMore { rule }
+                | EbnfStatement::And { rule }
+                | EbnfStatement::Not { rule } => pending.push(rule),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<'a> EbnfCoverage<'a> {
+    //parses the input with the start rule and counts the rules and branches the chart took
+    pub fn parse(&mut self, input: &str) -> Result<(), ParseCodeError> {
+        let input = EbnfInput::new(input);
+        let (rulename, body) = self.parser.startrule(self.startrule)?;
+        let mut chart = self.parser.startchart(&input, body)?;
+        for position in chart.start()..=input.end() as u32 {
+            self.parser.process(&mut chart, &input, position)?;
+        }
+        let root = chart.accepted(&input)?;
+        self.inputs += 1;
+        if let Some(count) = self.rules.get_mut(rulename.as_str()) {
+            *count += 1;
+        }
+        self.count(&chart, root);
+        Ok(())
+    }
+
+    //every partial of the derivation records the choice it was parsed with, repetitions are
+    //chains of partials and counted from their outermost one
+    fn count(&mut self, chart: &EbnfChart, root: u32) {
+        let mut stack = vec![root];
+        while let Some(index) = stack.pop() {
+            let statement = chart.statement(index);
+            let alternative = chart.partial(index).alternative;
+            match statement {
+                _ if chart.partial(index).isterminal => continue,
+                EbnfStatement::DefinedRule { rulename } => {
+                    if let Some(count) = self.rules.get_mut(*rulename) {
+                        *count += 1;
+                    }
+                }
+                EbnfStatement::Or { .. } => self.choice(statement, alternative as usize),
+                //the second alternative is the one with the statement
+                EbnfStatement::Optional { .. } => self.branch(statement, 1 - alternative as usize),
+                EbnfStatement::ZeroOrMore { .. } | EbnfStatement::OneOrMore { .. } => {
+                    let mut count = 0;
+                    let mut current = index;
+                    //the second alternative repeats the statement once more after itself
+                    while chart.partial(current).alternative == 1 {
+                        let children = chart.children(current);
+                        count += 1;
+                        stack.extend(children.get(1));
+                        current = children[0];
+                    }
+                    if let EbnfStatement::OneOrMore { .. } = statement {
+                        count += 1;
+                    }
+                    self.branch(statement, count.min(2));
+                    stack.extend(chart.children(current));
+                    continue;
+                }
+                //lookaheads leave nothing in the derivation
+                EbnfStatement::And { .. } | EbnfStatement::Not { .. } => continue,
+                _ => {}
+            }
+            stack.extend(chart.children(index));
+        }
+    }
+
+    //a choice of a chain of `|` takes one side of each `|` on the way to it
+    fn choice(&mut self, statement: &EbnfStatement, mut choice: usize) {
+        let mut current = statement;
+        while let EbnfStatement::Or { left, right } = current {
+            let mut choices = Vec::new();
+            choose(left, &mut choices);
+            if choice < choices.len() {
+                self.branch(current, 0);
+                current = left;
+            } else {
+                self.branch(current, 1);
+                choice -= choices.len();
+                current = right;
+            }
+        }
+    }
+
+    fn branch(&mut self, statement: &EbnfStatement, branch: usize) {
+        self.branches.entry(address(statement)).or_default()[branch] += 1;
+    }
+
+    fn taken(&self, statement: &EbnfStatement) -> [usize; 3] {
+        self.branches
+            .get(&address(statement))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    //the statement with the counts of its branches, and what was reached but never taken
+    fn annotate(
+        &self,
+        rulename: &str,
+        statement: &EbnfStatement,
+        nested: bool,
+        out: &mut String,
+        missing: &mut Vec<String>,
+    ) {
+        match statement {
+            EbnfStatement::Concatenation { rules } => {
+                out.push_str(if nested { "(" } else { "" });
+                for (i, rule) in rules.iter().enumerate() {
+                    out.push_str(if i > 0 { " " } else { "" });
+                    self.annotate(rulename, rule, true, out, missing);
+                }
+                out.push_str(if nested { ")" } else { "" });
+            }
+            EbnfStatement::Or { .. } => {
+                let alternatives = self.alternatives(statement);
+                let reached = alternatives.iter().map(|(_, count)| count).sum::<usize>() > 0;
+                out.push_str(if nested { "(" } else { "" });
+                for (i, (alternative, count)) in alternatives.iter().enumerate() {
+                    out.push_str(if i > 0 { " | " } else { "" });
+                    self.annotate(rulename, alternative, true, out, missing);
+                    let _ = write!(out, "{{{count}}}");
+                    if reached && *count == 0 {
+                        missing.push(format!(
+                            "{rulename}: alternative {} never taken",
+                            written(alternative, false)
+                        ));
+                    }
+                }
+                out.push_str(if nested { ")" } else { "" });
+            }
+            EbnfStatement::Optional { rule } => {
+                let [present, skipped, _] = self.taken(statement);
+                self.annotate(rulename, rule, true, out, missing);
+                let _ = write!(out, "?{{taken {present}, skipped {skipped}}}");
+                for (count, what) in [(present, "taken"), (skipped, "skipped")] {
+                    if present + skipped > 0 && count == 0 {
+                        let written = written(statement, false);
+                        missing.push(format!("{rulename}: {written} never {what}"));
+                    }
+                }
+            }
+            EbnfStatement::ZeroOrMore { rule } | EbnfStatement::OneOrMore { rule } => {
+                let [none, once, more] = self.taken(statement);
+                self.annotate(rulename, rule, true, out, missing);
+                let mut counts = vec![(once, "once"), (more, "more than once")];
+                if let EbnfStatement::ZeroOrMore { .. } = statement {
+                    counts.insert(0, (none, "0 times"));
+                    let _ = write!(out, "*{{none {none}, once {once}, more {more}}}");
+                } else {
+                    let _ = write!(out, "+{{once {once}, more {more}}}");
+                }
+                for (count, what) in counts {
+                    if none + once + more > 0 && count == 0 {
+                        let written = written(statement, false);
+                        missing.push(format!("{rulename}: {written} never matched {what}"));
+                    }
+                }
+            }
+            EbnfStatement::And { rule } | EbnfStatement::Not { rule } => {
+                out.push(if let EbnfStatement::And { .. } = statement {
+                    '&'
+                } else {
+                    '!'
+                });
+                self.annotate(rulename, rule, true, out, missing);
+            }
+            _ => {
+                let _ = write!(out, "{statement}");
+            }
+        }
+    }
+
+    //the choices of nested `|` with how often each was taken
+    fn alternatives<'s>(
+        &self,
+        statement: &'s EbnfStatement<'a>,
+    ) -> Vec<(&'s EbnfStatement<'a>, usize)> {
+        let mut alternatives = Vec::new();
+        let mut current = statement;
+        while let EbnfStatement::Or { left, right } = current {
+            let [leftcount, rightcount, _] = self.taken(current);
+            alternatives.push((left.as_ref(), leftcount));
+            current = right;
+            if !matches!(current, EbnfStatement::Or { .. }) {
+                alternatives.push((current, rightcount));
+            }
+        }
+        alternatives
+    }
+
+    //unused rules and the branches of used ones that were never taken
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        let unused: Vec<&str> = self
+            .order
+            .iter()
+            .copied()
+            .filter(|rule| self.rules[rule] == 0)
+            .collect();
+        let _ = writeln!(
+            out,
+            "{} inputs, {} of {} rules used",
+            self.inputs,
+            self.order.len() - unused.len(),
+            self.order.len()
+        );
+        if !unused.is_empty() {
+            let _ = writeln!(out, "never used: {}", unused.join(", "));
+        }
+        let mut missing = Vec::new();
+        for rulename in &self.order {
+            if self.rules[rulename] > 0 {
+                let body = &self.parser.rules[*rulename];
+                self.annotate(rulename, body, false, &mut String::new(), &mut missing);
+            }
+        }
+        for branch in missing {
+            let _ = writeln!(out, "{branch}");
+        }
+        out
+    }
+
+    //the grammar with the counts of every rule, alternative and branch, lines with something
+    //that was never taken are marked with '!'
+    pub fn listing(&self) -> String {
+        let mut out = String::new();
+        for rulename in &self.order {
+            let used = self.rules[rulename];
+            let body = &self.parser.rules[*rulename];
+            let mut missing = Vec::new();
+            let mut line = |count: usize, text: &str, missing: &mut Vec<String>| {
+                let mark = if count == 0 || !missing.is_empty() {
+                    '!'
+                } else {
+                    ' '
+                };
+                let _ = writeln!(out, "{count:>8} {mark} {text}");
+                missing.clear();
+            };
+            if let EbnfStatement::Or { .. } = body {
+                line(used, &format!("{rulename}:"), &mut missing);
+                for (i, (alternative, count)) in self.alternatives(body).into_iter().enumerate() {
+                    let mut text = String::from(if i > 0 { "    | " } else { "      " });
+                    self.annotate(rulename, alternative, false, &mut text, &mut missing);
+                    line(count, &text, &mut missing);
+                }
+            } else {
+                let mut text = format!("{rulename}: ");
+                self.annotate(rulename, body, false, &mut text, &mut missing);
+                line(used, &text, &mut missing);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAMMAR: &str = r#"
+list: "[" items? "]";
+items: item ("," item)*;
+item: number | name;
+number: /[0-9]+/;
+name: /[a-z]+/;
+unused: "x";
+"#;
+
+    #[test]
+    fn thereportlistswhatwasnevertaken() {
+        let parser = EbnfParser::from_str(GRAMMAR).expect("grammar");
+        let mut coverage = parser.coverage("list").expect("coverage");
+        coverage.parse("[1]").expect("parse");
+        coverage.parse("[2,3]").expect("parse");
+        assert!(coverage.parse("[").is_err());
+        assert_eq!(coverage.inputs, 2);
+        assert_eq!(coverage.rules["number"], 3);
+        assert_eq!(
+            coverage.report(),
+            concat!(
+                "2 inputs, 4 of 6 rules used\n",
+                "never used: name, unused\n",
+                "list: items? never skipped\n",
+                "items: (\",\" item)* never matched more than once\n",
+                "item: alternative name never taken\n",
+            )
+        );
+    }
+
+    #[test]
+    fn thelistingcountseverybranch() {
+        let parser = EbnfParser::from_str(GRAMMAR).expect("grammar");
+        let mut coverage = parser.coverage("list").expect("coverage");
+        coverage.parse("[]").expect("parse");
+        coverage.parse("[a,b,c]").expect("parse");
+        assert_eq!(
+            coverage.listing(),
+            concat!(
+                "       2   list: \"[\" items?{taken 1, skipped 1} \"]\"\n",
+                "       1 ! items: item (\",\" item)*{none 0, once 0, more 1}\n",
+                "       3   item:\n",
+                "       0 !       number\n",
+                "       3       | name\n",
+                "       0 ! number: /[0-9]+/\n",
+                "       3   name: /[a-z]+/\n",
+                "       0 ! unused: \"x\"\n",
+            )
+        );
+    }
+}
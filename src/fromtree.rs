@@ -0,0 +1,588 @@
+use std::{any, collections::HashSet, fmt};
+
+use crate::grammar::EbnfStatement;
+use crate::parser::EbnfParser;
+use crate::transform::EbnfVisible;
+use crate::tree::EbnfTree;
+
+//types read from parse trees, usually derived with #[derive(FromParseTree)], see the
+//Transformer for which tokens and nodes are visible
+pub trait FromParseTree: Sized {
+    //the rule a struct is read from, None for enums and plain values
+    fn rulename() -> Option<&'static str> {
+        None
+    }
+
+    fn accepts(parser: &EbnfParser, tree: &EbnfTree) -> bool;
+
+    fn fromtree(parser: &EbnfParser, tree: &EbnfTree) -> Result<Self, FromTreeError>;
+
+    //compares the rules the type reads with the grammar, `checked` has the types already done
+    fn checkgrammar(
+        _parser: &EbnfParser,
+        _checked: &mut HashSet<&'static str>,
+    ) -> Result<(), FromTreeError> {
+        Ok(())
+    }
+}
+
+//fields of derived types, read from the children of a node one after the other
+pub trait FromChildren: Sized {
+    fn rulename() -> Option<&'static str> {
+        None
+    }
+
+    fn fromchildren(children: &mut EbnfChildren) -> Result<Self, FromTreeError>;
+
+    //fewest and most children read, None if there is no limit
+    fn count() -> (usize, Option<usize>);
+
+    fn checkgrammar(
+        parser: &EbnfParser,
+        checked: &mut HashSet<&'static str>,
+    ) -> Result<(), FromTreeError>;
+}
+
+//the visible children of a rule node and how many of them the fields have read
+pub struct EbnfChildren<'t> {
+    parser: &'t EbnfParser<'t>,
+    node: &'t EbnfTree,
+    rule: &'t str,
+    children: Vec<&'t EbnfTree>,
+    at: usize,
+}
+
+pub struct FromTreeError {
+    //byte offset of the node, 0 for errors found in the grammar
+    pub position: usize,
+    pub errtype: FromTreeErrorType,
+}
+
+pub enum FromTreeErrorType {
+    UnexpectedNode {
+        expected: String,
+        found: String,
+    },
+    MissingChild {
+        rule: String,
+        expected: String,
+    },
+    ExtraChildren {
+        rule: String,
+        found: String,
+    },
+    InvalidValue {
+        text: String,
+        expected: String,
+    },
+    UnknownRule(String),
+    ShapeMismatch {
+        name: String,
+        rule: String,
+        fields: (usize, Option<usize>),
+        children: (usize, Option<usize>),
+    },
+}
+
+impl fmt::Display for FromTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let position = self.position;
+        match &self.errtype {
+            FromTreeErrorType::UnexpectedNode { expected, found } => {
+                write!(f, "Expected {expected}, found {found} at byte {position}")
+            }
+            FromTreeErrorType::MissingChild { rule, expected } => {
+                write!(f, "Missing {expected} in {rule} at byte {position}")
+            }
+            FromTreeErrorType::ExtraChildren { rule, found } => {
+                write!(f, "Unexpected {found} in {rule} at byte {position}")
+            }
+            FromTreeErrorType::InvalidValue { text, expected } => {
+                write!(f, "'{text}' is not a valid {expected} at byte {position}")
+            }
+            FromTreeErrorType::UnknownRule(rule) => {
+                write!(f, "The grammar has no rule or alias '{rule}'")
+            }
+            FromTreeErrorType::ShapeMismatch {
+                name,
+                rule,
+                fields,
+                children,
+            } => write!(
+                f,
+                "{name} reads {} children, but '{rule}' has {}",
+                range(*fields),
+                range(*children)
+            ),
+        }
+    }
+}
+
+impl fmt::Debug for FromTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+fn range((min, max): (usize, Option<usize>)) -> String {
+    match max {
+        Some(max) if max == min => min.to_string(),
+        Some(max) => format!("{min} to {max}"),
+        None => format!("{min} or more"),
+    }
+}
+
+impl<'a> EbnfParser<'a> {
+    pub fn fromtree<T: FromParseTree>(&self, tree: &EbnfTree) -> Result<T, FromTreeError> {
+        T::fromtree(self, tree)
+    }
+
+    //finds types that can never be read from the grammar's trees, meant to be called once
+    //after the grammar is loaded
+    pub fn checktree<T: FromParseTree>(&self) -> Result<(), FromTreeError> {
+        T::checkgrammar(self, &mut HashSet::new())
+    }
+}
+
+//the name and visible children of a rule node, looking through inlined ?rules
+pub fn treenode<'t>(
+    parser: &'t EbnfParser,
+    tree: &'t EbnfTree,
+) -> Option<(&'t str, Vec<&'t EbnfTree>)> {
+    let mut tree = tree;
+    loop {
+        let EbnfTree::Rule {
+            name,
+            alternative,
+            children,
+            ..
+        } = tree
+        else {
+            return None;
+        };
+        match parser.visible(name, *alternative, children) {
+            EbnfVisible::Inlined(child) => tree = child,
+            EbnfVisible::Node(name, children) => return Some((name, children)),
+        }
+    }
+}
+
+impl<'t> EbnfChildren<'t> {
+    //the children of the node, which has to be `rule`
+    pub fn new(
+        parser: &'t EbnfParser<'t>,
+        tree: &'t EbnfTree,
+        rule: &'t str,
+    ) -> Result<Self, FromTreeError> {
+        match treenode(parser, tree) {
+            Some((name, children)) if name == rule => Ok(EbnfChildren {
+                parser,
+                node: tree,
+                rule,
+                children,
+                at: 0,
+            }),
+            _ => Err(unexpectednode(parser, tree, rule)),
+        }
+    }
+
+    //the node as the only child, for enum variants holding the type of their rule
+    pub fn whole(parser: &'t EbnfParser<'t>, tree: &'t EbnfTree, rule: &'t str) -> Self {
+        EbnfChildren {
+            parser,
+            node: tree,
+            rule,
+            children: vec![tree],
+            at: 0,
+        }
+    }
+
+    pub fn read<T: FromChildren>(&mut self) -> Result<T, FromTreeError> {
+        T::fromchildren(self)
+    }
+
+    //every child has to be read by the fields
+    pub fn end(self) -> Result<(), FromTreeError> {
+        match self.children.get(self.at) {
+            None => Ok(()),
+            Some(child) => Err(FromTreeError {
+                position: child.start(),
+                errtype: FromTreeErrorType::ExtraChildren {
+                    rule: self.rule.to_owned(),
+                    found: describe(child),
+                },
+            }),
+        }
+    }
+
+    //the next child if `accepts` takes it
+    fn next(&mut self, accepts: impl Fn(&EbnfParser, &EbnfTree) -> bool) -> Option<&'t EbnfTree> {
+        let child = *self.children.get(self.at)?;
+        if !accepts(self.parser, child) {
+            return None;
+        }
+        self.at += 1;
+        Some(child)
+    }
+
+    fn missing(&self, expected: &str) -> FromTreeError {
+        let position = match self.children.get(self.at) {
+            Some(child) => child.start(),
+            None => self.node.end(),
+        };
+        FromTreeError {
+            position,
+            errtype: FromTreeErrorType::MissingChild {
+                rule: self.rule.to_owned(),
+                expected: expected.to_owned(),
+            },
+        }
+    }
+}
+
+pub fn unexpectednode(parser: &EbnfParser, tree: &EbnfTree, expected: &str) -> FromTreeError {
+    let found = match treenode(parser, tree) {
+        Some((name, _)) => name.to_owned(),
+        None => describe(tree),
+    };
+    FromTreeError {
+        position: tree.start(),
+        errtype: FromTreeErrorType::UnexpectedNode {
+            expected: expected.to_owned(),
+            found,
+        },
+    }
+}
+
+fn describe(tree: &EbnfTree) -> String {
+    match tree {
+        EbnfTree::Rule { name, .. } => name.clone(),
+        EbnfTree::Token { text, .. } => format!("token '{text}'"),
+        EbnfTree::Error { text, .. } => format!("skipped input '{text}'"),
+    }
+}
+
+//compares the children the fields of `name` read with the ones the rule or alias can have
+pub fn checkfields(
+    parser: &EbnfParser,
+    name: &str,
+    rule: &str,
+    fields: &[(usize, Option<usize>)],
+) -> Result<(), FromTreeError> {
+    let fields = fields
+        .iter()
+        .fold((0, Some(0)), |total, field| add(total, *field));
+    let mut children = None;
+    if let Some(body) = parser.rules.get(rule) {
+        children = Some(childcount(parser, rule, body));
+    }
+    for ((owner, alternative), alias) in &parser.aliases {
+        if alias == rule {
+            let count = childcount(
+                parser,
+                owner,
+                nthalternative(&parser.rules[owner], *alternative),
+            );
+            children = Some(children.map_or(count, |children| either(children, count)));
+        }
+    }
+    let Some(children) = children else {
+        return Err(FromTreeError {
+            position: 0,
+            errtype: FromTreeErrorType::UnknownRule(rule.to_owned()),
+        });
+    };
+    let toofew = fields.1.is_some_and(|max| max < children.0);
+    let toomany = children.1.is_some_and(|max| max < fields.0);
+    if toofew || toomany {
+        return Err(FromTreeError {
+            position: 0,
+            errtype: FromTreeErrorType::ShapeMismatch {
+                name: name.to_owned(),
+                rule: rule.to_owned(),
+                fields,
+                children,
+            },
+        });
+    }
+    Ok(())
+}
+
+//true the first time a type is checked, recursive types stop there
+pub fn firstcheck<T>(checked: &mut HashSet<&'static str>) -> bool {
+    checked.insert(any::type_name::<T>())
+}
+
+fn nthalternative<'s, 'a>(body: &'s EbnfStatement<'a>, n: usize) -> &'s EbnfStatement<'a> {
+    let mut current = body;
+    for _ in 0..n {
+        if let EbnfStatement::Or { right, .. } = current {
+            current = right;
+        }
+    }
+    match current {
+        EbnfStatement::Or { left, .. } => left,
+        current => current,
+    }
+}
+
+//how many visible children the statement leaves in a node of `rule`
+fn childcount(
+    parser: &EbnfParser,
+    rule: &str,
+    statement: &EbnfStatement,
+) -> (usize, Option<usize>) {
+    match statement {
+        EbnfStatement::StringTerminal { .. } if !parser.keeptokens.contains(rule) => (0, Some(0)),
+        EbnfStatement::StringTerminal { .. }
+        | EbnfStatement::RegexTerminal { .. }
+        | EbnfStatement::DefinedRule { .. } => (1, Some(1)),
+        EbnfStatement::Concatenation { rules } => {
+            rules.iter().fold((0, Some(0)), |total, rule_| {
+                add(total, childcount(parser, rule, rule_))
+            })
+        }
+        EbnfStatement::Or { left, right } => either(
+            childcount(parser, rule, left),
+            childcount(parser, rule, right),
+        ),
+        EbnfStatement::Optional { rule: inner } => (0, childcount(parser, rule, inner).1),
+        EbnfStatement::ZeroOrMore { rule: inner } | EbnfStatement::OneOrMore { rule: inner } => {
+            let (min, max) = childcount(parser, rule, inner);
+            let min = if matches!(statement, EbnfStatement::OneOrMore { .. }) {
+                min
+            } else {
+                0
+            };
+            (min, if max == Some(0) { Some(0) } else { None })
+        }
+        EbnfStatement::And { .. } | EbnfStatement::Not { .. } | EbnfStatement::Empty => {
+            (0, Some(0))
+        }
+    }
+}
+
+fn add(a: (usize, Option<usize>), b: (usize, Option<usize>)) -> (usize, Option<usize>) {
+    (a.0 + b.0, a.1.zip(b.1).map(|(a, b)| a + b))
+}
+
+fn either(a: (usize, Option<usize>), b: (usize, Option<usize>)) -> (usize, Option<usize>) {
+    (a.0.min(b.0), a.1.zip(b.1).map(|(a, b)| a.max(b)))
+}
+
+//the text of every token below the node
+fn treetext(tree: &EbnfTree) -> String {
+    match tree {
+        EbnfTree::Rule { children, .. } => children.iter().map(treetext).collect(),
+        EbnfTree::Token { text, .. } | EbnfTree::Error { text, .. } => text.clone(),
+    }
+}
+
+impl<T: FromParseTree> FromChildren for T {
+    fn rulename() -> Option<&'static str> {
+        T::rulename()
+    }
+
+    //a child that is not accepted is still read, for the error it gives
+    fn fromchildren(children: &mut EbnfChildren) -> Result<Self, FromTreeError> {
+        match children.next(|_, _| true) {
+            Some(child) => T::fromtree(children.parser, child),
+            None => Err(children.missing(any::type_name::<T>())),
+        }
+    }
+
+    fn count() -> (usize, Option<usize>) {
+        (1, Some(1))
+    }
+
+    fn checkgrammar(
+        parser: &EbnfParser,
+        checked: &mut HashSet<&'static str>,
+    ) -> Result<(), FromTreeError> {
+        T::checkgrammar(parser, checked)
+    }
+}
+
+impl<T: FromParseTree> FromChildren for Option<T> {
+    fn fromchildren(children: &mut EbnfChildren) -> Result<Self, FromTreeError> {
+        match children.next(T::accepts) {
+            Some(child) => Ok(Some(T::fromtree(children.parser, child)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn count() -> (usize, Option<usize>) {
+        (0, Some(1))
+    }
+
+    fn checkgrammar(
+        parser: &EbnfParser,
+        checked: &mut HashSet<&'static str>,
+    ) -> Result<(), FromTreeError> {
+        T::checkgrammar(parser, checked)
+    }
+}
+
+impl<T: FromParseTree> FromChildren for Vec<T> {
+    fn fromchildren(children: &mut EbnfChildren) -> Result<Self, FromTreeError> {
+        let mut values = Vec::new();
+        while let Some(child) = children.next(T::accepts) {
+            values.push(T::fromtree(children.parser, child)?);
+        }
+        Ok(values)
+    }
+
+    fn count() -> (usize, Option<usize>) {
+        (0, None)
+    }
+
+    fn checkgrammar(
+        parser: &EbnfParser,
+        checked: &mut HashSet<&'static str>,
+    ) -> Result<(), FromTreeError> {
+        T::checkgrammar(parser, checked)
+    }
+}
+
+impl<T: FromParseTree> FromParseTree for Box<T> {
+    fn rulename() -> Option<&'static str> {
+        T::rulename()
+    }
+
+    fn accepts(parser: &EbnfParser, tree: &EbnfTree) -> bool {
+        T::accepts(parser, tree)
+    }
+
+    fn fromtree(parser: &EbnfParser, tree: &EbnfTree) -> Result<Self, FromTreeError> {
+        Ok(Box::new(T::fromtree(parser, tree)?))
+    }
+
+    fn checkgrammar(
+        parser: &EbnfParser,
+        checked: &mut HashSet<&'static str>,
+    ) -> Result<(), FromTreeError> {
+        T::checkgrammar(parser, checked)
+    }
+}
+
+//the node itself, for parts of the tree that are not worth a type
+impl FromParseTree for EbnfTree {
+    fn accepts(_parser: &EbnfParser, _tree: &EbnfTree) -> bool {
+        true
+    }
+
+    fn fromtree(_parser: &EbnfParser, tree: &EbnfTree) -> Result<Self, FromTreeError> {
+        Ok(tree.clone())
+    }
+}
+
+//the text of the node, for rules like `identifier: /[a-z]+/;`
+impl FromParseTree for String {
+    fn accepts(_parser: &EbnfParser, _tree: &EbnfTree) -> bool {
+        true
+    }
+
+    fn fromtree(_parser: &EbnfParser, tree: &EbnfTree) -> Result<Self, FromTreeError> {
+        Ok(treetext(tree))
+    }
+}
+
+macro_rules! fromtext {
+    ($($type:ty),*) => {$(
+        impl FromParseTree for $type {
+            fn accepts(_parser: &EbnfParser, tree: &EbnfTree) -> bool {
+                treetext(tree).parse::<$type>().is_ok()
+            }
+
+            fn fromtree(_parser: &EbnfParser, tree: &EbnfTree) -> Result<Self, FromTreeError> {
+                let text = treetext(tree);
+                text.parse().map_err(|_| FromTreeError {
+                    position: tree.start(),
+                    errtype: FromTreeErrorType::InvalidValue {
+                        text,
+                        expected: stringify!($type).to_owned(),
+                    },
+                })
+            }
+        }
+    )*};
+}
+
+fromtext!(i32, i64, u32, u64, usize, f64, bool, char);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = r#"
+program: statement*;
+?statement: assignment | call;
+assignment: name "=" expression ";";
+call: name "(" (expression ("," expression)*)? ")" ";";
+?expression: number | name;
+name: /[a-z]+/;
+number: /[0-9]+/;
+%ignore /[ ]+/;
+"#;
+
+    #[derive(crate::FromParseTree, PartialEq, Debug)]
+    struct Program(Vec<Statement>);
+
+    #[derive(crate::FromParseTree, PartialEq, Debug)]
+    enum Statement {
+        Assignment { target: Name, value: Expression },
+        Call(Name, Vec<Expression>),
+    }
+
+    #[derive(crate::FromParseTree, PartialEq, Debug)]
+    enum Expression {
+        Number(i64),
+        Name(Name),
+    }
+
+    #[derive(crate::FromParseTree, PartialEq, Debug)]
+    struct Name(String);
+
+    fn read<T: FromParseTree>(grammar: &str, input: &str) -> Result<T, String> {
+        let parser = EbnfParser::from_str(grammar).expect("grammar");
+        parser.checktree::<T>().map_err(|error| error.to_string())?;
+        let tree = parser.parse(input, "program").expect("parsed");
+        parser.fromtree(&tree).map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn fieldsarereadfromthechildren() {
+        let name = |name: &str| Name(name.to_owned());
+        assert_eq!(
+            read::<Program>(PROGRAM, "a = 1; f(a, 2);"),
+            Ok(Program(vec![
+                Statement::Assignment {
+                    target: name("a"),
+                    value: Expression::Number(1)
+                },
+                Statement::Call(
+                    name("f"),
+                    vec![Expression::Name(name("a")), Expression::Number(2)]
+                ),
+            ]))
+        );
+    }
+
+    #[test]
+    fn shapemismatchesarefoundbeforeanyparse() {
+        let grammar = PROGRAM.replace(r#"assignment: name "=""#, r#"assignment: name name "=""#);
+        assert_eq!(
+            read::<Program>(&grammar, ""),
+            Err("Statement::Assignment reads 2 children, but 'assignment' has 3".to_owned())
+        );
+    }
+
+    #[test]
+    fn invalidvaluesnamethetype() {
+        let grammar = PROGRAM.replace("/[0-9]+/", "/[0-9]+x?/");
+        assert_eq!(
+            read::<Program>(&grammar, "a = 1x;"),
+            Err("'1x' is not a valid i64 at byte 4".to_owned())
+        );
+    }
+}
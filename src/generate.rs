@@ -0,0 +1,399 @@
+use regex_syntax::hir::{Class, Hir, HirKind};
+use std::collections::HashMap;
+
+use crate::grammar::EbnfStatement;
+use crate::parser::{EbnfParser, ParseCodeError, ParseCodeErrorType};
+use crate::table::{choose, compiled};
+
+//random sentences of a rule, for fuzzing whatever consumes the language
+pub struct EbnfGenerator<'a> {
+    parser: &'a EbnfParser<'a>,
+    startrule: &'a str,
+    //rules expanded deeper than this take the shortest way to the end
+    pub maxdepth: u32,
+    //extra repetitions of `*`, `+` and unbounded regex repetitions
+    pub maxrepeat: u32,
+    //weights of alternatives that are a single rule name, 1 if missing, 0 never picks them
+    pub weights: HashMap<String, u32>,
+    //put between tokens, a space if the grammar ignores one
+    pub separator: String,
+    //state of the random numbers, the same seed gives the same sentences
+    pub seed: u64,
+    //the fewest rule expansions each rule needs to end, u32::MAX if it never does
+    heights: HashMap<&'a str, u32>,
+    patterns: HashMap<&'a str, Hir>,
+}
+
+fn grammarerror(errtype: ParseCodeErrorType) -> ParseCodeError {
+    ParseCodeError {
+        line: 0,
+        column: 0,
+        errtype,
+    }
+}
+
+//splitmix64, a number below `bound`
+fn random(seed: &mut u64, bound: u64) -> u64 {
+    *seed = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut value = *seed;
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^= value >> 31;
+    if bound == 0 { 0 } else { value % bound }
+}
+
+impl<'a> EbnfParser<'a> {
+    pub fn generator(&'a self, startrule: &str) -> Result<EbnfGenerator<'a>, ParseCodeError> {
+        let (startrule, _) = self
+            .rules
+            .get_key_value(startrule)
+            .ok_or_else(|| grammarerror(ParseCodeErrorType::UnknownRule))?;
+        let separator = match self.skipignored(" ", 0) {
+            Ok(1) => " ",
+            _ => "",
+        };
+        let mut generator = EbnfGenerator {
+            parser: self,
+            startrule,
+            maxdepth: 12,
+            maxrepeat: 3,
+            weights: HashMap::new(),
+            separator: separator.to_owned(),
+            seed: 0,
+            heights: HashMap::new(),
+            patterns: HashMap::new(),
+        };
+        let mut pending = vec![startrule.as_str()];
+        while let Some(rulename) = pending.pop() {
+            let (rulename, body) = self
+                .rules
+                .get_key_value(rulename)
+                .ok_or_else(|| grammarerror(ParseCodeErrorType::UnknownRule))?;
+            if generator.heights.insert(rulename, u32::MAX).is_none() {
+                generator.collect(body, &mut pending)?;
+            }
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for rulename in generator.heights.keys().copied().collect::<Vec<_>>() {
+                let height = generator.height(&self.rules[rulename]).saturating_add(1);
+                if height < generator.heights[rulename] {
+                    generator.heights.insert(rulename, height);
+                    changed = true;
+                }
+            }
+        }
+        Ok(generator)
+    }
+}
+
+impl<'a> EbnfGenerator<'a> {
+    //the rules a statement uses, and its regexes parsed for sampling
+    fn collect(
+        &mut self,
+        statement: &'a EbnfStatement<'a>,
+        pending: &mut Vec<&'a str>,
+    ) -> Result<(), ParseCodeError> {
+        match statement {
+            EbnfStatement::DefinedRule { rulename } => pending.push(rulename),
+            EbnfStatement::RegexTerminal { string } => {
+                let invalid = || grammarerror(ParseCodeErrorType::InvalidRegex);
+                compiled(string, &self.parser.regexes).ok_or_else(invalid)?;
+                let hir = regex_syntax::parse(string).map_err(|_| invalid())?;
+                self.patterns.insert(string, hir);
+            }
+            EbnfStatement::Concatenation { rules } => {
+                for rule in rules {
+                    self.collect(rule, pending)?;
+                }
+            }
+            EbnfStatement::Or { left, right } => {
+                self.collect(left, pending)?;
+                self.collect(right, pending)?;
+            }
+            EbnfStatement::Optional { rule }
+            | EbnfStatement::OneOrMore { rule }
+            | EbnfStatement::ZeroOrMore { rule }
+            | EbnfStatement::And { rule }
+            | EbnfStatement::Not { rule } => self.collect(rule, pending)?,
+            EbnfStatement::StringTerminal { .. } | EbnfStatement::Empty => {}
+        }
+        Ok(())
+    }
+
+    fn height(&self, statement: &EbnfStatement) -> u32 {
+        match statement {
+            EbnfStatement::DefinedRule { rulename } => {
+                self.heights.get(rulename).copied().unwrap_or(u32::MAX)
+            }
+            EbnfStatement::Concatenation { rules } => rules
+                .iter()
+                .map(|rule| self.height(rule))
+                .max()
+                .unwrap_or(0),
+            EbnfStatement::Or { left, right } => self.height(left).min(self.height(right)),
+            EbnfStatement::OneOrMore { rule } => self.height(rule),
+            _ => 0,
+        }
+    }
+
+    //a new random sentence of the start rule, its tokens joined by the separator
+    pub fn generate(&mut self) -> String {
+        let mut tokens = Vec::new();
+        self.expand(&self.parser.rules[self.startrule], 0, &mut tokens);
+        tokens.join(&self.separator)
+    }
+
+    fn expand(&mut self, statement: &'a EbnfStatement<'a>, depth: u32, tokens: &mut Vec<String>) {
+        let limited = depth >= self.maxdepth;
+        match statement {
+            EbnfStatement::StringTerminal { string } => tokens.push(string.to_string()),
+            EbnfStatement::RegexTerminal { string } => tokens.push(self.sample(string)),
+            EbnfStatement::DefinedRule { rulename } => {
+                //rules that never end are cut off once the limit is reached
+                if !limited || self.heights[rulename] != u32::MAX {
+                    self.expand(&self.parser.rules[*rulename], depth + 1, tokens);
+                }
+            }
+            EbnfStatement::Concatenation { rules } => {
+                for rule in rules {
+                    self.expand(rule, depth, tokens);
+                }
+            }
+            EbnfStatement::Optional { rule } => {
+                if !limited && random(&mut self.seed, 2) == 1 {
+                    self.expand(rule, depth, tokens);
+                }
+            }
+            EbnfStatement::ZeroOrMore { rule } | EbnfStatement::OneOrMore { rule } => {
+                let least = matches!(statement, EbnfStatement::OneOrMore { .. }) as u64;
+                let extra = if limited {
+                    0
+                } else {
+                    random(&mut self.seed, self.maxrepeat as u64 + 1)
+                };
+                for _ in 0..least + extra {
+                    self.expand(rule, depth, tokens);
+                }
+            }
+            EbnfStatement::Or { .. } => {
+                let mut choices = Vec::new();
+                choose(statement, &mut choices);
+                let choice = self.pick(&choices, limited);
+                self.expand(choice, depth, tokens);
+            }
+            //lookaheads consume nothing, so they are left out
+            EbnfStatement::And { .. } | EbnfStatement::Not { .. } | EbnfStatement::Empty => {}
+        }
+    }
+
+    //a weighted choice, or past the depth limit one of the shortest
+    fn pick(&mut self, choices: &[&'a EbnfStatement<'a>], limited: bool) -> &'a EbnfStatement<'a> {
+        let weights: Vec<u64> = choices
+            .iter()
+            .map(|choice| match choice {
+                EbnfStatement::DefinedRule { rulename } => {
+                    self.weights.get(*rulename).copied().unwrap_or(1) as u64
+                }
+                _ => 1,
+            })
+            .collect();
+        let total: u64 = weights.iter().sum();
+        if !limited && total > 0 {
+            let mut target = random(&mut self.seed, total);
+            for (choice, weight) in choices.iter().zip(weights) {
+                if target < weight {
+                    return choice;
+                }
+                target -= weight;
+            }
+        }
+        let shortest = choices
+            .iter()
+            .map(|choice| self.height(choice))
+            .min()
+            .unwrap_or(0);
+        let candidates: Vec<_> = choices
+            .iter()
+            .filter(|choice| self.height(choice) == shortest)
+            .collect();
+        candidates[random(&mut self.seed, candidates.len() as u64) as usize]
+    }
+
+    //a text the regex matches completely, a few tries as the regex prefers earlier alternatives
+    fn sample(&mut self, pattern: &str) -> String {
+        let regex = compiled(pattern, &self.parser.regexes);
+        let mut text = String::new();
+        for _ in 0..8 {
+            text.clear();
+            samplehir(
+                &self.patterns[pattern],
+                self.maxrepeat,
+                &mut self.seed,
+                &mut text,
+            );
+            if regex
+                .as_ref()
+                .and_then(|regex| regex.find(&text))
+                .is_some_and(|found| found.end() == text.len())
+                && !self.parser.reserved.contains(&text.as_str())
+            {
+                break;
+            }
+        }
+        text
+    }
+}
+
+fn samplehir(hir: &Hir, maxrepeat: u32, seed: &mut u64, out: &mut String) {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => {}
+        HirKind::Literal(literal) => out.push_str(&String::from_utf8_lossy(&literal.0)),
+        HirKind::Class(Class::Unicode(class)) => {
+            let ranges: Vec<(u32, u32)> = class
+                .ranges()
+                .iter()
+                .map(|range| (range.start() as u32, range.end() as u32))
+                .collect();
+            //mostly printable ascii, where the class allows it
+            let printable: Vec<(u32, u32)> = ranges
+                .iter()
+                .map(|&(start, end)| (start.max(0x20), end.min(0x7e)))
+                .filter(|(start, end)| start <= end)
+                .collect();
+            let ranges = if printable.is_empty() || random(seed, 4) == 0 {
+                ranges
+            } else {
+                printable
+            };
+            if let Some(code) = sampleranges(&ranges, seed) {
+                out.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+            }
+        }
+        HirKind::Class(Class::Bytes(class)) => {
+            let ranges: Vec<(u32, u32)> = class
+                .ranges()
+                .iter()
+                .map(|range| (range.start() as u32, range.end().min(0x7f) as u32))
+                .filter(|(start, end)| start <= end)
+                .collect();
+            if let Some(code) = sampleranges(&ranges, seed) {
+                out.push(code as u8 as char);
+            }
+        }
+        HirKind::Repetition(repetition) => {
+            let most = repetition.min.saturating_add(maxrepeat);
+            let most = repetition.max.map_or(most, |max| max.min(most));
+            let count = repetition.min as u64 + random(seed, (most - repetition.min) as u64 + 1);
+            for _ in 0..count {
+                samplehir(&repetition.sub, maxrepeat, seed, out);
+            }
+        }
+        HirKind::Capture(capture) => samplehir(&capture.sub, maxrepeat, seed, out),
+        HirKind::Concat(hirs) => {
+            for hir in hirs {
+                samplehir(hir, maxrepeat, seed, out);
+            }
+        }
+        HirKind::Alternation(hirs) => {
+            let hir = &hirs[random(seed, hirs.len() as u64) as usize];
+            samplehir(hir, maxrepeat, seed, out);
+        }
+    }
+}
+
+//a code point out of inclusive ranges, each equally likely
+fn sampleranges(ranges: &[(u32, u32)], seed: &mut u64) -> Option<u32> {
+    let total: u64 = ranges
+        .iter()
+        .map(|(start, end)| (end - start) as u64 + 1)
+        .sum();
+    if total == 0 {
+        return None;
+    }
+    let mut target = random(seed, total);
+    for &(start, end) in ranges {
+        let size = (end - start) as u64 + 1;
+        if target < size {
+            return Some(start + target as u32);
+        }
+        target -= size;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAMMAR: &str = r#"
+expression: term ("+" term)*;
+term: number | name | "(" expression ")";
+number: /[1-9][0-9]{0,3}/;
+name: /[a-z]+/;
+%ignore /[ ]+/;
+"#;
+
+    #[test]
+    fn sentencesparsewiththeirownrule() {
+        let parser = EbnfParser::from_str(GRAMMAR).expect("grammar");
+        let mut generator = parser.generator("expression").expect("generator");
+        assert_eq!(generator.separator, " ");
+        for _ in 0..200 {
+            let sentence = generator.generate();
+            assert!(parser.parse(&sentence, "expression").is_ok(), "{sentence}");
+        }
+    }
+
+    #[test]
+    fn theseedrepeatsthesentences() {
+        let parser = EbnfParser::from_str(GRAMMAR).expect("grammar");
+        let mut first = parser.generator("expression").expect("generator");
+        let mut second = parser.generator("expression").expect("generator");
+        first.seed = 7;
+        second.seed = 7;
+        for _ in 0..20 {
+            assert_eq!(first.generate(), second.generate());
+        }
+    }
+
+    #[test]
+    fn zeroweightsareneverpicked() {
+        let parser = EbnfParser::from_str(GRAMMAR).expect("grammar");
+        let mut generator = parser.generator("expression").expect("generator");
+        generator.weights.insert("name".to_owned(), 0);
+        generator.maxdepth = 100;
+        generator.maxrepeat = 0;
+        for _ in 0..50 {
+            let sentence = generator.generate();
+            assert!(
+                !sentence.contains(|c: char| c.is_ascii_lowercase()),
+                "{sentence}"
+            );
+        }
+    }
+
+    #[test]
+    fn pastthedepthlimitonlytheshortestchoicesaretaken() {
+        let parser = EbnfParser::from_str(GRAMMAR).expect("grammar");
+        let mut generator = parser.generator("expression").expect("generator");
+        generator.maxdepth = 0;
+        for _ in 0..50 {
+            let sentence = generator.generate();
+            assert!(
+                sentence.chars().all(|c| c.is_ascii_alphanumeric()),
+                "{sentence}"
+            );
+        }
+    }
+
+    #[test]
+    fn unknownrulesareerrors() {
+        let parser = EbnfParser::from_str(GRAMMAR).expect("grammar");
+        let error = parser.generator("statement").err().expect("error");
+        assert!(matches!(error.errtype, ParseCodeErrorType::UnknownRule));
+        let parser = EbnfParser::from_str("start: other;").expect("grammar");
+        assert!(parser.generator("start").is_err());
+    }
+}
@@ -0,0 +1,1082 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::parser::EbnfParser;
+use crate::table::choose;
+use crate::testcase::parsetest;
+
+#[derive(Clone, PartialEq)]
+pub enum EbnfStatement<'a> {
+    StringTerminal {
+        string: &'a str,
+    },
+    RegexTerminal {
+        string: &'a str,
+    },
+    DefinedRule {
+        rulename: &'a str,
+    },
+    Concatenation {
+        rules: Vec<EbnfStatement<'a>>,
+    },
+    Optional {
+        rule: Box<EbnfStatement<'a>>,
+    },
+    OneOrMore {
+        rule: Box<EbnfStatement<'a>>,
+    },
+    ZeroOrMore {
+        rule: Box<EbnfStatement<'a>>,
+    },
+    /*Repetition {
+        rule: Box<EbnfStatement<'a>>,
+        minamount: i32,
+        maxamount: i32,
+    },*/
+    Or {
+        left: Box<EbnfStatement<'a>>,
+        right: Box<EbnfStatement<'a>>,
+    },
+    //lookahead predicates, they succeed or fail without consuming input
+    And {
+        rule: Box<EbnfStatement<'a>>,
+    },
+    Not {
+        rule: Box<EbnfStatement<'a>>,
+    },
+    Empty,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EbnfAssociativity {
+    Left,
+    Right,
+    Nonassoc,
+}
+
+//categories of %highlight, see highlight.rs
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum EbnfHighlight {
+    Keyword,
+    Number,
+    Identifier,
+    Operator,
+    Comment,
+    String,
+    Type,
+}
+
+pub struct ParseEbnfError {
+    pub line: i32,
+    pub column: i32,
+    pub errtype: ParseEbnfErrorType,
+}
+
+pub enum ParseEbnfErrorType {
+    UnclosedString,
+    UnclosedParen,
+    UnexpectedCharacter(char),
+    EmptyRule,
+    UnclosedRule,
+    UnknownDirective(String),
+    UnknownHighlight(String),
+    UnclosedBrace,
+    UnknownTemplate(String),
+    TemplateArguments {
+        template: String,
+        expected: usize,
+        found: usize,
+    },
+    RecursiveTemplate(String),
+}
+
+impl fmt::Display for ParseEbnfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let errtype = &self.errtype;
+        let line = self.line;
+        let column = self.column;
+        match errtype {
+            ParseEbnfErrorType::UnclosedString => {
+                write!(
+                    f,
+                    "Unclosed String, expected '\"' at line {line}, column {column}!"
+                )
+            }
+            ParseEbnfErrorType::UnexpectedCharacter(character) => {
+                write!(
+                    f,
+                    "Unexpected '{character}' at line {line}, column {column}!"
+                )
+            }
+            ParseEbnfErrorType::EmptyRule => {
+                write!(f, "Empty rule in line {line}, column {column}!")
+            }
+            ParseEbnfErrorType::UnclosedParen => {
+                write!(
+                    f,
+                    "Unclosed Parentheses, expected ')' at line {line}, column {column}"
+                )
+            }
+            ParseEbnfErrorType::UnclosedRule => {
+                write!(
+                    f,
+                    "Unclosed Rule, expected ';' at line {line}, column {column}"
+                )
+            }
+            ParseEbnfErrorType::UnknownDirective(directive) => {
+                write!(
+                    f,
+                    "Unknown directive '%{directive}' at line {line}, column {column}"
+                )
+            }
+            ParseEbnfErrorType::UnknownHighlight(category) => {
+                write!(
+                    f,
+                    "Unknown highlight category '{category}' at line {line}, column {column}"
+                )
+            }
+            ParseEbnfErrorType::UnclosedBrace => {
+                write!(
+                    f,
+                    "Unclosed Braces, expected '}}' at line {line}, column {column}"
+                )
+            }
+            ParseEbnfErrorType::UnknownTemplate(template) => {
+                write!(
+                    f,
+                    "Unknown template '{template}' at line {line}, column {column}"
+                )
+            }
+            ParseEbnfErrorType::TemplateArguments {
+                template,
+                expected,
+                found,
+            } => {
+                write!(
+                    f,
+                    "Template '{template}' takes {expected} arguments, found {found} at line {line}, column {column}"
+                )
+            }
+            ParseEbnfErrorType::RecursiveTemplate(template) => {
+                write!(
+                    f,
+                    "Template '{template}' uses itself at line {line}, column {column}"
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ParseEbnfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+impl<'a> fmt::Display for EbnfStatement<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EbnfStatement::StringTerminal { string } => write!(f, "\"{string}\""),
+            EbnfStatement::RegexTerminal { string } => write!(f, "/{string}/"),
+            EbnfStatement::DefinedRule { rulename } => write!(f, "{rulename}"),
+            EbnfStatement::Concatenation { rules } => {
+                write!(
+                    f,
+                    "({})",
+                    rules
+                        .iter()
+                        .map(|rule| rule.to_string())
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                )
+            }
+            EbnfStatement::Optional { rule } => write!(f, "{}?", rule),
+            /*EbnfStatement::Repetition {
+                rule,
+                minamount,
+                maxamount,
+            } => write!(f, "{rule}{{{minamount},{maxamount}}}"),*/
+            EbnfStatement::Or { left, right } => write!(f, "{left} | {right}"),
+            EbnfStatement::OneOrMore { rule } => write!(f, "{rule}+"),
+            EbnfStatement::ZeroOrMore { rule } => write!(f, "{rule}*"),
+            EbnfStatement::And { rule } => write!(f, "&{rule}"),
+            EbnfStatement::Not { rule } => write!(f, "!{rule}"),
+            EbnfStatement::Empty => write!(f, "§"),
+        }
+    }
+}
+
+impl<'a> EbnfStatement<'a> {
+    pub fn new(s: &'a str, startline: i32, startcolumn: i32) -> Result<Self, ParseEbnfError> {
+        Self::scan(s, startline, startcolumn, false).map(|(statement, _)| statement)
+    }
+
+    //reads a body up to the first ';' outside of strings, regexes, brackets and template
+    //arguments, the rest after the first '|' is read again as its right side. `closed` bodies
+    //are rules that have to end with a ';', returns the offset of it
+    fn scan(
+        s: &'a str,
+        startline: i32,
+        startcolumn: i32,
+        closed: bool,
+    ) -> Result<(Self, Option<usize>), ParseEbnfError> {
+        let mut parsedrules: Vec<EbnfStatement> = Vec::new();
+        let mut matchstart = 0;
+        let mut currentcolumn: i32 = startcolumn;
+        let mut currentline: i32 = startline;
+        let mut stringparse = false;
+        let mut escaped = false;
+        let mut bracketparse = false;
+        let mut bracketlevel = 0;
+        let mut regexparse = false;
+        //the closing brace of a template use, its arguments are part of the name
+        let mut braceend: Option<usize> = None;
+        //prefix predicates by the index of the statement they apply to
+        let mut predicates: Vec<(usize, char)> = Vec::new();
+
+        let mut end = None;
+
+        for (i, char) in s.char_indices() {
+            let inbraces = braceend.is_some_and(|end| i <= end);
+            let plain = !(escaped || stringparse || bracketparse || regexparse || inbraces);
+            //rule names directly followed by an operator
+            if plain && ['?', '*', '+', '|', ';', '&', '!'].contains(&char) && matchstart < i {
+                parsedrules.push(namedstatement(&s[matchstart..i]));
+                matchstart = i;
+            }
+            //parsing strings
+            if escaped {
+                escaped = false;
+            } else if stringparse {
+                if char == '"' {
+                    stringparse = false;
+                    parsedrules.push(EbnfStatement::StringTerminal {
+                        string: &s[matchstart + 1..i],
+                    });
+                    matchstart = i + 1;
+                } else if char == '\n' {
+                    return Err(ParseEbnfError {
+                        line: currentline,
+                        column: currentcolumn,
+                        errtype: ParseEbnfErrorType::UnexpectedCharacter(char.to_owned()),
+                    });
+                } else if char == '\\' {
+                    escaped = true;
+                }
+            }
+            //parsing brackets
+            else if bracketparse {
+                if char == '(' {
+                    bracketlevel += 1;
+                }
+                if char == ')' {
+                    bracketlevel -= 1;
+                    if bracketlevel == 0 {
+                        bracketparse = false;
+                        parsedrules.push(EbnfStatement::new(
+                            &s[matchstart + 1..i],
+                            currentline,
+                            currentcolumn,
+                        )?);
+                        matchstart = i + 1;
+                    }
+                }
+            }
+            //parsing regex
+            else if regexparse {
+                if char == '/' {
+                    regexparse = false;
+                    parsedrules.push(EbnfStatement::RegexTerminal {
+                        string: &s[matchstart + 1..i],
+                    });
+                    matchstart = i + 1;
+                }
+            }
+            //template arguments are read when the templates are expanded
+            else if !inbraces {
+                //parsing regular rule names, split by space, newline or tab
+                if [' ', '\n', '\t'].contains(&char) {
+                    if matchstart != i {
+                        parsedrules.push(namedstatement(&s[matchstart..i]));
+                    }
+                    matchstart = i + 1;
+                } else if char == '"' {
+                    stringparse = true;
+                } else if char == '(' {
+                    bracketparse = true;
+                    bracketlevel = 1;
+                } else if char == '/' {
+                    regexparse = true;
+                } else if char == '{' {
+                    braceend = Some(i + closingbrace(&s[i..], currentline, currentcolumn)?);
+                } else if ['?', '*', '+'].contains(&char) {
+                    let Some(lastrule) = parsedrules.pop() else {
+                        return Err(ParseEbnfError {
+                            line: currentline,
+                            column: currentcolumn,
+                            errtype: ParseEbnfErrorType::EmptyRule,
+                        });
+                    };
+                    let rule = Box::new(lastrule);
+                    parsedrules.push(match char {
+                        '?' => EbnfStatement::Optional { rule },
+                        '*' => EbnfStatement::ZeroOrMore { rule },
+                        _ => EbnfStatement::OneOrMore { rule },
+                    });
+                    matchstart = i + 1;
+                } else if char == '&' || char == '!' {
+                    predicates.push((parsedrules.len(), char));
+                    matchstart = i + 1;
+                } else if char == '|' {
+                    applypredicates(
+                        &mut parsedrules,
+                        &mut predicates,
+                        currentline,
+                        currentcolumn,
+                    )?;
+                    let left: Box<EbnfStatement> = match parsedrules.len() {
+                        0 => {
+                            return Err(ParseEbnfError {
+                                line: currentline,
+                                column: currentcolumn,
+                                errtype: ParseEbnfErrorType::EmptyRule,
+                            });
+                        }
+                        1 => Box::new(parsedrules.pop().expect("")),
+                        _ => Box::new(EbnfStatement::Concatenation {
+                            rules: std::mem::take(&mut parsedrules),
+                        }),
+                    };
+                    let (right, end) =
+                        Self::scan(&s[i + 1..], currentline, currentcolumn + 1, closed)?;
+                    let right = Box::new(right);
+                    return Ok((
+                        EbnfStatement::Or { left, right },
+                        end.map(|end| i + 1 + end),
+                    ));
+                } else if char == ';' {
+                    end = Some(i);
+                    break;
+                }
+            }
+
+            currentcolumn += 1;
+            if char == '\n' {
+                currentcolumn = 0;
+                currentline += 1;
+            }
+        }
+
+        if closed && end.is_none() {
+            return Err(ParseEbnfError {
+                line: currentline,
+                column: currentcolumn,
+                errtype: ParseEbnfErrorType::UnclosedRule,
+            });
+        }
+
+        if stringparse {
+            return Err(ParseEbnfError {
+                line: currentline,
+                column: currentcolumn,
+                errtype: ParseEbnfErrorType::UnclosedString,
+            });
+        }
+
+        if bracketparse {
+            return Err(ParseEbnfError {
+                line: currentline,
+                column: currentcolumn,
+                errtype: ParseEbnfErrorType::UnclosedParen,
+            });
+        }
+
+        let length = end.unwrap_or(s.len());
+        if matchstart < length {
+            parsedrules.push(namedstatement(&s[matchstart..length]));
+        }
+        applypredicates(
+            &mut parsedrules,
+            &mut predicates,
+            currentline,
+            currentcolumn,
+        )?;
+
+        if parsedrules.len() == 1 {
+            let rule = parsedrules.pop();
+            Ok((rule.expect("This is not supposed to happen"), end))
+        } else if !parsedrules.is_empty() {
+            Ok((EbnfStatement::Concatenation { rules: parsedrules }, end))
+        } else {
+            Err(ParseEbnfError {
+                line: currentline,
+                column: currentcolumn,
+                errtype: ParseEbnfErrorType::EmptyRule,
+            })
+        }
+    }
+}
+
+impl<'a> EbnfParser<'a> {
+    //not FromStr, the parsed rules borrow from the grammar text
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &'a str) -> Result<Self, ParseEbnfError> {
+        let mut parsename = false;
+        let mut parsebody = false;
+        let mut parsedirective = false;
+        let mut matchstart = 0;
+        let mut currentline = 0;
+        let mut currentcolumn = 0;
+        let mut rulename = "";
+        let mut parser = EbnfParser {
+            rules: HashMap::new(),
+            ignore: Vec::new(),
+            sync: Vec::new(),
+            precedence: Vec::new(),
+            regexes: RefCell::new(HashMap::new()),
+            observer: None,
+            inline: HashSet::new(),
+            keeptokens: HashSet::new(),
+            aliases: HashMap::new(),
+            tests: Vec::new(),
+            highlights: Vec::new(),
+            templates: HashMap::new(),
+            reserved: Vec::new(),
+        };
+        //'?' or '!' in front of the rule name being read
+        let mut ruleprefix = None;
+
+        //the rule body, read at the ':', and the offset of its ';'
+        let mut body: Option<EbnfStatement> = None;
+        let mut bodyend = 0;
+        let mut stringparse = false;
+        let mut escaped = false;
+        let mut regexparse = false;
+        let mut braceend: Option<usize> = None;
+
+        for (i, char) in s.char_indices() {
+            let inbraces = braceend.is_some_and(|end| i <= end);
+            if parsename {
+                if char == '{' && !inbraces {
+                    braceend = Some(i + closingbrace(&s[i..], currentline, currentcolumn)?);
+                } else if char == ':' && !inbraces {
+                    rulename = &s[matchstart..i];
+                    match ruleprefix.take() {
+                        Some('?') => parser.inline.insert(rulename.to_owned()),
+                        Some(_) => parser.keeptokens.insert(rulename.to_owned()),
+                        None => false,
+                    };
+                    let (statement, end) =
+                        EbnfStatement::scan(&s[i + 1..], currentline, currentcolumn + 1, true)?;
+                    parsename = false;
+                    parsebody = true;
+                    body = Some(statement);
+                    bodyend = i + 1 + end.expect("closed bodies end at a ';'");
+                } else if !(inbraces || char.is_alphanumeric() || char == '_') {
+                    return Err(ParseEbnfError {
+                        line: currentline,
+                        column: currentcolumn,
+                        errtype: ParseEbnfErrorType::UnexpectedCharacter(char),
+                    });
+                }
+                currentcolumn += 1;
+            } else if parsedirective {
+                //directives end at ';' or the end of the line, unless inside a string or regex
+                if escaped {
+                    escaped = false;
+                } else if stringparse || regexparse {
+                    if char == '\\' {
+                        escaped = true;
+                    } else if stringparse && char == '"' {
+                        stringparse = false;
+                    } else if regexparse && char == '/' {
+                        regexparse = false;
+                    }
+                } else if char == '"' {
+                    stringparse = true;
+                } else if char == '/' {
+                    regexparse = true;
+                } else if char == ';' || char == '\n' {
+                    parsedirective = false;
+                    parsedirectivebody(&s[matchstart..i], currentline, currentcolumn, &mut parser)?;
+                }
+                currentcolumn += 1;
+            } else if parsebody {
+                //the body was read at the ':', its lines are only counted up to the ';'
+                currentcolumn += 1;
+                if i == bodyend {
+                    parsebody = false;
+                    let mut parserule = body.take().expect("read at the ':'");
+                    //`name{parameters}: body;` defines a template instead of a rule
+                    if let Some((template, parameters)) = templateuse(rulename) {
+                        parser
+                            .templates
+                            .insert(template.to_owned(), (parameters, parserule));
+                    } else {
+                        let mut alternative = &mut parserule;
+                        let mut index = 0;
+                        loop {
+                            let (current, next) = match alternative {
+                                EbnfStatement::Or { left, right } => (left.as_mut(), Some(right)),
+                                other => (other, None),
+                            };
+                            if let Some(alias) = takealias(current, currentline, currentcolumn)? {
+                                let key = (rulename.to_owned(), index);
+                                parser.aliases.insert(key, alias.to_owned());
+                            }
+                            match next {
+                                Some(right) => alternative = right.as_mut(),
+                                None => break,
+                            }
+                            index += 1;
+                        }
+                        parser.rules.insert(rulename.to_owned(), parserule);
+                    }
+                }
+            } else {
+                if char.is_alphanumeric() || char == '_' {
+                    matchstart = i;
+                    parsename = true;
+                } else if char == '?' || char == '!' {
+                    ruleprefix = Some(char);
+                } else if char == '%' {
+                    matchstart = i + 1;
+                    parsedirective = true;
+                }
+                currentcolumn += 1;
+            }
+            if char == '\n' {
+                currentline += 1;
+                currentcolumn = 0;
+            }
+        }
+        if parsename {
+            return Err(ParseEbnfError {
+                line: currentline,
+                column: currentcolumn,
+                errtype: ParseEbnfErrorType::EmptyRule,
+            });
+        }
+
+        if parsedirective {
+            parsedirectivebody(&s[matchstart..], currentline, currentcolumn, &mut parser)?;
+        }
+
+        //templates can be used before they are defined, so they are expanded at the end
+        let templates = std::mem::take(&mut parser.templates);
+        let statements = parser
+            .rules
+            .values_mut()
+            .chain(&mut parser.ignore)
+            .chain(&mut parser.sync)
+            .chain(
+                parser
+                    .precedence
+                    .iter_mut()
+                    .flat_map(|(_, statements)| statements),
+            )
+            .chain(
+                parser
+                    .highlights
+                    .iter_mut()
+                    .flat_map(|(_, statements)| statements),
+            );
+        for statement in statements {
+            expandtemplates(statement, &templates, s, &mut Vec::new())?;
+        }
+        parser.templates = templates;
+
+        Ok(parser)
+    }
+}
+
+fn parsedirectivebody<'a>(
+    directive: &'a str,
+    line: i32,
+    column: i32,
+    parser: &mut EbnfParser<'a>,
+) -> Result<(), ParseEbnfError> {
+    let (name, argument) = directive.split_once([' ', '\t']).unwrap_or((directive, ""));
+    match name {
+        "ignore" => {
+            parser
+                .ignore
+                .push(EbnfStatement::new(argument.trim(), line, column)?);
+            Ok(())
+        }
+        //tokens the parser skips ahead to after a syntax error
+        "sync" => {
+            parser.sync.extend(statementlist(argument, line, column)?);
+            Ok(())
+        }
+        //operator precedence for LALR conflicts, later declarations bind tighter
+        "left" | "right" | "nonassoc" => {
+            let associativity = match name {
+                "left" => EbnfAssociativity::Left,
+                "right" => EbnfAssociativity::Right,
+                _ => EbnfAssociativity::Nonassoc,
+            };
+            parser
+                .precedence
+                .push((associativity, statementlist(argument, line, column)?));
+            Ok(())
+        }
+        //words identifiers cannot be, string terminals of them only match whole words
+        "reserved" => {
+            for statement in statementlist(argument, line, column)? {
+                let EbnfStatement::StringTerminal { string } = statement else {
+                    let found = statement.to_string().chars().next().unwrap_or(';');
+                    return Err(ParseEbnfError {
+                        line,
+                        column,
+                        errtype: ParseEbnfErrorType::UnexpectedCharacter(found),
+                    });
+                };
+                parser.reserved.push(string);
+            }
+            Ok(())
+        }
+        //examples the grammar has to accept or reject, see runtests
+        "test" | "test-fail" => {
+            let test = parsetest(argument, name == "test-fail", line, column)?;
+            parser.tests.push(test);
+            Ok(())
+        }
+        //the category of the terminals and rules that follow it
+        "highlight" => {
+            let (category, statements) = argument
+                .trim()
+                .split_once([' ', '\t'])
+                .unwrap_or((argument.trim(), ""));
+            let highlight = match category {
+                "keyword" => EbnfHighlight::Keyword,
+                "number" => EbnfHighlight::Number,
+                "identifier" => EbnfHighlight::Identifier,
+                "operator" => EbnfHighlight::Operator,
+                "comment" => EbnfHighlight::Comment,
+                "string" => EbnfHighlight::String,
+                "type" => EbnfHighlight::Type,
+                _ => {
+                    return Err(ParseEbnfError {
+                        line,
+                        column,
+                        errtype: ParseEbnfErrorType::UnknownHighlight(category.to_owned()),
+                    });
+                }
+            };
+            parser
+                .highlights
+                .push((highlight, statementlist(statements, line, column)?));
+            Ok(())
+        }
+        _ => Err(ParseEbnfError {
+            line,
+            column,
+            errtype: ParseEbnfErrorType::UnknownDirective(name.to_owned()),
+        }),
+    }
+}
+
+//the offset of the '}' closing the '{' the text starts with, strings and regexes in template
+//arguments may contain braces and commas
+fn closingbrace(text: &str, line: i32, column: i32) -> Result<usize, ParseEbnfError> {
+    let mut level = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, char) in text.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if let Some(end) = quote {
+            if char == '\\' {
+                escaped = true;
+            } else if char == end {
+                quote = None;
+            }
+        } else if char == '"' || char == '/' {
+            quote = Some(char);
+        } else if char == '{' || char == '(' {
+            level += 1;
+        } else if char == '}' || char == ')' {
+            level -= 1;
+            if level == 0 {
+                return Ok(i);
+            }
+        }
+    }
+    Err(ParseEbnfError {
+        line,
+        column,
+        errtype: ParseEbnfErrorType::UnclosedBrace,
+    })
+}
+
+//splits `name{a, b}` into the name and its arguments, None for plain rule names
+fn templateuse(rulename: &str) -> Option<(&str, Vec<&str>)> {
+    let (name, rest) = rulename.split_once('{')?;
+    let arguments = rest.strip_suffix('}')?;
+    let mut out = Vec::new();
+    let mut start = 0;
+    let mut level = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, char) in arguments.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if let Some(end) = quote {
+            if char == '\\' {
+                escaped = true;
+            } else if char == end {
+                quote = None;
+            }
+        } else if char == '"' || char == '/' {
+            quote = Some(char);
+        } else if char == '{' || char == '(' {
+            level += 1;
+        } else if char == '}' || char == ')' {
+            level -= 1;
+        } else if char == ',' && level == 0 {
+            out.push(arguments[start..i].trim());
+            start = i + 1;
+        }
+    }
+    out.push(arguments[start..].trim());
+    Some((name, out))
+}
+
+//line and column of a part of the grammar text, for errors found after reading all of it
+fn textposition(s: &str, part: &str) -> (i32, i32) {
+    let offset = (part.as_ptr() as usize)
+        .saturating_sub(s.as_ptr() as usize)
+        .min(s.len());
+    let before = &s[..offset];
+    let linestart = before.rfind('\n').map_or(0, |newline| newline + 1);
+    (
+        before.matches('\n').count() as i32,
+        before[linestart..].chars().count() as i32,
+    )
+}
+
+//replaces the template uses in a statement by the template body with the arguments for its
+//parameters, `active` are the templates being expanded
+fn expandtemplates<'a>(
+    statement: &mut EbnfStatement<'a>,
+    templates: &HashMap<String, (Vec<&'a str>, EbnfStatement<'a>)>,
+    s: &'a str,
+    active: &mut Vec<&'a str>,
+) -> Result<(), ParseEbnfError> {
+    match statement {
+        EbnfStatement::DefinedRule { rulename } => {
+            let Some((template, arguments)) = templateuse(rulename) else {
+                return Ok(());
+            };
+            let (line, column) = textposition(s, rulename);
+            let error = |errtype| ParseEbnfError {
+                line,
+                column,
+                errtype,
+            };
+            let Some((parameters, body)) = templates.get(template) else {
+                return Err(error(ParseEbnfErrorType::UnknownTemplate(
+                    template.to_owned(),
+                )));
+            };
+            if parameters.len() != arguments.len() {
+                return Err(error(ParseEbnfErrorType::TemplateArguments {
+                    template: template.to_owned(),
+                    expected: parameters.len(),
+                    found: arguments.len(),
+                }));
+            }
+            if active.contains(&template) {
+                return Err(error(ParseEbnfErrorType::RecursiveTemplate(
+                    template.to_owned(),
+                )));
+            }
+            let mut values = Vec::new();
+            for argument in arguments {
+                let (line, column) = textposition(s, argument);
+                let mut value = EbnfStatement::new(argument, line, column)?;
+                expandtemplates(&mut value, templates, s, active)?;
+                values.push(value);
+            }
+            //the body is expanded before the arguments are put in, they are already expanded
+            let mut expanded = body.clone();
+            active.push(template);
+            expandtemplates(&mut expanded, templates, s, active)?;
+            active.pop();
+            substituteparameters(&mut expanded, parameters, &values);
+            *statement = expanded;
+        }
+        EbnfStatement::Concatenation { rules } => {
+            for rule in rules {
+                expandtemplates(rule, templates, s, active)?;
+            }
+        }
+        EbnfStatement::Or { left, right } => {
+            expandtemplates(left, templates, s, active)?;
+            expandtemplates(right, templates, s, active)?;
+        }
+        EbnfStatement::Optional { rule }
+        | EbnfStatement::OneOrMore { rule }
+        | EbnfStatement::ZeroOrMore { rule }
+        | EbnfStatement::And { rule }
+        | EbnfStatement::Not { rule } => expandtemplates(rule, templates, s, active)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+fn substituteparameters<'a>(
+    statement: &mut EbnfStatement<'a>,
+    parameters: &[&str],
+    values: &[EbnfStatement<'a>],
+) {
+    match statement {
+        EbnfStatement::DefinedRule { rulename } => {
+            if let Some(index) = parameters
+                .iter()
+                .position(|parameter| parameter == rulename)
+            {
+                *statement = values[index].clone();
+            }
+        }
+        EbnfStatement::Concatenation { rules } => {
+            for rule in rules {
+                substituteparameters(rule, parameters, values);
+            }
+        }
+        EbnfStatement::Or { left, right } => {
+            substituteparameters(left, parameters, values);
+            substituteparameters(right, parameters, values);
+        }
+        EbnfStatement::Optional { rule }
+        | EbnfStatement::OneOrMore { rule }
+        | EbnfStatement::ZeroOrMore { rule }
+        | EbnfStatement::And { rule }
+        | EbnfStatement::Not { rule } => substituteparameters(rule, parameters, values),
+        _ => {}
+    }
+}
+
+//a name in a rule body, \p{..} and \P{..} are Unicode classes matching one character
+fn namedstatement(name: &str) -> EbnfStatement<'_> {
+    if (name.starts_with("\\p{") || name.starts_with("\\P{")) && name.ends_with('}') {
+        EbnfStatement::RegexTerminal { string: name }
+    } else {
+        EbnfStatement::DefinedRule { rulename: name }
+    }
+}
+
+//wraps the statements preceded by '&' or '!', innermost first so that !&a is !(&a)
+fn applypredicates(
+    parsedrules: &mut [EbnfStatement],
+    predicates: &mut Vec<(usize, char)>,
+    line: i32,
+    column: i32,
+) -> Result<(), ParseEbnfError> {
+    while let Some((index, predicate)) = predicates.pop() {
+        let Some(statement) = parsedrules.get_mut(index) else {
+            return Err(ParseEbnfError {
+                line,
+                column,
+                errtype: ParseEbnfErrorType::EmptyRule,
+            });
+        };
+        let rule = Box::new(std::mem::replace(statement, EbnfStatement::Empty));
+        *statement = match predicate {
+            '&' => EbnfStatement::And { rule },
+            _ => EbnfStatement::Not { rule },
+        };
+    }
+    Ok(())
+}
+
+//takes `-> alias` off the end of a top-level alternative, it names the tree node of the rule
+//when that alternative matched
+fn takealias<'a>(
+    alternative: &mut EbnfStatement<'a>,
+    line: i32,
+    column: i32,
+) -> Result<Option<&'a str>, ParseEbnfError> {
+    let EbnfStatement::Concatenation { rules } = alternative else {
+        return Ok(None);
+    };
+    let alias = match rules.as_slice() {
+        [
+            ..,
+            EbnfStatement::DefinedRule { rulename: "->" },
+            EbnfStatement::DefinedRule { rulename },
+        ] => {
+            let alias = *rulename;
+            rules.truncate(rules.len() - 2);
+            alias
+        }
+        [.., EbnfStatement::DefinedRule { rulename }]
+            if rulename.len() > 2 && rulename.starts_with("->") =>
+        {
+            let alias = &rulename[2..];
+            rules.pop();
+            alias
+        }
+        _ => return Ok(None),
+    };
+    //an alternative that is a group of alternatives itself stays a group
+    match rules.len() {
+        0 => Err(ParseEbnfError {
+            line,
+            column,
+            errtype: ParseEbnfErrorType::EmptyRule,
+        }),
+        1 if !matches!(rules[0], EbnfStatement::Or { .. }) => {
+            *alternative = rules.pop().expect("one rule");
+            Ok(Some(alias))
+        }
+        _ => Ok(Some(alias)),
+    }
+}
+
+//like Display, with parentheses wherever they are needed to read it back
+pub(crate) fn written(statement: &EbnfStatement, nested: bool) -> String {
+    let grouped = |text: String| if nested { format!("({text})") } else { text };
+    match statement {
+        EbnfStatement::Concatenation { rules } => grouped(
+            rules
+                .iter()
+                .map(|rule| written(rule, true))
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        EbnfStatement::Or { .. } => {
+            let mut choices = Vec::new();
+            choose(statement, &mut choices);
+            grouped(
+                choices
+                    .iter()
+                    .map(|choice| written(choice, true))
+                    .collect::<Vec<_>>()
+                    .join(" | "),
+            )
+        }
+        EbnfStatement::Optional { rule } => format!("{}?", written(rule, true)),
+        EbnfStatement::ZeroOrMore { rule } => format!("{}*", written(rule, true)),
+        EbnfStatement::OneOrMore { rule } => format!("{}+", written(rule, true)),
+        EbnfStatement::And { rule } => format!("&{}", written(rule, true)),
+        EbnfStatement::Not { rule } => format!("!{}", written(rule, true)),
+        _ => statement.to_string(),
+    }
+}
+
+fn statementlist<'a>(
+    argument: &'a str,
+    line: i32,
+    column: i32,
+) -> Result<Vec<EbnfStatement<'a>>, ParseEbnfError> {
+    Ok(match EbnfStatement::new(argument.trim(), line, column)? {
+        EbnfStatement::Concatenation { rules } => rules,
+        statement => vec![statement],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grammarsmayusenonasciitext() {
+        let parser = EbnfParser::from_str(
+            "grüße: \"grüß\" name \"!\";\nname: /[a-zäöü]+/;\n%ignore /[ ]+/;",
+        )
+        .expect("grammar");
+        let tree = parser
+            .parse("grüß jürgen!", "grüße")
+            .map(|tree| tree.tosexp());
+        assert_eq!(
+            tree.ok().as_deref(),
+            Some(r#"(grüße "grüß" (name "jürgen") "!")"#)
+        );
+    }
+
+    #[test]
+    fn unicodeclassesmatchonecharacter() {
+        let parser =
+            EbnfParser::from_str(r#"word: \p{Lu} \p{Ll}+; other: \P{L};"#).expect("grammar");
+        assert!(parser.parse("Ölbaum", "word").is_ok());
+        assert!(parser.parse("ölbaum", "word").is_err());
+        assert!(parser.parse("7", "other").is_ok());
+        assert!(parser.parse("λ", "other").is_err());
+    }
+
+    #[test]
+    fn alternativescountcolumnsfromthestartoftheirline() {
+        //the string in the last alternative ends at the newline in column 6 of line 2
+        let error = EbnfParser::from_str("a: b\n  | c\n  | \"d\n\";")
+            .err()
+            .expect("unclosed");
+        assert_eq!((error.line, error.column), (2, 6));
+        let error = EbnfParser::from_str("a: ;").err().expect("empty");
+        assert!(matches!(error.errtype, ParseEbnfErrorType::EmptyRule));
+        assert_eq!(error.column, 3);
+    }
+
+    #[test]
+    fn grammarerrorscountcharacters() {
+        //the rule ends after 13 characters, which are 15 bytes
+        let error = EbnfParser::from_str("größe: ( \"x\";")
+            .err()
+            .expect("unclosed");
+        assert_eq!(error.column, 13);
+    }
+
+    #[test]
+    fn templatesareexpandedwheretheyareused() {
+        let parser = EbnfParser::from_str(
+            r#"call: name "(" list{value, ","}? ")";
+list{item, separator}: item (separator item)*;
+value: name | "[" list{value, ";"} "]";
+name: /[a-z]+/;"#,
+        )
+        .expect("grammar");
+        assert_eq!(
+            parser.rules["call"].to_string(),
+            r#"(name "(" (value ("," value)*)? ")")"#
+        );
+        let tree = parser.parse("f(a,[b;c])", "call").map(|tree| tree.tosexp());
+        assert_eq!(
+            tree.ok().as_deref(),
+            Some(concat!(
+                r#"(call (name "f") "(" (value (name "a")) "," "#,
+                r#"(value "[" (value (name "b")) ";" (value (name "c")) "]") ")")"#
+            ))
+        );
+    }
+
+    #[test]
+    fn templateargumentsmaycontainbracesandcommas() {
+        let parser = EbnfParser::from_str(r#"pair: twice{"{,}"} twice{/[a,b]/}; twice{x}: x x;"#)
+            .expect("grammar");
+        assert!(parser.parse("{,}{,}ab", "pair").is_ok());
+    }
+
+    #[test]
+    fn templateerrorspointattheuse() {
+        let error = |grammar| EbnfParser::from_str(grammar).err().expect("error");
+        let unknown = error("start: \"a\";\nother: \"b\" list{\"c\"};");
+        assert_eq!((unknown.line, unknown.column), (1, 11));
+        assert!(
+            matches!(unknown.errtype, ParseEbnfErrorType::UnknownTemplate(ref name) if name == "list")
+        );
+        let arguments = error("start: pair{\"a\"}; pair{a, b}: a b;");
+        assert_eq!(
+            arguments.to_string(),
+            "Template 'pair' takes 2 arguments, found 1 at line 0, column 7"
+        );
+        let recursive = error("start: nest{\"a\"}; nest{x}: \"(\" nest{x} \")\" | x;");
+        assert_eq!(
+            recursive.to_string(),
+            "Template 'nest' uses itself at line 0, column 31"
+        );
+    }
+}
@@ -0,0 +1,402 @@
+use regex::{Regex, escape};
+use std::{collections::HashMap, fmt::Write, rc::Rc};
+
+use crate::grammar::{EbnfHighlight, EbnfStatement};
+use crate::input::EbnfInput;
+use crate::parser::{EbnfParser, ParseCodeError};
+use crate::table::compiled;
+use crate::trace::jsonstring;
+use crate::tree::EbnfTree;
+
+//a highlighted part of the input, by byte offsets
+pub struct EbnfSpan {
+    pub start: usize,
+    pub end: usize,
+    pub highlight: EbnfHighlight,
+}
+
+//the %highlight tags, by how the tagged statement is written
+struct EbnfTags {
+    tags: HashMap<String, EbnfHighlight>,
+    regexes: Vec<(Rc<Regex>, EbnfHighlight)>,
+}
+
+//the order of the TextMate patterns, identical regexes keep the first category
+const PATTERNORDER: [EbnfHighlight; 7] = [
+    EbnfHighlight::Comment,
+    EbnfHighlight::String,
+    EbnfHighlight::Keyword,
+    EbnfHighlight::Number,
+    EbnfHighlight::Operator,
+    EbnfHighlight::Identifier,
+    EbnfHighlight::Type,
+];
+
+impl EbnfHighlight {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EbnfHighlight::Keyword => "keyword",
+            EbnfHighlight::Number => "number",
+            EbnfHighlight::Identifier => "identifier",
+            EbnfHighlight::Operator => "operator",
+            EbnfHighlight::Comment => "comment",
+            EbnfHighlight::String => "string",
+            EbnfHighlight::Type => "type",
+        }
+    }
+
+    //SGR parameters of the terminal color
+    fn ansi(&self) -> &'static str {
+        match self {
+            EbnfHighlight::Keyword => "1;35",
+            EbnfHighlight::Number => "36",
+            EbnfHighlight::Identifier => "34",
+            EbnfHighlight::Operator => "33",
+            EbnfHighlight::Comment => "90",
+            EbnfHighlight::String => "32",
+            EbnfHighlight::Type => "96",
+        }
+    }
+
+    //TextMate scope, the language name is appended
+    fn scope(&self) -> &'static str {
+        match self {
+            EbnfHighlight::Keyword => "keyword.control",
+            EbnfHighlight::Number => "constant.numeric",
+            EbnfHighlight::Identifier => "variable.other",
+            EbnfHighlight::Operator => "keyword.operator",
+            EbnfHighlight::Comment => "comment.line",
+            EbnfHighlight::String => "string.quoted",
+            EbnfHighlight::Type => "entity.name.type",
+        }
+    }
+}
+
+impl EbnfTags {
+    //string terminals by their text, then the tag of the rule the token is directly in, then
+    //regex terminals matching all of it
+    fn token(&self, text: &str, rulename: &str) -> Option<EbnfHighlight> {
+        self.tags
+            .get(&format!("\"{text}\""))
+            .or_else(|| self.tags.get(rulename))
+            .copied()
+            .or_else(|| {
+                self.regexes
+                    .iter()
+                    .find(|(regex, _)| {
+                        regex.find(text).map(|found| found.end()) == Some(text.len())
+                    })
+                    .map(|(_, highlight)| *highlight)
+            })
+    }
+
+    //the tokens and skipped errors in order, errors are never highlighted
+    fn leaves(
+        &self,
+        tree: &EbnfTree,
+        rulename: &str,
+        out: &mut Vec<(usize, usize, Option<EbnfHighlight>)>,
+    ) {
+        match tree {
+            EbnfTree::Rule { name, children, .. } => {
+                for child in children {
+                    self.leaves(child, name, out);
+                }
+            }
+            EbnfTree::Token {
+                text, start, end, ..
+            } => out.push((*start, *end, self.token(text, rulename))),
+            EbnfTree::Error { start, end, .. } => out.push((*start, *end, None)),
+        }
+    }
+}
+
+impl<'a> EbnfParser<'a> {
+    fn tags(&self) -> EbnfTags {
+        let mut tags = EbnfTags {
+            tags: HashMap::new(),
+            regexes: Vec::new(),
+        };
+        for (highlight, statements) in &self.highlights {
+            for statement in statements {
+                tags.tags.entry(statement.to_string()).or_insert(*highlight);
+                if let EbnfStatement::RegexTerminal { string } = statement
+                    && let Some(regex) = compiled(string, &self.regexes)
+                {
+                    tags.regexes.push((regex, *highlight));
+                }
+            }
+        }
+        tags
+    }
+
+    //the highlighted parts of the input in order, including ignored input like comments, the
+    //input is parsed with recovery so that broken code is highlighted too
+    pub fn highlight(&self, input: &str, startrule: &str) -> Result<Vec<EbnfSpan>, ParseCodeError> {
+        let (tree, _) = self.parsewithrecovery(input, startrule)?;
+        let tags = self.tags();
+        let mut leaves = Vec::new();
+        tags.leaves(&tree, startrule, &mut leaves);
+        leaves.push((input.len(), input.len(), None));
+
+        let text = EbnfInput::new(input);
+        let mut spans = Vec::new();
+        let mut position = 0;
+        for (start, end, highlight) in leaves {
+            //ignored input in front of the token
+            'skipping: while position < start {
+                for statement in &self.ignore {
+                    if let Some(length) = self.matchgreedy(statement, &text, position as i64)?
+                        && length > 0
+                    {
+                        let end = (position + length as usize).min(start);
+                        if let Some(&highlight) = tags.tags.get(&statement.to_string()) {
+                            spans.push(EbnfSpan {
+                                start: position,
+                                end,
+                                highlight,
+                            });
+                        }
+                        position = end;
+                        continue 'skipping;
+                    }
+                }
+                position += input[position..].chars().next().map_or(1, char::len_utf8);
+            }
+            if let Some(highlight) = highlight
+                && start < end
+            {
+                spans.push(EbnfSpan {
+                    start,
+                    end,
+                    highlight,
+                });
+            }
+            position = position.max(end);
+        }
+        Ok(spans)
+    }
+
+    //the input with ANSI colors for terminals
+    pub fn highlightansi(&self, input: &str, startrule: &str) -> Result<String, ParseCodeError> {
+        let mut out = String::new();
+        let mut position = 0;
+        for span in self.highlight(input, startrule)? {
+            out += &input[position..span.start];
+            let _ = write!(
+                out,
+                "\x1b[{}m{}\x1b[0m",
+                span.highlight.ansi(),
+                &input[span.start..span.end]
+            );
+            position = span.end;
+        }
+        out += &input[position..];
+        Ok(out)
+    }
+
+    //a <pre> block with a <span> per highlighted part, classed by category name
+    pub fn highlighthtml(&self, input: &str, startrule: &str) -> Result<String, ParseCodeError> {
+        let mut out = String::from("<pre class=\"ebnf\">");
+        let mut position = 0;
+        for span in self.highlight(input, startrule)? {
+            out += &htmlescape(&input[position..span.start]);
+            let _ = write!(
+                out,
+                "<span class=\"{}\">{}</span>",
+                span.highlight.name(),
+                htmlescape(&input[span.start..span.end])
+            );
+            position = span.end;
+        }
+        out += &htmlescape(&input[position..]);
+        out += "</pre>\n";
+        Ok(out)
+    }
+
+    //a TextMate grammar for editors, regexes cannot see the context of a token so rules are
+    //highlighted by the terminals directly in them
+    pub fn tmlanguage(&self, name: &str) -> String {
+        let language = name.to_lowercase();
+        let mut seen = Vec::new();
+        let mut patterns = Vec::new();
+        for category in PATTERNORDER {
+            let mut words = Vec::new();
+            let mut symbols = Vec::new();
+            let mut regexes = Vec::new();
+            for (highlight, statements) in &self.highlights {
+                if *highlight != category {
+                    continue;
+                }
+                for statement in statements {
+                    match statement {
+                        EbnfStatement::DefinedRule { rulename } => {
+                            if let Some(body) = self.rules.get(*rulename) {
+                                terminals(body, &mut words, &mut symbols, &mut regexes);
+                            }
+                        }
+                        _ => terminals(statement, &mut words, &mut symbols, &mut regexes),
+                    }
+                }
+            }
+            symbols.sort_by_key(|symbol: &String| std::cmp::Reverse(symbol.len()));
+            if !words.is_empty() {
+                regexes.insert(0, format!("\\b(?:{})\\b", words.join("|")));
+            }
+            if !symbols.is_empty() {
+                regexes.push(symbols.join("|"));
+            }
+            for regex in regexes {
+                if !seen.contains(&regex) {
+                    patterns.push(format!(
+                        "    {{ \"name\": {}, \"match\": {} }}",
+                        jsonstring(&format!("{}.{language}", category.scope())),
+                        jsonstring(&regex)
+                    ));
+                    seen.push(regex);
+                }
+            }
+        }
+        format!(
+            "{{\n  \"name\": {},\n  \"scopeName\": {},\n  \"patterns\": [\n{}\n  ]\n}}\n",
+            jsonstring(name),
+            jsonstring(&format!("source.{language}")),
+            patterns.join(",\n")
+        )
+    }
+}
+
+//the terminals of a statement without going into other rules, strings made of word
+//characters get word boundaries
+fn terminals(
+    statement: &EbnfStatement,
+    words: &mut Vec<String>,
+    symbols: &mut Vec<String>,
+    regexes: &mut Vec<String>,
+) {
+    let add = |list: &mut Vec<String>, item: String| {
+        if !list.contains(&item) {
+            list.push(item);
+        }
+    };
+    match statement {
+        EbnfStatement::StringTerminal { string: "" } => {}
+        EbnfStatement::StringTerminal { string }
+            if string
+                .chars()
+                .all(|char| char.is_alphanumeric() || char == '_') =>
+        {
+            add(words, escape(string))
+        }
+        EbnfStatement::StringTerminal { string } => add(symbols, escape(string)),
+        EbnfStatement::RegexTerminal { string } => add(regexes, string.to_string()),
+        EbnfStatement::Concatenation { rules } => {
+            for rule in rules {
+                terminals(rule, words, symbols, regexes);
+            }
+        }
+        EbnfStatement::Or { left, right } => {
+            terminals(left, words, symbols, regexes);
+            terminals(right, words, symbols, regexes);
+        }
+        EbnfStatement::Optional { rule }
+        | EbnfStatement::OneOrMore { rule }
+        | EbnfStatement::ZeroOrMore { rule } => terminals(rule, words, symbols, regexes),
+        _ => {}
+    }
+}
+
+fn htmlescape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::ParseEbnfErrorType;
+
+    const GRAMMAR: &str = r#"
+program: statement*;
+statement: "let" name "=" value ";";
+value: number | name | /"[^"]*"/;
+name: /[a-z]+/;
+number: /[0-9]+/;
+%ignore /[ \n]+/;
+%ignore /#[^\n]*/;
+%sync ";";
+%highlight keyword "let";
+%highlight identifier name;
+%highlight number /[0-9]+/;
+%highlight string /"[^"]*"/;
+%highlight operator "=" ";";
+%highlight comment /#[^\n]*/;
+"#;
+
+    #[test]
+    fn htmlwrapseverytaggedtoken() {
+        let parser = EbnfParser::from_str(GRAMMAR).expect("grammar");
+        let html = parser
+            .highlighthtml("let a = \"<b>\"; # set a\nlet b = 12;", "program")
+            .expect("highlight");
+        assert_eq!(
+            html,
+            concat!(
+                "<pre class=\"ebnf\"><span class=\"keyword\">let</span> ",
+                "<span class=\"identifier\">a</span> <span class=\"operator\">=</span> ",
+                "<span class=\"string\">&quot;&lt;b&gt;&quot;</span><span class=\"operator\">;</span> ",
+                "<span class=\"comment\"># set a</span>\n",
+                "<span class=\"keyword\">let</span> <span class=\"identifier\">b</span> ",
+                "<span class=\"operator\">=</span> <span class=\"number\">12</span>",
+                "<span class=\"operator\">;</span></pre>\n",
+            )
+        );
+    }
+
+    #[test]
+    fn brokeninputishighlightedtoo() {
+        let parser = EbnfParser::from_str(GRAMMAR).expect("grammar");
+        let ansi = parser
+            .highlightansi("let = 1; let c = 2;", "program")
+            .expect("highlight");
+        //the skipped error is left uncolored
+        assert_eq!(
+            ansi,
+            concat!(
+                "\x1b[1;35mlet\x1b[0m = 1; \x1b[1;35mlet\x1b[0m \x1b[34mc\x1b[0m ",
+                "\x1b[33m=\x1b[0m \x1b[36m2\x1b[0m\x1b[33m;\x1b[0m",
+            )
+        );
+    }
+
+    #[test]
+    fn tmlanguagehasapatternpercategory() {
+        let parser = EbnfParser::from_str(GRAMMAR).expect("grammar");
+        assert_eq!(
+            parser.tmlanguage("Let"),
+            concat!(
+                "{\n  \"name\": \"Let\",\n  \"scopeName\": \"source.let\",\n  \"patterns\": [\n",
+                "    { \"name\": \"comment.line.let\", \"match\": \"#[^\\\\n]*\" },\n",
+                "    { \"name\": \"string.quoted.let\", \"match\": \"\\\"[^\\\"]*\\\"\" },\n",
+                "    { \"name\": \"keyword.control.let\", \"match\": \"\\\\b(?:let)\\\\b\" },\n",
+                "    { \"name\": \"constant.numeric.let\", \"match\": \"[0-9]+\" },\n",
+                "    { \"name\": \"keyword.operator.let\", \"match\": \"=|;\" },\n",
+                "    { \"name\": \"variable.other.let\", \"match\": \"[a-z]+\" }\n",
+                "  ]\n}\n",
+            )
+        );
+    }
+
+    #[test]
+    fn unknowncategoriesareerrors() {
+        let error = EbnfParser::from_str("start: \"a\";\n%highlight color \"a\";")
+            .err()
+            .expect("error");
+        assert_eq!(error.line, 1);
+        assert!(
+            matches!(error.errtype, ParseEbnfErrorType::UnknownHighlight(ref name) if name == "color")
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use std::ops::Range;
+
+use crate::input::EbnfInput;
+use crate::parser::{EbnfChart, EbnfParser, EbnfSuffix, ParseCodeError, ParseCodeErrorType};
+use crate::tree::EbnfTree;
+
+//the chart of the previous parse with where it was cut, the columns to scan again and the
+//chart after the edit
+type EbnfReuse<'a> = (EbnfChart<'a>, u32, Vec<u32>, EbnfSuffix);
+
+//a parse result that keeps its chart, so that edits to the input can be reparsed cheaply
+pub struct EbnfParse<'a> {
+    pub input: String,
+    pub result: Result<EbnfTree, ParseCodeError>,
+    startrule: String,
+    //None if the parse stopped early, the next edit parses from scratch
+    chart: Option<EbnfChart<'a>>,
+}
+
+impl<'a> EbnfParser<'a> {
+    pub fn parseincremental(&'a self, input: &str, startrule: &str) -> EbnfParse<'a> {
+        self.reparsefrom(input.to_owned(), startrule.to_owned(), None)
+    }
+
+    //applies the edit to the previous input and parses again from the token before it. the
+    //chart columns up to there are reused, and so is the chart after the edit from where the
+    //parse matches it again, which for a statement is usually the next one. the tree is
+    //built from the chart again. an edit that is not within the input or splits a character
+    //keeps the previous input and chart with an InvalidEdit error
+    pub fn reparse(
+        &'a self,
+        previous: EbnfParse<'a>,
+        edit: Range<usize>,
+        replacement: &str,
+    ) -> EbnfParse<'a> {
+        let mut input = previous.input;
+        if edit.start > edit.end
+            || edit.end > input.len()
+            || !input.is_char_boundary(edit.start)
+            || !input.is_char_boundary(edit.end)
+        {
+            let position = (0..=edit.start.min(input.len()))
+                .rev()
+                .find(|&position| input.is_char_boundary(position))
+                .unwrap_or(0);
+            let error = ParseCodeError::at(
+                &input,
+                position as i64,
+                ParseCodeErrorType::InvalidEdit(edit),
+            );
+            return EbnfParse {
+                input,
+                result: Err(error),
+                ..previous
+            };
+        }
+        input.replace_range(edit.clone(), replacement);
+        let chart = previous.chart.and_then(|mut chart| {
+            let edit = edit.start as u32..edit.end as u32;
+            let (cut, rescan, suffix) = chart.reuse(edit, input.len())?;
+            Some((chart, cut, rescan, suffix))
+        });
+        self.reparsefrom(input, previous.startrule, chart)
+    }
+
+    fn reparsefrom(
+        &'a self,
+        input: String,
+        startrule: String,
+        chart: Option<EbnfReuse<'a>>,
+    ) -> EbnfParse<'a> {
+        let (chart, result) = match self.parsechart(&input, &startrule, chart) {
+            Ok((chart, result)) => (Some(chart), result),
+            Err(err) => (None, Err(err)),
+        };
+        EbnfParse {
+            input,
+            result,
+            startrule,
+            chart,
+        }
+    }
+
+    //the outer error is for parses that could not fill the whole chart
+    fn parsechart(
+        &'a self,
+        input: &str,
+        startrule: &str,
+        chart: Option<EbnfReuse<'a>>,
+    ) -> Result<(EbnfChart<'a>, Result<EbnfTree, ParseCodeError>), ParseCodeError> {
+        let input = EbnfInput::new(input);
+        let (rulename, body) = self.startrule(startrule)?;
+        let (mut chart, resume) = match chart {
+            Some((mut chart, cut, rescan, mut suffix)) => {
+                self.rescan(&mut chart, &input, &rescan)?;
+                //once the parse after the edit matches the old chart, the rest is spliced in
+                let end = input.end() as u32;
+                let mut resume = cut;
+                while resume <= end {
+                    if chart.splice(&mut suffix, resume) {
+                        resume = end + 1;
+                        break;
+                    }
+                    self.process(&mut chart, &input, resume)?;
+                    resume += 1;
+                }
+                chart.settle(suffix);
+                (chart, resume)
+            }
+            None => {
+                let chart = self.startchart(&input, body)?;
+                let start = chart.start();
+                (chart, start)
+            }
+        };
+        match self.complete(&mut chart, &input, rulename, resume, false) {
+            Ok((tree, _)) => Ok((chart, Ok(tree))),
+            Err(
+                err @ ParseCodeError {
+                    errtype:
+                        ParseCodeErrorType::UnexpectedToken { .. }
+                        | ParseCodeErrorType::UnexpectedEof { .. },
+                    ..
+                },
+            ) => Ok((chart, Err(err))),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::EbnfStatement;
+    use crate::trace::ParseObserver;
+    use std::{cell::RefCell, rc::Rc};
+
+    const STATEMENTS: &str = r#"
+list: item*;
+item: identifier "=" value? ";" | "if" identifier "{" item* "}" | &"x" identifier "!" ";";
+identifier: !"while" /[a-z]+/;
+value: /[0-9]+/ | identifier | value "+" value;
+%reserved "if";
+%ignore /[ \n]+/;
+%ignore /#[^\n]*/;
+"#;
+
+    struct Scans(usize);
+
+    impl ParseObserver for Scans {
+        fn scanned(&mut self, _terminal: &EbnfStatement, _position: u32, _text: &str) {
+            self.0 += 1;
+        }
+    }
+
+    fn full(parser: &EbnfParser, input: &str) -> Result<String, String> {
+        parser
+            .parse(input, "list")
+            .map(|tree| tree.tosexp())
+            .map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn editsevaluatelookaheadsagain() {
+        let parser =
+            EbnfParser::from_str(r#"start: word; word: !"while" /[a-z]+/;"#).expect("grammar");
+        assert!(parser.parse("while", "start").is_err());
+
+        let parse = parser.parseincremental("whil", "start");
+        assert!(parse.result.is_ok());
+        let parse = parser.reparse(parse, 4..4, "e");
+        assert_eq!(parse.input, "while");
+        assert!(parse.result.is_err());
+
+        let parse = parser.reparse(parse, 4..5, "");
+        assert_eq!(
+            parse.result.map(|tree| tree.tosexp()).ok(),
+            parser.parse("whil", "start").map(|tree| tree.tosexp()).ok()
+        );
+    }
+
+    #[test]
+    fn editsgivethetreeofafullparse() {
+        let parser =
+            EbnfParser::from_str(include_str!("../benches/minicpu.ebnf")).expect("grammar");
+        let source = "counter: int = 0;\n\
+            function f(a: int) -> int {\n    c: int = a * 2;\n    return c;\n}\n\
+            function g(b: int) -> int {\n    if (b > 0) { print(b); }\n    return b;\n}\n\
+            f(1);\n";
+        let mut parse = parser.parseincremental(source, "codebody");
+        //an edit inside a token, one that joins two tokens, one that breaks the input and one
+        //that fixes it again
+        for (edit, replacement) in [
+            ("* 2", "* 27"),
+            ("int = 0", "int= 0"),
+            ("f(1)", "f(1"),
+            ("f(1", "f(1)"),
+        ] {
+            let start = parse.input.find(edit).expect("edited text");
+            parse = parser.reparse(parse, start..start + edit.len(), replacement);
+            assert_eq!(
+                parse.result.as_ref().map(|tree| tree.tosexp()).ok(),
+                parser
+                    .parse(&parse.input, "codebody")
+                    .map(|tree| tree.tosexp())
+                    .ok(),
+                "{}",
+                parse.input
+            );
+        }
+        assert!(parse.result.is_ok());
+    }
+
+    #[test]
+    fn editsreusethechartafterthem() {
+        let mut parser = EbnfParser::from_str(STATEMENTS).expect("grammar");
+        let scans = Rc::new(RefCell::new(Scans(0)));
+        parser.observer = Some(scans.clone());
+        let source = "a = 1;\n".repeat(1000);
+        let parse = parser.parseincremental(&source, "list");
+        assert!(scans.borrow().0 > 1000);
+
+        scans.borrow_mut().0 = 0;
+        let parse = parser.reparse(parse, 11..12, "23 + b");
+        assert!(scans.borrow().0 < 20, "{} scans", scans.borrow().0);
+        assert_eq!(parse.input[7..20].to_owned(), "a = 23 + b;\na");
+        assert_eq!(
+            parse.result.map(|tree| tree.tosexp()).ok(),
+            full(&parser, &parse.input).ok()
+        );
+    }
+
+    #[test]
+    fn randomeditsgivethetreeofafullparse() {
+        let parser = EbnfParser::from_str(STATEMENTS).expect("grammar");
+        let source =
+            "a = 1;\nif b { c = ; d = 2 + 3; }\nxy ! ;\nif c { if d { e = f; } }\n# note\ng = 4;\n";
+        let pieces = [
+            "", "", "a", "2", " ", "{", "}", ";", "\n", "x", "if", "while", "=", "#", "+",
+        ];
+        let mut seed = 0x2545f491u64;
+        let mut next = |n: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % n as u64) as usize
+        };
+        for _ in 0..50 {
+            let mut parse = parser.parseincremental(source, "list");
+            for _ in 0..20 {
+                let start = next(parse.input.len() + 1);
+                let end = (start + next(4)).min(parse.input.len());
+                let replacement = pieces[next(pieces.len())];
+                let before = parse.input.clone();
+                parse = parser.reparse(parse, start..end, replacement);
+                assert_eq!(
+                    parse
+                        .result
+                        .as_ref()
+                        .map(|tree| tree.tosexp())
+                        .map_err(|err| err.to_string()),
+                    full(&parser, &parse.input),
+                    "{before:?} with {start}..{end} replaced by {replacement:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn invalideditskeepthepreviousparse() {
+        let parser = EbnfParser::from_str(STATEMENTS).expect("grammar");
+        let parse = parser.parseincremental("é = 1;", "list");
+        let parse = parser.reparse(parse, 1..1, "x");
+        assert_eq!(parse.input, "é = 1;");
+        assert!(matches!(
+            parse.result,
+            Err(ParseCodeError { errtype: ParseCodeErrorType::InvalidEdit(ref edit), line: 1, column: 1 }) if *edit == (1..1)
+        ));
+        let parse = parser.reparse(parse, 5..9, "");
+        assert!(matches!(
+            parse.result,
+            Err(ParseCodeError {
+                errtype: ParseCodeErrorType::InvalidEdit(_),
+                line: 1,
+                column: 5
+            })
+        ));
+
+        let parse = parser.reparse(parse, 5..6, "2");
+        assert_eq!(parse.input, "é = 2;");
+        assert_eq!(
+            parse.result.map(|tree| tree.tosexp()).ok(),
+            full(&parser, "é = 2;").ok()
+        );
+    }
+}
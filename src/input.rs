@@ -0,0 +1,106 @@
+use crate::parser::{ParseCodeError, ParseCodeErrorType};
+
+//the part of the input a parser can currently see, positions stay absolute
+pub(crate) struct EbnfInput<'i> {
+    text: &'i str,
+    //position of the first byte of text
+    offset: usize,
+    //newlines before offset and the characters of the line containing offset before it
+    lines: usize,
+    linechars: usize,
+}
+
+impl<'i> EbnfInput<'i> {
+    pub(crate) fn new(text: &'i str) -> Self {
+        EbnfInput {
+            text,
+            offset: 0,
+            lines: 0,
+            linechars: 0,
+        }
+    }
+
+    pub(crate) fn window(text: &'i str, offset: usize, lines: usize, linechars: usize) -> Self {
+        EbnfInput {
+            text,
+            offset,
+            lines,
+            linechars,
+        }
+    }
+
+    //the position right after the visible text
+    pub(crate) fn end(&self) -> i64 {
+        (self.offset + self.text.len()) as i64
+    }
+
+    pub(crate) fn rest(&self, position: i64) -> &'i str {
+        &self.text[position as usize - self.offset..]
+    }
+
+    pub(crate) fn slice(&self, start: usize, end: usize) -> &'i str {
+        &self.text[start - self.offset..end - self.offset]
+    }
+
+    //1-based line and column of `position`, columns count characters
+    pub(crate) fn error(&self, position: i64, errtype: ParseCodeErrorType) -> ParseCodeError {
+        let before = &self.text[..position as usize - self.offset];
+        let line = self.lines + before.matches('\n').count() + 1;
+        let column = match before.rfind('\n') {
+            Some(newline) => before[newline + 1..].chars().count() + 1,
+            None => self.linechars + before.chars().count() + 1,
+        };
+        ParseCodeError {
+            line: line as i32,
+            column: column as i32,
+            errtype,
+        }
+    }
+
+    //an unexpected token or end of input at `position`, with the descriptions of what would have been accepted
+    pub(crate) fn unexpected(&self, position: i64, expected: Vec<String>) -> ParseCodeError {
+        let rest = self.rest(position);
+        let errtype = match rest.chars().next() {
+            None => ParseCodeErrorType::UnexpectedEof { expected },
+            Some(first) => {
+                let length = if first.is_alphanumeric() || first == '_' {
+                    rest.find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                        .unwrap_or(rest.len())
+                } else {
+                    first.len_utf8()
+                };
+                ParseCodeErrorType::UnexpectedToken {
+                    found: rest[..length].to_owned(),
+                    expected,
+                }
+            }
+        };
+        self.error(position, errtype)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columnscountcharacters() {
+        let input = EbnfInput::new("ä = 1\nöß = ?");
+        let error = input.unexpected(14, Vec::new());
+        assert_eq!((error.line, error.column), (2, 6));
+        assert!(
+            matches!(error.errtype, ParseCodeErrorType::UnexpectedToken { found, .. } if found == "?")
+        );
+    }
+
+    #[test]
+    fn windowscontinuethecountsofthedroppedtext() {
+        //"ab\nçd é" with the five bytes of "ab\nç" dropped
+        let input = EbnfInput::window("d é", 5, 1, 1);
+        let error = input.unexpected(7, Vec::new());
+        assert_eq!((error.line, error.column), (2, 4));
+        assert!(
+            matches!(error.errtype, ParseCodeErrorType::UnexpectedToken { found, .. } if found == "é")
+        );
+    }
+}
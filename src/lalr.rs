@@ -0,0 +1,719 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    fmt,
+};
+
+use crate::bnf::{BnfGrammar, BnfSets, BnfSymbol, BnfTerminal};
+use crate::grammar::{EbnfAssociativity, EbnfStatement};
+use crate::parser::{EbnfParser, ParseCodeError};
+use crate::tree::EbnfTree;
+
+//(production, dot)
+type LalrItem = (usize, usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub(crate) enum LalrAction {
+    Shift(usize),
+    Reduce(usize),
+    Accept,
+}
+
+//shift-reduce parser driven by LALR(1) tables
+pub struct LalrParser<'a> {
+    parser: &'a EbnfParser<'a>,
+    pub grammar: BnfGrammar<'a>,
+    //per state, lookahead terminal (terminals.len() is the end of the input) -> action
+    pub(crate) actions: Vec<HashMap<usize, LalrAction>>,
+    //per state, reduced nonterminal -> next state
+    pub(crate) gotos: Vec<HashMap<usize, usize>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LalrConflictKind {
+    ShiftReduce,
+    ReduceReduce,
+}
+
+pub struct LalrConflict {
+    pub state: usize,
+    pub kind: LalrConflictKind,
+    pub lookahead: String,
+    pub items: Vec<String>,
+    pub example: String,
+}
+
+pub enum LalrError {
+    InvalidGrammar(ParseCodeError),
+    Conflicts(Vec<LalrConflict>),
+}
+
+struct LalrAutomaton<'g, 'a> {
+    grammar: &'g BnfGrammar<'a>,
+    sets: BnfSets,
+    //the grammar's productions followed by the augmented start production
+    productions: Vec<(usize, Vec<BnfSymbol>)>,
+    productionsof: Vec<Vec<usize>>,
+    kernels: Vec<Vec<LalrItem>>,
+    transitions: Vec<BTreeMap<BnfSymbol, usize>>,
+    lookaheads: Vec<BTreeMap<LalrItem, BTreeSet<usize>>>,
+}
+
+impl fmt::Display for LalrConflictKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LalrConflictKind::ShiftReduce => write!(f, "shift/reduce"),
+            LalrConflictKind::ReduceReduce => write!(f, "reduce/reduce"),
+        }
+    }
+}
+
+impl fmt::Display for LalrConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} conflict in state {} on {}:",
+            self.kind, self.state, self.lookahead
+        )?;
+        for item in &self.items {
+            writeln!(f, "    {item}")?;
+        }
+        writeln!(f, "  example: {}", self.example)
+    }
+}
+
+impl fmt::Display for LalrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LalrError::InvalidGrammar(err) => write!(f, "{err}"),
+            LalrError::Conflicts(conflicts) => {
+                writeln!(f, "Grammar is not LALR(1), {} conflicts:", conflicts.len())?;
+                for conflict in conflicts {
+                    write!(f, "{conflict}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Debug for LalrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+impl<'g, 'a> LalrAutomaton<'g, 'a> {
+    fn new(grammar: &'g BnfGrammar<'a>) -> Self {
+        let mut productions: Vec<(usize, Vec<BnfSymbol>)> = grammar
+            .productions
+            .iter()
+            .map(|production| (production.lhs, production.rhs.clone()))
+            .collect();
+        let augmented = grammar.nonterminals.len();
+        productions.push((augmented, vec![BnfSymbol::Nonterminal(grammar.start)]));
+        let mut productionsof = vec![Vec::new(); augmented + 1];
+        for (index, (lhs, _)) in productions.iter().enumerate() {
+            productionsof[*lhs].push(index);
+        }
+
+        let mut automaton = LalrAutomaton {
+            grammar,
+            sets: grammar.sets(),
+            productions,
+            productionsof,
+            kernels: Vec::new(),
+            transitions: Vec::new(),
+            lookaheads: Vec::new(),
+        };
+        automaton.buildstates();
+        automaton.buildlookaheads();
+        automaton
+    }
+
+    fn end(&self) -> usize {
+        self.grammar.terminals.len()
+    }
+
+    fn next(&self, (production, dot): LalrItem) -> Option<BnfSymbol> {
+        self.productions[production].1.get(dot).copied()
+    }
+
+    //the LR(0) item sets and their transitions
+    fn buildstates(&mut self) {
+        let start = vec![(self.productions.len() - 1, 0)];
+        let mut index: HashMap<Vec<LalrItem>, usize> = HashMap::new();
+        index.insert(start.clone(), 0);
+        self.kernels.push(start);
+
+        let mut state = 0;
+        while state < self.kernels.len() {
+            let mut successors: BTreeMap<BnfSymbol, Vec<LalrItem>> = BTreeMap::new();
+            for item in self.closure(&self.kernels[state]) {
+                if let Some(symbol) = self.next(item) {
+                    successors
+                        .entry(symbol)
+                        .or_default()
+                        .push((item.0, item.1 + 1));
+                }
+            }
+            let mut transitions = BTreeMap::new();
+            for (symbol, mut kernel) in successors {
+                kernel.sort();
+                kernel.dedup();
+                let target = *index.entry(kernel.clone()).or_insert_with(|| {
+                    self.kernels.push(kernel);
+                    self.kernels.len() - 1
+                });
+                transitions.insert(symbol, target);
+            }
+            self.transitions.push(transitions);
+            state += 1;
+        }
+    }
+
+    fn closure(&self, kernel: &[LalrItem]) -> Vec<LalrItem> {
+        let mut items = kernel.to_vec();
+        let mut i = 0;
+        while i < items.len() {
+            if let Some(BnfSymbol::Nonterminal(nonterminal)) = self.next(items[i]) {
+                for &production in &self.productionsof[nonterminal] {
+                    if !items.contains(&(production, 0)) {
+                        items.push((production, 0));
+                    }
+                }
+            }
+            i += 1;
+        }
+        items
+    }
+
+    //LR(1) closure, lookaheads past the last terminal index are the end or a placeholder
+    fn closurewithlookaheads(&self, kernel: Vec<(LalrItem, usize)>) -> Vec<(LalrItem, usize)> {
+        let mut items = kernel;
+        let mut i = 0;
+        while i < items.len() {
+            let ((production, dot), lookahead) = items[i];
+            i += 1;
+            let Some(BnfSymbol::Nonterminal(nonterminal)) = self.next((production, dot)) else {
+                continue;
+            };
+            let (mut first, nullable) = self
+                .sets
+                .firstof(&self.productions[production].1[dot + 1..]);
+            if nullable {
+                first.insert(lookahead);
+            }
+            for &next in &self.productionsof[nonterminal] {
+                for &terminal in &first {
+                    if !items.contains(&((next, 0), terminal)) {
+                        items.push(((next, 0), terminal));
+                    }
+                }
+            }
+        }
+        items
+    }
+
+    //spontaneous generation and propagation of lookaheads between kernel items
+    fn buildlookaheads(&mut self) {
+        let placeholder = self.end() + 1;
+        self.lookaheads = self
+            .kernels
+            .iter()
+            .map(|kernel| kernel.iter().map(|&item| (item, BTreeSet::new())).collect())
+            .collect();
+        let (start, end) = (self.kernels[0][0], self.end());
+        self.lookaheads[0]
+            .get_mut(&start)
+            .expect("start item")
+            .insert(end);
+
+        let mut propagations = Vec::new();
+        for state in 0..self.kernels.len() {
+            for &kernelitem in &self.kernels[state] {
+                for (item, lookahead) in self.closurewithlookaheads(vec![(kernelitem, placeholder)])
+                {
+                    let Some(symbol) = self.next(item) else {
+                        continue;
+                    };
+                    let target = self.transitions[state][&symbol];
+                    let advanced = (item.0, item.1 + 1);
+                    if lookahead == placeholder {
+                        propagations.push((state, kernelitem, target, advanced));
+                    } else {
+                        self.lookaheads[target]
+                            .get_mut(&advanced)
+                            .expect("advanced items are kernel items")
+                            .insert(lookahead);
+                    }
+                }
+            }
+        }
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &(state, item, target, advanced) in &propagations {
+                let lookaheads = self.lookaheads[state][&item].clone();
+                let targetlookaheads = self.lookaheads[target]
+                    .get_mut(&advanced)
+                    .expect("advanced items are kernel items");
+                for lookahead in lookaheads {
+                    changed |= targetlookaheads.insert(lookahead);
+                }
+            }
+        }
+    }
+
+    fn items(&self, state: usize) -> Vec<(LalrItem, usize)> {
+        let kernel = self.lookaheads[state]
+            .iter()
+            .flat_map(|(&item, lookaheads)| {
+                lookaheads.iter().map(move |&lookahead| (item, lookahead))
+            })
+            .collect();
+        self.closurewithlookaheads(kernel)
+    }
+
+    fn describeitem(&self, (production, dot): LalrItem) -> String {
+        let (lhs, rhs) = &self.productions[production];
+        let lhs = match self.grammar.nonterminals.get(*lhs) {
+            Some(nonterminal) => nonterminal.name.clone(),
+            None => format!("{}'", self.grammar.nonterminals[self.grammar.start].name),
+        };
+        let mut symbols: Vec<String> = rhs
+            .iter()
+            .map(|&symbol| self.grammar.symbol(symbol))
+            .collect();
+        symbols.insert(dot, "°".to_owned());
+        format!("{lhs} -> {}", symbols.join(" "))
+    }
+
+    fn describelookahead(&self, lookahead: usize) -> String {
+        self.grammar
+            .describelookahead((lookahead != self.end()).then_some(lookahead))
+    }
+
+    //the shortest sequence of terminals that leads from the start state into `state`
+    fn example(&self, state: usize) -> Vec<usize> {
+        let mut previous: Vec<Option<(usize, BnfSymbol)>> = vec![None; self.kernels.len()];
+        let mut queue = VecDeque::from([0]);
+        while let Some(current) = queue.pop_front() {
+            for (&symbol, &target) in &self.transitions[current] {
+                if target != 0 && previous[target].is_none() {
+                    previous[target] = Some((current, symbol));
+                    queue.push_back(target);
+                }
+            }
+        }
+        let mut path = Vec::new();
+        let mut current = state;
+        while let Some((from, symbol)) = previous[current] {
+            path.push(symbol);
+            current = from;
+        }
+        path.reverse();
+
+        let shortest = self.shortestyields();
+        path.into_iter()
+            .flat_map(|symbol| match symbol {
+                BnfSymbol::Terminal(terminal) => vec![terminal],
+                BnfSymbol::Nonterminal(nonterminal) => {
+                    shortest[nonterminal].clone().unwrap_or_default()
+                }
+            })
+            .collect()
+    }
+
+    fn shortestyields(&self) -> Vec<Option<Vec<usize>>> {
+        let mut shortest: Vec<Option<Vec<usize>>> = vec![None; self.productionsof.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (lhs, rhs) in &self.productions {
+                let mut candidate = Vec::new();
+                let complete = rhs.iter().all(|&symbol| match symbol {
+                    BnfSymbol::Terminal(terminal) => {
+                        candidate.push(terminal);
+                        true
+                    }
+                    BnfSymbol::Nonterminal(nonterminal) => match &shortest[nonterminal] {
+                        Some(terminals) => {
+                            candidate.extend(terminals);
+                            true
+                        }
+                        None => false,
+                    },
+                });
+                if complete
+                    && shortest[*lhs]
+                        .as_ref()
+                        .is_none_or(|known| candidate.len() < known.len())
+                {
+                    shortest[*lhs] = Some(candidate);
+                    changed = true;
+                }
+            }
+        }
+        shortest
+    }
+}
+
+impl<'a> EbnfParser<'a> {
+    //builds the LALR(1) tables for `startrule`, shift/reduce conflicts are resolved with
+    //%left, %right and %nonassoc where possible and every remaining conflict is reported
+    pub fn lalr(&'a self, startrule: &str) -> Result<LalrParser<'a>, LalrError> {
+        let grammar = BnfGrammar::new(&self.rules, startrule).map_err(LalrError::InvalidGrammar)?;
+        let automaton = LalrAutomaton::new(&grammar);
+        let end = automaton.end();
+        let augmented = automaton.productions.len() - 1;
+
+        let mut actions = Vec::new();
+        let mut gotos = Vec::new();
+        let mut conflicts = Vec::new();
+        for state in 0..automaton.kernels.len() {
+            let items = automaton.items(state);
+            let mut entries: BTreeMap<usize, BTreeSet<LalrAction>> = BTreeMap::new();
+            for &(item, lookahead) in &items {
+                match automaton.next(item) {
+                    None if item.0 == augmented => {
+                        entries.entry(end).or_default().insert(LalrAction::Accept);
+                    }
+                    None => {
+                        entries
+                            .entry(lookahead)
+                            .or_default()
+                            .insert(LalrAction::Reduce(item.0));
+                    }
+                    Some(BnfSymbol::Terminal(terminal)) => {
+                        let target = automaton.transitions[state][&BnfSymbol::Terminal(terminal)];
+                        entries
+                            .entry(terminal)
+                            .or_default()
+                            .insert(LalrAction::Shift(target));
+                    }
+                    Some(BnfSymbol::Nonterminal(_)) => {}
+                }
+            }
+
+            let mut stateactions = HashMap::new();
+            for (lookahead, candidates) in entries {
+                let resolved = match candidates.len() {
+                    1 => candidates.first().copied(),
+                    _ => self.resolve(&grammar, &candidates, lookahead),
+                };
+                match resolved {
+                    Some(action) => {
+                        stateactions.insert(lookahead, action);
+                    }
+                    //nonassoc operators that follow each other are a syntax error
+                    None if self.isnonassoc(&grammar, &candidates, lookahead) => {}
+                    None => conflicts.push(LalrConflict {
+                        state,
+                        kind: if candidates
+                            .iter()
+                            .any(|action| matches!(action, LalrAction::Shift(_)))
+                        {
+                            LalrConflictKind::ShiftReduce
+                        } else {
+                            LalrConflictKind::ReduceReduce
+                        },
+                        lookahead: automaton.describelookahead(lookahead),
+                        items: items
+                            .iter()
+                            .filter(|&&(item, itemlookahead)| match automaton.next(item) {
+                                None => itemlookahead == lookahead,
+                                Some(symbol) => symbol == BnfSymbol::Terminal(lookahead),
+                            })
+                            .map(|&(item, _)| automaton.describeitem(item))
+                            .collect::<BTreeSet<_>>()
+                            .into_iter()
+                            .collect(),
+                        example: {
+                            let mut example: Vec<String> = automaton
+                                .example(state)
+                                .into_iter()
+                                .map(|terminal| grammar.describe(terminal))
+                                .collect();
+                            example.push("°".to_owned());
+                            example.push(automaton.describelookahead(lookahead));
+                            example.join(" ")
+                        },
+                    }),
+                }
+            }
+            actions.push(stateactions);
+            gotos.push(
+                automaton.transitions[state]
+                    .iter()
+                    .filter_map(|(&symbol, &target)| match symbol {
+                        BnfSymbol::Nonterminal(nonterminal) => Some((nonterminal, target)),
+                        BnfSymbol::Terminal(_) => None,
+                    })
+                    .collect(),
+            );
+        }
+
+        if !conflicts.is_empty() {
+            return Err(LalrError::Conflicts(conflicts));
+        }
+        Ok(LalrParser {
+            parser: self,
+            grammar,
+            actions,
+            gotos,
+        })
+    }
+
+    fn precedenceof(
+        &self,
+        grammar: &BnfGrammar,
+        terminal: usize,
+    ) -> Option<(usize, EbnfAssociativity)> {
+        let described = grammar.describe(terminal);
+        let displayed = grammar.terminals[terminal].to_string();
+        self.precedence
+            .iter()
+            .enumerate()
+            .find(|(_, (_, statements))| {
+                statements.iter().any(|statement| match statement {
+                    EbnfStatement::DefinedRule { rulename } => *rulename == described,
+                    _ => statement.to_string() == displayed,
+                })
+            })
+            .map(|(level, (associativity, _))| (level, *associativity))
+    }
+
+    //the precedence of a production is the one of its last terminal that has one
+    fn productionprecedence(
+        &self,
+        grammar: &BnfGrammar,
+        production: usize,
+    ) -> Option<(usize, EbnfAssociativity)> {
+        grammar.productions[production]
+            .rhs
+            .iter()
+            .rev()
+            .find_map(|&symbol| match symbol {
+                BnfSymbol::Terminal(terminal) => self.precedenceof(grammar, terminal),
+                BnfSymbol::Nonterminal(_) => None,
+            })
+    }
+
+    fn shiftreduce(candidates: &BTreeSet<LalrAction>) -> Option<(LalrAction, usize)> {
+        match candidates.iter().collect::<Vec<_>>().as_slice() {
+            [LalrAction::Shift(_), LalrAction::Reduce(production)]
+            | [LalrAction::Reduce(production), LalrAction::Shift(_)] => {
+                let shift = *candidates
+                    .iter()
+                    .find(|action| matches!(action, LalrAction::Shift(_)))
+                    .expect("matched above");
+                Some((shift, *production))
+            }
+            _ => None,
+        }
+    }
+
+    fn resolve(
+        &self,
+        grammar: &BnfGrammar,
+        candidates: &BTreeSet<LalrAction>,
+        lookahead: usize,
+    ) -> Option<LalrAction> {
+        let (shift, production) = Self::shiftreduce(candidates)?;
+        let (productionlevel, associativity) = self.productionprecedence(grammar, production)?;
+        let (lookaheadlevel, _) = self.precedenceof(grammar, lookahead)?;
+        if productionlevel > lookaheadlevel {
+            Some(LalrAction::Reduce(production))
+        } else if productionlevel < lookaheadlevel {
+            Some(shift)
+        } else {
+            match associativity {
+                EbnfAssociativity::Left => Some(LalrAction::Reduce(production)),
+                EbnfAssociativity::Right => Some(shift),
+                EbnfAssociativity::Nonassoc => None,
+            }
+        }
+    }
+
+    fn isnonassoc(
+        &self,
+        grammar: &BnfGrammar,
+        candidates: &BTreeSet<LalrAction>,
+        lookahead: usize,
+    ) -> bool {
+        let Some((_, production)) = Self::shiftreduce(candidates) else {
+            return false;
+        };
+        match (
+            self.productionprecedence(grammar, production),
+            self.precedenceof(grammar, lookahead),
+        ) {
+            (Some((productionlevel, EbnfAssociativity::Nonassoc)), Some((lookaheadlevel, _))) => {
+                productionlevel == lookaheadlevel
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<'a> LalrParser<'a> {
+    pub fn parse(&self, input: &str) -> Result<EbnfTree, ParseCodeError> {
+        let mut position = self.parser.skipignored(input, 0)? as usize;
+        let mut states = vec![0];
+        //the built nodes in input order, and per symbol on the stack where its nodes start
+        let mut nodes: Vec<EbnfTree> = Vec::new();
+        let mut offsets: Vec<usize> = Vec::new();
+
+        loop {
+            let state = *states.last().expect("the start state is never popped");
+            let action = self
+                .lookahead(state, input, position)
+                .and_then(|(lookahead, length)| {
+                    self.actions[state]
+                        .get(&lookahead)
+                        .map(|&action| (action, lookahead, length))
+                });
+            let Some((action, lookahead, length)) = action else {
+                return Err(ParseCodeError::unexpected(
+                    input,
+                    position as i64,
+                    self.expected(state),
+                ));
+            };
+            match action {
+                LalrAction::Shift(target) => {
+                    offsets.push(nodes.len());
+                    nodes.push(
+                        self.grammar
+                            .token(lookahead, input, position, position + length),
+                    );
+                    position = self.parser.skipignored(input, (position + length) as i64)? as usize;
+                    states.push(target);
+                }
+                LalrAction::Reduce(index) => {
+                    let production = &self.grammar.productions[index];
+                    let count = production.rhs.len();
+                    let offset = offsets
+                        .drain(offsets.len() - count..)
+                        .next()
+                        .unwrap_or(nodes.len());
+                    states.truncate(states.len() - count);
+                    self.grammar.reduce(index, &mut nodes, offset, position);
+                    offsets.push(offset);
+                    let state = *states.last().expect("the start state is never popped");
+                    states.push(self.gotos[state][&production.lhs]);
+                }
+                LalrAction::Accept => {
+                    return Ok(nodes.pop().expect("the start rule always builds a node"));
+                }
+            }
+        }
+    }
+
+    //the longest matching terminal the state has an action for, string terminals win ties
+    fn lookahead(&self, state: usize, input: &str, position: usize) -> Option<(usize, usize)> {
+        let mut best: Option<(usize, bool, usize)> = None;
+        for &terminal in self.actions[state].keys() {
+            let Some(candidate) = self.grammar.terminals.get(terminal) else {
+                continue;
+            };
+            if let Some(length) = candidate.matchat(input, position, &self.parser.reserved) {
+                let isstring = matches!(candidate, BnfTerminal::String(_));
+                if best.is_none_or(|(bestlength, beststring, _)| {
+                    (length, isstring) > (bestlength, beststring)
+                }) {
+                    best = Some((length, isstring, terminal));
+                }
+            }
+        }
+        match best {
+            Some((length, _, terminal)) => Some((terminal, length)),
+            None if position == input.len() => Some((self.grammar.terminals.len(), 0)),
+            None => None,
+        }
+    }
+
+    fn expected(&self, state: usize) -> Vec<String> {
+        let end = self.grammar.terminals.len();
+        let mut expected: Vec<String> = self.actions[state]
+            .keys()
+            .map(|&lookahead| {
+                self.grammar
+                    .describelookahead((lookahead != end).then_some(lookahead))
+            })
+            .collect();
+        expected.sort();
+        expected.dedup();
+        expected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPRESSIONS: &str = r#"
+expression: expression "+" expression | expression "-" expression
+          | expression "*" expression | number;
+number: /[0-9]+/;
+%ignore /[ ]+/;
+"#;
+
+    #[test]
+    fn precedencedeclarationsresolveconflicts() {
+        let grammar = format!("{EXPRESSIONS}%left \"+\" \"-\";\n%left \"*\";\n");
+        let parser = EbnfParser::from_str(&grammar).expect("grammar");
+        let lalr = parser.lalr("expression").expect("conflicts resolved");
+        let tree = lalr.parse("1 - 2 - 3 * 4").map(|tree| tree.tosexp()).ok();
+        assert_eq!(
+            tree.as_deref(),
+            Some(concat!(
+                r#"(expression (expression (expression (number "1")) "-" (expression (number "2")))"#,
+                r#" "-" (expression (expression (number "3")) "*" (expression (number "4"))))"#
+            ))
+        );
+        assert!(lalr.parse("1 - * 2").is_err());
+    }
+
+    #[test]
+    fn conflictscomewithanexample() {
+        let parser = EbnfParser::from_str(EXPRESSIONS).expect("grammar");
+        let Err(LalrError::Conflicts(conflicts)) = parser.lalr("expression") else {
+            panic!("the operators have no precedence");
+        };
+        assert!(
+            conflicts
+                .iter()
+                .all(|conflict| conflict.kind == LalrConflictKind::ShiftReduce)
+        );
+        let conflict = &conflicts[0];
+        assert_eq!(conflict.lookahead, r#""+""#);
+        assert_eq!(conflict.example, r#"number "+" number ° "+""#);
+    }
+
+    #[test]
+    fn reducereduceconflictsarereported() {
+        let parser = EbnfParser::from_str(r#"start: a | b; a: word; b: word; word: /[a-z]+/;"#)
+            .expect("grammar");
+        let Err(LalrError::Conflicts(conflicts)) = parser.lalr("start") else {
+            panic!("a and b derive the same input");
+        };
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].kind, LalrConflictKind::ReduceReduce);
+    }
+
+    #[test]
+    fn longrepetitionsparseinlineartime() {
+        let parser = EbnfParser::from_str(r#"list: number*; number: /[0-9]+/; %ignore /[ ]+/;"#)
+            .expect("grammar");
+        let Ok(lalr) = parser.lalr("list") else {
+            panic!("the grammar is LALR(1)");
+        };
+        //every item is moved into the tree once, not copied again at every level of the repetition
+        let Ok(EbnfTree::Rule { children, .. }) = lalr.parse(&"1 ".repeat(100_000)) else {
+            panic!("the input parses");
+        };
+        assert_eq!(children.len(), 100_000);
+    }
+}
@@ -0,0 +1,51 @@
+//the derived FromParseTree impls name the crate, in its own tests as well
+#[cfg(test)]
+extern crate self as compiler;
+
+mod action;
+mod ambiguity;
+mod bnf;
+mod codegen;
+mod complete;
+mod coverage;
+mod fromtree;
+mod generate;
+mod grammar;
+mod highlight;
+mod incremental;
+mod input;
+mod lalr;
+mod ll1;
+mod parser;
+mod peg;
+mod simplify;
+mod stream;
+mod table;
+mod testcase;
+mod trace;
+mod transform;
+mod tree;
+
+pub use action::EbnfActions;
+pub use ambiguity::EbnfAmbiguity;
+pub use bnf::{BnfGrammar, BnfNonterminal, BnfProduction, BnfSets, BnfSymbol, BnfTerminal};
+pub use compiler_derive::FromParseTree;
+pub use complete::EbnfCompletion;
+pub use coverage::EbnfCoverage;
+pub use fromtree::{
+    EbnfChildren, FromChildren, FromParseTree, FromTreeError, FromTreeErrorType, checkfields,
+    firstcheck, treenode, unexpectednode,
+};
+pub use generate::EbnfGenerator;
+pub use grammar::{
+    EbnfAssociativity, EbnfHighlight, EbnfStatement, ParseEbnfError, ParseEbnfErrorType,
+};
+pub use highlight::EbnfSpan;
+pub use incremental::EbnfParse;
+pub use lalr::{LalrConflict, LalrConflictKind, LalrError, LalrParser};
+pub use ll1::{Ll1Conflict, Ll1Error, Ll1Parser};
+pub use parser::{EbnfItem, EbnfParser, EbnfPartial, ParseCodeError, ParseCodeErrorType};
+pub use testcase::{EbnfTest, EbnfTestResult};
+pub use trace::{JsonTraceWriter, ParseObserver, TracePrinter};
+pub use transform::{EbnfHook, EbnfHooks, Transformer};
+pub use tree::EbnfTree;
@@ -0,0 +1,311 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+};
+
+use crate::bnf::{BnfGrammar, BnfSymbol, BnfTerminal};
+use crate::parser::{EbnfParser, ParseCodeError};
+use crate::tree::EbnfTree;
+
+//table-driven predictive parser for grammars that are LL(1)
+pub struct Ll1Parser<'a> {
+    parser: &'a EbnfParser<'a>,
+    pub grammar: BnfGrammar<'a>,
+    //(nonterminal, lookahead terminal or None for the end of the input) -> production
+    pub table: HashMap<(usize, Option<usize>), usize>,
+    //the terminals each nonterminal has table entries for
+    lookaheads: Vec<Vec<usize>>,
+    first: Vec<Vec<usize>>,
+    //a production deriving the empty string, used to defer errors to where they are precise
+    empty: Vec<Option<usize>>,
+}
+
+pub struct Ll1Conflict {
+    pub rule: String,
+    pub lookahead: String,
+    pub productions: Vec<String>,
+}
+
+pub enum Ll1Error {
+    InvalidGrammar(ParseCodeError),
+    Conflicts(Vec<Ll1Conflict>),
+}
+
+enum Ll1Entry {
+    Symbol(BnfSymbol),
+    //closes the tree node of a nonterminal once its production is fully matched, its children
+    //are the nodes built since `offset`
+    End { production: usize, offset: usize },
+}
+
+impl fmt::Display for Ll1Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} on {} could start any of:", self.rule, self.lookahead)?;
+        for production in &self.productions {
+            writeln!(f, "    {production}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Ll1Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Ll1Error::InvalidGrammar(err) => write!(f, "{err}"),
+            Ll1Error::Conflicts(conflicts) => {
+                writeln!(f, "Grammar is not LL(1), {} conflicts:", conflicts.len())?;
+                for conflict in conflicts {
+                    write!(f, "{conflict}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Ll1Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+impl<'a> EbnfParser<'a> {
+    //builds the LL(1) table for `startrule`, or reports every conflict that prevents it
+    pub fn ll1(&'a self, startrule: &str) -> Result<Ll1Parser<'a>, Ll1Error> {
+        let grammar = BnfGrammar::new(&self.rules, startrule).map_err(Ll1Error::InvalidGrammar)?;
+        let sets = grammar.sets();
+
+        let mut entries: BTreeMap<(usize, Option<usize>), BTreeSet<usize>> = BTreeMap::new();
+        for (index, production) in grammar.productions.iter().enumerate() {
+            let (first, nullable) = sets.firstof(&production.rhs);
+            let mut lookaheads: Vec<Option<usize>> = first.into_iter().map(Some).collect();
+            if nullable {
+                lookaheads.extend(&sets.follow[production.lhs]);
+            }
+            for lookahead in lookaheads {
+                entries
+                    .entry((production.lhs, lookahead))
+                    .or_default()
+                    .insert(index);
+            }
+        }
+
+        let conflicts: Vec<Ll1Conflict> = entries
+            .iter()
+            .filter(|(_, productions)| productions.len() > 1)
+            .map(|(&(lhs, lookahead), productions)| Ll1Conflict {
+                rule: grammar.nonterminals[lhs].name.clone(),
+                lookahead: grammar.describelookahead(lookahead),
+                productions: productions
+                    .iter()
+                    .map(|&production| grammar.production(production))
+                    .collect(),
+            })
+            .collect();
+        if !conflicts.is_empty() {
+            return Err(Ll1Error::Conflicts(conflicts));
+        }
+
+        let mut lookaheads = vec![Vec::new(); grammar.nonterminals.len()];
+        let mut table = HashMap::new();
+        for ((lhs, lookahead), productions) in entries {
+            if let Some(terminal) = lookahead {
+                lookaheads[lhs].push(terminal);
+            }
+            table.insert(
+                (lhs, lookahead),
+                *productions.first().expect("no empty entries"),
+            );
+        }
+
+        let mut empty = vec![None; grammar.nonterminals.len()];
+        for (index, production) in grammar.productions.iter().enumerate() {
+            if sets.firstof(&production.rhs).1 {
+                empty[production.lhs] = Some(index);
+            }
+        }
+
+        Ok(Ll1Parser {
+            parser: self,
+            empty,
+            grammar,
+            table,
+            lookaheads,
+            first: sets
+                .first
+                .into_iter()
+                .map(|first| first.into_iter().collect())
+                .collect(),
+        })
+    }
+}
+
+impl<'a> Ll1Parser<'a> {
+    pub fn parse(&self, input: &str) -> Result<EbnfTree, ParseCodeError> {
+        let end = input.len();
+        let mut position = self.parser.skipignored(input, 0)? as usize;
+        let mut stack = vec![Ll1Entry::Symbol(BnfSymbol::Nonterminal(self.grammar.start))];
+        //the nodes of the open rules, each rule's children at the end
+        let mut nodes: Vec<EbnfTree> = Vec::new();
+        //everything that could have been matched since the last token, for error messages
+        let mut expected: BTreeSet<String> = BTreeSet::new();
+
+        while let Some(entry) = stack.pop() {
+            match entry {
+                Ll1Entry::End { production, offset } => {
+                    self.grammar
+                        .reduce(production, &mut nodes, offset, position);
+                }
+                Ll1Entry::Symbol(BnfSymbol::Terminal(terminal)) => {
+                    let Some(length) = self.grammar.terminals[terminal].matchat(
+                        input,
+                        position,
+                        &self.parser.reserved,
+                    ) else {
+                        expected.insert(self.grammar.describe(terminal));
+                        return Err(ParseCodeError::unexpected(
+                            input,
+                            position as i64,
+                            expected.into_iter().collect(),
+                        ));
+                    };
+                    expected.clear();
+                    nodes.push(
+                        self.grammar
+                            .token(terminal, input, position, position + length),
+                    );
+                    position = self.parser.skipignored(input, (position + length) as i64)? as usize;
+                }
+                Ll1Entry::Symbol(BnfSymbol::Nonterminal(lhs)) => {
+                    expected.extend(
+                        self.first[lhs]
+                            .iter()
+                            .map(|&terminal| self.grammar.describe(terminal)),
+                    );
+                    let production = self
+                        .lookahead(lhs, input, position)
+                        .and_then(|lookahead| self.table.get(&(lhs, lookahead)).copied())
+                        .or(self.empty[lhs]);
+                    let Some(production) = production else {
+                        return Err(ParseCodeError::unexpected(
+                            input,
+                            position as i64,
+                            expected.into_iter().collect(),
+                        ));
+                    };
+                    //synthetic nonterminals leave their nodes to the enclosing rule
+                    if !self.grammar.nonterminals[lhs].synthetic {
+                        stack.push(Ll1Entry::End {
+                            production,
+                            offset: nodes.len(),
+                        });
+                    }
+                    for &symbol in self.grammar.productions[production].rhs.iter().rev() {
+                        stack.push(Ll1Entry::Symbol(symbol));
+                    }
+                }
+            }
+        }
+
+        if position != end {
+            expected.insert("end of input".to_owned());
+            return Err(ParseCodeError::unexpected(
+                input,
+                position as i64,
+                expected.into_iter().collect(),
+            ));
+        }
+        Ok(nodes.pop().expect("the start rule always builds a node"))
+    }
+
+    //the longest matching terminal the nonterminal has an entry for, string terminals win ties.
+    //Some(None) is the end of the input, None means nothing matched
+    fn lookahead(&self, lhs: usize, input: &str, position: usize) -> Option<Option<usize>> {
+        let mut best: Option<(usize, bool, usize)> = None;
+        for &terminal in &self.lookaheads[lhs] {
+            let candidate = &self.grammar.terminals[terminal];
+            if let Some(length) = candidate.matchat(input, position, &self.parser.reserved) {
+                let isstring = matches!(candidate, BnfTerminal::String(_));
+                if best.is_none_or(|(bestlength, beststring, _)| {
+                    (length, isstring) > (bestlength, beststring)
+                }) {
+                    best = Some((length, isstring, terminal));
+                }
+            }
+        }
+        match best {
+            Some((_, _, terminal)) => Some(Some(terminal)),
+            None if position == input.len() => Some(None),
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+config: entry*;
+entry: key "=" value ";";
+key: /[a-z]+/;
+value: /[0-9]+/ | "[" (value ("," value)*)? "]";
+%ignore /[ \n]+/;
+"#;
+
+    #[test]
+    fn ll1treesmatchtheearleytrees() {
+        let parser = EbnfParser::from_str(CONFIG).expect("grammar");
+        let ll1 = parser.ll1("config").expect("LL(1) grammar");
+        for input in ["", "a = 1;", "a = [1, [2, 3], []];\nb = 4;"] {
+            let tree = ll1.parse(input).map(|tree| tree.tosexp()).ok();
+            assert!(tree.is_some());
+            assert_eq!(
+                tree,
+                parser.parse(input, "config").map(|tree| tree.tosexp()).ok()
+            );
+        }
+    }
+
+    #[test]
+    fn ll1syntaxerrorslisttheexpectedterminals() {
+        let parser = EbnfParser::from_str(CONFIG).expect("grammar");
+        let ll1 = parser.ll1("config").expect("LL(1) grammar");
+        let error = ll1.parse("a = [1 2];").err().expect("missing ','");
+        assert_eq!(
+            error.to_string(),
+            parser
+                .parse("a = [1 2];", "config")
+                .err()
+                .expect("missing ','")
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn conflictsnametheruleandthelookahead() {
+        let parser = EbnfParser::from_str(r#"sum: sum "+" number | number; number: /[0-9]+/;"#)
+            .expect("grammar");
+        let Err(Ll1Error::Conflicts(conflicts)) = parser.ll1("sum") else {
+            panic!("left recursion is not LL(1)");
+        };
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].rule, "sum");
+        assert_eq!(conflicts[0].lookahead, "number");
+        assert_eq!(conflicts[0].productions.len(), 2);
+    }
+
+    #[test]
+    fn longrepetitionsparseinlineartime() {
+        let parser = EbnfParser::from_str(r#"list: number*; number: /[0-9]+/; %ignore /[ ]+/;"#)
+            .expect("grammar");
+        let Ok(ll1) = parser.ll1("list") else {
+            panic!("the grammar is LL(1)");
+        };
+        //every item is moved into the tree once, not copied again at every level of the repetition
+        let Ok(EbnfTree::Rule { children, .. }) = ll1.parse(&"1 ".repeat(100_000)) else {
+            panic!("the input parses");
+        };
+        assert_eq!(children.len(), 100_000);
+    }
+}
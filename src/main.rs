@@ -1,758 +1,298 @@
-use regex::Regex;
-use std::{collections::HashMap, fmt};
+use compiler::EbnfParser;
 
-struct EbnfRule<'a> {
-    name: &'a str,
-    rule: EbnfStatement<'a>,
+fn readgrammar(path: &str) -> Option<String> {
+    std::fs::read_to_string(path)
+        .inspect_err(|err| println!("Could not read {path}: {err}"))
+        .ok()
 }
 
-struct EbnfParser<'rules, 'partials> {
-    currentline: i32,
-    currentcolumn: i32,
-    rules: HashMap<String, EbnfStatement<'rules>>,
-    charnum: i64,
-    partialparses: Vec<Box<EbnfPartial<'partials>>>,
-}
-
-struct EbnfPartial<'a> {
-    starttoken: i64,
-    currenttoken: i64,
-    statement: &'a EbnfStatement<'a>,
-    isterminal: bool,
-    currentchar: i64,
-}
-
-enum EbnfStatement<'a> {
-    StringTerminal {
-        string: &'a str,
-    },
-    RegexTerminal {
-        string: &'a str,
-    },
-    DefinedRule {
-        rulename: &'a str,
-    },
-    Concatenation {
-        rules: Vec<Box<EbnfStatement<'a>>>,
-    },
-    Optional {
-        rule: Box<EbnfStatement<'a>>,
-    },
-    OneOrMore {
-        rule: Box<EbnfStatement<'a>>,
-    },
-    ZeroOrMore {
-        rule: Box<EbnfStatement<'a>>,
-    },
-    /*Repetition {
-        rule: Box<EbnfStatement<'a>>,
-        minamount: i32,
-        maxamount: i32,
-    },*/
-    Or {
-        left: Box<EbnfStatement<'a>>,
-        right: Box<EbnfStatement<'a>>,
-    },
-    Empty,
-}
-
-struct ParseEbnfError {
-    line: i32,
-    column: i32,
-    errtype: ParseEbnfErrorType,
-}
-
-enum ParseEbnfErrorType {
-    UnclosedString,
-    UnclosedParen,
-    UnexpectedCharacter(char),
-    EmptyRule,
-    UnclosedRule,
-}
-
-struct ParseCodeError {
-    line: i32,
-    column: i32,
-    errtype: ParseCodeErrorType,
-}
-
-enum ParseCodeErrorType {
-    InvalidRegex,
-    UnknownRule,
-}
-
-impl fmt::Display for ParseEbnfError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let errtype = &self.errtype;
-        let line = self.line;
-        let column = self.column;
-        match errtype {
-            ParseEbnfErrorType::UnclosedString => {
-                write!(
-                    f,
-                    "Unclosed String, expected '\"' at line {line}, column {column}!"
-                )
-            }
-            ParseEbnfErrorType::UnexpectedCharacter(character) => {
-                write!(
-                    f,
-                    "Unexpected '{character}' at line {line}, column {column}!"
-                )
-            }
-            ParseEbnfErrorType::EmptyRule => {
-                write!(f, "Empty rule in line {line}, column {column}!")
-            }
-            ParseEbnfErrorType::UnclosedParen => {
-                write!(
-                    f,
-                    "Unclosed Parentheses, expected ')' at line {line}, column {column}"
-                )
-            }
-            ParseEbnfErrorType::UnclosedRule => {
-                write!(
-                    f,
-                    "Unclosed Rule, expected ';' at line {line}, column {column}"
-                )
-            }
+//runs the %test examples of a grammar file, the exit code is 1 if any failed
+fn testgrammar(path: &str) -> i32 {
+    let Some(grammar) = readgrammar(path) else {
+        return 2;
+    };
+    let parser = match EbnfParser::from_str(&grammar) {
+        Ok(parser) => parser,
+        Err(err) => {
+            println!("{err}");
+            return 2;
         }
+    };
+    let results = parser.runtests();
+    for result in &results {
+        println!("{result}");
     }
+    let failed = results.iter().filter(|result| !result.passed).count();
+    println!("{} passed, {failed} failed", results.len() - failed);
+    (failed > 0) as i32
 }
 
-impl fmt::Debug for ParseEbnfError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{self}")
-    }
-}
-
-impl fmt::Display for ParseCodeError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self.errtype {
-            ParseCodeErrorType::InvalidRegex => {
-                write!(
-                    f,
-                    "Invalid Regex at line {}, column {}",
-                    self.line, self.column
-                )
-            }
-            ParseCodeErrorType::UnknownRule => {
-                write!(
-                    f,
-                    "Unknown Rule at line {}, column {}",
-                    self.line, self.column
-                )
-            }
+//prints `count` random sentences of `rule`, one per line
+fn generatesentences(path: &str, rule: &str, count: &str, seed: &str) -> i32 {
+    let Some(grammar) = readgrammar(path) else {
+        return 2;
+    };
+    let (Ok(count), Ok(seed)) = (count.parse::<usize>(), seed.parse::<u64>()) else {
+        println!("Expected numbers for the count and the seed");
+        return 2;
+    };
+    let parser = match EbnfParser::from_str(&grammar) {
+        Ok(parser) => parser,
+        Err(err) => {
+            println!("{err}");
+            return 2;
+        }
+    };
+    let mut generator = match parser.generator(rule) {
+        Ok(generator) => generator,
+        Err(err) => {
+            println!("{err}");
+            return 2;
         }
+    };
+    generator.seed = seed;
+    for _ in 0..count {
+        println!("{}", generator.generate());
     }
+    0
 }
 
-impl<'a> fmt::Display for EbnfStatement<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            EbnfStatement::StringTerminal { string } => write!(f, "\"{string}\""),
-            EbnfStatement::RegexTerminal { string } => write!(f, "/{string}/"),
-            EbnfStatement::DefinedRule { rulename } => write!(f, "{rulename}"),
-            EbnfStatement::Concatenation { rules } => {
-                write!(
-                    f,
-                    "({})",
-                    rules
-                        .iter()
-                        .map(|rule| (*rule).to_string())
-                        .collect::<Vec<_>>()
-                        .join(" ")
-                )
-            }
-            EbnfStatement::Optional { rule } => write!(f, "{}?", rule),
-            /*EbnfStatement::Repetition {
-                rule,
-                minamount,
-                maxamount,
-            } => write!(f, "{rule}{{{minamount},{maxamount}}}"),*/
-            EbnfStatement::Or { left, right } => write!(f, "{left} | {right}"),
-            EbnfStatement::OneOrMore { rule } => write!(f, "{rule}+"),
-            EbnfStatement::ZeroOrMore { rule } => write!(f, "{rule}*"),
-            EbnfStatement::Empty => write!(f, "§"),
+//parses every file of a directory and prints which parts of the grammar they used
+fn coveragereport(path: &str, rule: &str, directory: &str) -> i32 {
+    let Some(grammar) = readgrammar(path) else {
+        return 2;
+    };
+    let parser = match EbnfParser::from_str(&grammar) {
+        Ok(parser) => parser,
+        Err(err) => {
+            println!("{err}");
+            return 2;
+        }
+    };
+    let mut coverage = match parser.coverage(rule) {
+        Ok(coverage) => coverage,
+        Err(err) => {
+            println!("{err}");
+            return 2;
+        }
+    };
+    let mut files: Vec<_> = match std::fs::read_dir(directory) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|file| file.is_file())
+            .collect(),
+        Err(err) => {
+            println!("Could not read {directory}: {err}");
+            return 2;
+        }
+    };
+    files.sort();
+    for file in files {
+        let result = match std::fs::read_to_string(&file) {
+            Ok(input) => coverage.parse(&input).map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        if let Err(err) = result {
+            println!("{}: {err}", file.display());
         }
     }
+    println!("{}", coverage.report());
+    print!("{}", coverage.listing());
+    0
 }
 
-impl<'a> fmt::Display for EbnfPartial<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let statement = &self.statement;
-        if let EbnfStatement::Concatenation { rules } = statement {
-            let mut out = String::new();
-            for (i, rule) in rules.iter().enumerate() {
-                if i as i64 == self.currenttoken {
-                    out += "°";
-                }
-                out.push_str(format!("{rule} ").as_str());
-            }
-            write!(f, "{out}")
-        } else {
-            let statement = &self.statement;
-            if self.currenttoken == 0 {
-                write!(f, "° {statement}")
-            } else {
-                write!(f, "{statement}°")
-            }
+//prints a file colored for the terminal, or as html
+fn highlightfile(path: &str, rule: &str, file: &str, format: &str) -> i32 {
+    let (Some(grammar), Some(input)) = (readgrammar(path), readgrammar(file)) else {
+        return 2;
+    };
+    let parser = match EbnfParser::from_str(&grammar) {
+        Ok(parser) => parser,
+        Err(err) => {
+            println!("{err}");
+            return 2;
+        }
+    };
+    let highlighted = match format {
+        "html" => parser.highlighthtml(&input, rule),
+        _ => parser.highlightansi(&input, rule),
+    };
+    match highlighted {
+        Ok(highlighted) => {
+            print!("{highlighted}");
+            0
+        }
+        Err(err) => {
+            println!("{err}");
+            1
         }
     }
 }
 
-impl<'a> EbnfStatement<'a> {
-    fn new(s: &'a str, startline: i32, startcolumn: i32) -> Result<Self, ParseEbnfError> {
-        let mut parsedrules: Vec<EbnfStatement> = Vec::new();
-        let characters = s.chars();
-        let mut matchstart = 0;
-        let mut currentcolumn: i32 = startcolumn;
-        let mut currentline: i32 = startline;
-        let mut stringparse = false;
-        let mut escaped = false;
-        let mut bracketparse = false;
-        let mut bracketlevel = 0;
-        let mut regexparse = false;
-
-        for (i, char) in characters.enumerate() {
-            //parsing strings
-            if escaped {
-                escaped = false;
-            } else if stringparse {
-                if char == '"' {
-                    stringparse = false;
-                    parsedrules.push(EbnfStatement::StringTerminal {
-                        string: &s[matchstart + 1..i],
-                    });
-                    matchstart = i + 1;
-                } else if char == '\n' {
-                    return Err(ParseEbnfError {
-                        line: currentline,
-                        column: currentcolumn,
-                        errtype: ParseEbnfErrorType::UnexpectedCharacter(char.to_owned()),
-                    });
-                } else if char == '\\' {
-                    escaped = true;
-                }
-            }
-            //parsing brackets
-            else if bracketparse {
-                if char == '(' {
-                    bracketlevel += 1;
-                }
-                if char == ')' {
-                    bracketlevel -= 1;
-                    if bracketlevel == 0 {
-                        bracketparse = false;
-                        parsedrules.push(EbnfStatement::new(
-                            &s[matchstart + 1..i],
-                            currentline,
-                            currentcolumn,
-                        )?);
-                        matchstart = i + 1;
-                    }
-                }
-            }
-            //parsing regex
-            else if regexparse {
-                if char == '/' {
-                    regexparse = false;
-                    parsedrules.push(EbnfStatement::RegexTerminal {
-                        string: &s[matchstart + 1..i],
-                    });
-                    matchstart = i + 1;
-                }
-            }
-            //parsing regular rule names, split by space, newline or tab
-            else if [' ', '\n', '\t'].contains(&char) {
-                if matchstart != i {
-                    parsedrules.push(EbnfStatement::DefinedRule {
-                        rulename: &s[matchstart..i],
-                    });
-                }
-                matchstart = i + 1;
-            } else if char == '"' {
-                stringparse = true;
-            } else if char == '(' {
-                bracketparse = true;
-                bracketlevel = 1;
-            } else if char == '/' {
-                regexparse = true;
-            } else if char == '?' {
-                if parsedrules.len() == 0 {
-                    return Err(ParseEbnfError {
-                        line: currentline,
-                        column: currentcolumn,
-                        errtype: ParseEbnfErrorType::EmptyRule,
-                    });
-                }
-                let lastrule = parsedrules.pop().expect("");
-
-                parsedrules.push(EbnfStatement::Optional {
-                    rule: Box::new(lastrule),
-                });
-                matchstart = i + 1;
-            } else if char == '*' {
-                if parsedrules.len() == 0 {
-                    return Err(ParseEbnfError {
-                        line: currentline,
-                        column: currentcolumn,
-                        errtype: ParseEbnfErrorType::EmptyRule,
-                    });
-                }
-                let lastrule = parsedrules.pop().expect("");
-
-                parsedrules.push(EbnfStatement::ZeroOrMore {
-                    rule: Box::new(lastrule),
-                });
-                matchstart = i + 1;
-            } else if char == '+' {
-                if parsedrules.len() == 0 {
-                    return Err(ParseEbnfError {
-                        line: currentline,
-                        column: currentcolumn,
-                        errtype: ParseEbnfErrorType::EmptyRule,
-                    });
-                }
-                let lastrule = parsedrules.pop().expect("");
-
-                parsedrules.push(EbnfStatement::OneOrMore {
-                    rule: Box::new(lastrule),
-                });
-                matchstart = i + 1;
-            } else if char == '|' {
-                let left: Box<EbnfStatement> = match parsedrules.len() {
-                    0 => {
-                        return Err(ParseEbnfError {
-                            line: currentline,
-                            column: currentcolumn,
-                            errtype: ParseEbnfErrorType::EmptyRule,
-                        });
-                    }
-                    1 => Box::new(parsedrules.pop().expect("")),
-                    _ => {
-                        let mut concatrules: Vec<Box<EbnfStatement>> = Vec::new();
-                        while parsedrules.len() > 0 {
-                            concatrules.push(Box::new(parsedrules.remove(0)));
-                        }
-                        Box::new(EbnfStatement::Concatenation { rules: concatrules })
-                    }
-                };
-                let right: Box<EbnfStatement> = Box::new(EbnfStatement::new(
-                    &s[i + 1..],
-                    currentline,
-                    currentcolumn + 1,
-                )?);
-                return Ok(EbnfStatement::Or {
-                    left: left,
-                    right: right,
-                });
-            } else if char == ';' {
-                break;
-            }
-
-            if char == '\n' {
-                currentcolumn = 0;
-                currentline += 1;
-            }
-
-            currentcolumn += 1;
+//prints a TextMate grammar for the %highlight tags
+fn tmlanguage(path: &str, name: &str) -> i32 {
+    let Some(grammar) = readgrammar(path) else {
+        return 2;
+    };
+    match EbnfParser::from_str(&grammar) {
+        Ok(parser) => {
+            print!("{}", parser.tmlanguage(name));
+            0
         }
-
-        if stringparse {
-            return Err(ParseEbnfError {
-                line: currentline,
-                column: currentcolumn,
-                errtype: ParseEbnfErrorType::UnclosedString,
-            });
+        Err(err) => {
+            println!("{err}");
+            2
         }
+    }
+}
 
-        if bracketparse {
-            return Err(ParseEbnfError {
-                line: currentline,
-                column: currentcolumn,
-                errtype: ParseEbnfErrorType::UnclosedParen,
-            });
+//prints the shortest ambiguous example of every rule, the exit code is 1 if there is one
+fn ambiguities(path: &str, rule: &str, maxlength: &str) -> i32 {
+    let Some(grammar) = readgrammar(path) else {
+        return 2;
+    };
+    let Ok(maxlength) = maxlength.parse::<usize>() else {
+        println!("Expected a number for the length");
+        return 2;
+    };
+    let parser = match EbnfParser::from_str(&grammar) {
+        Ok(parser) => parser,
+        Err(err) => {
+            println!("{err}");
+            return 2;
         }
-
-        if matchstart < s.len() {
-            parsedrules.push(EbnfStatement::DefinedRule {
-                rulename: &s[matchstart..],
-            });
+    };
+    match parser.ambiguities(rule, maxlength) {
+        Ok(found) if found.is_empty() => {
+            println!("No ambiguous input of up to {maxlength} tokens");
+            0
         }
-
-        if parsedrules.len() == 1 {
-            let rule = parsedrules.pop();
-            return Ok(rule.expect("This is not supposed to happen"));
-        } else if parsedrules.len() > 0 {
-            let mut concatrules: Vec<Box<EbnfStatement>> = Vec::new();
-            while parsedrules.len() > 0 {
-                concatrules.push(Box::new(parsedrules.remove(0)));
+        Ok(found) => {
+            for ambiguity in found {
+                print!("{ambiguity}");
             }
-            return Ok(EbnfStatement::Concatenation { rules: concatrules });
-        } else {
-            return Err(ParseEbnfError {
-                line: currentline,
-                column: currentcolumn,
-                errtype: ParseEbnfErrorType::EmptyRule,
-            });
+            1
+        }
+        Err(err) => {
+            println!("{err}");
+            2
         }
     }
 }
 
-impl<'parser: 'partials, 'input, 'startrule, 'partials> EbnfParser<'parser, 'partials> {
-    fn from_str(s: &'parser str) -> Result<Self, ParseEbnfError> {
-        let mut parsename = false;
-        let mut parsebody = false;
-        let mut matchstart = 0;
-        let mut currentline = 0;
-        let mut currentcolumn = 0;
-        let mut rulename = "";
-        let mut parsedstatements: HashMap<String, EbnfStatement> = HashMap::new();
-        let mut i = 0;
-
-        let mut parsedrules: Vec<EbnfStatement> = Vec::new();
-        let mut stringparse = false;
-        let mut escaped = false;
-        let mut bracketparse = false;
-        let mut bracketlevel = 0;
-        let mut regexparse = false;
-        let mut out: Option<EbnfStatement> = None;
-        let mut outfound = false;
-
-        for char in s.chars() {
-            //println!("{currentcolumn}, {char}");
-            if parsename {
-                if char == ':' {
-                    rulename = &s[matchstart..i];
-                    parsename = false;
-                    parsebody = true;
-                    parsedrules = Vec::new();
-                    out = None;
-                    outfound = false;
-                    matchstart = i + 1;
-                } else if !char.is_alphanumeric() {
-                    return Err(ParseEbnfError {
-                        line: currentline,
-                        column: currentcolumn,
-                        errtype: ParseEbnfErrorType::UnexpectedCharacter(char),
-                    });
-                }
-                currentcolumn += 1;
-            } else if parsebody {
-                //parsing strings
-                if escaped {
-                    escaped = false;
-                } else if stringparse {
-                    if char == '"' {
-                        stringparse = false;
-                        parsedrules.push(EbnfStatement::StringTerminal {
-                            string: &s[matchstart + 1..i],
-                        });
-                        matchstart = i + 1;
-                    } else if char == '\n' {
-                        return Err(ParseEbnfError {
-                            line: currentline,
-                            column: currentcolumn,
-                            errtype: ParseEbnfErrorType::UnexpectedCharacter(char.to_owned()),
-                        });
-                    } else if char == '\\' {
-                        escaped = true;
-                    }
-                }
-                //parsing brackets
-                else if bracketparse {
-                    if char == '(' {
-                        bracketlevel += 1;
-                    }
-                    if char == ')' {
-                        bracketlevel -= 1;
-                        if bracketlevel == 0 {
-                            bracketparse = false;
-                            parsedrules.push(EbnfStatement::new(
-                                &s[matchstart + 1..i],
-                                currentline,
-                                currentcolumn,
-                            )?);
-                            matchstart = i + 1;
-                        }
-                    }
-                }
-                //parsing regex
-                else if regexparse {
-                    if char == '/' {
-                        regexparse = false;
-                        parsedrules.push(EbnfStatement::RegexTerminal {
-                            string: &s[matchstart + 1..i],
-                        });
-                        matchstart = i + 1;
-                    }
-                }
-                //parsing regular rule names, split by space, newline or tab
-                else if [' ', '\n', '\t'].contains(&char) {
-                    if matchstart != i {
-                        parsedrules.push(EbnfStatement::DefinedRule {
-                            rulename: &s[matchstart..i],
-                        });
-                    }
-                    matchstart = i + 1;
-                } else if char == '"' {
-                    stringparse = true;
-                } else if char == '(' {
-                    bracketparse = true;
-                    bracketlevel = 1;
-                } else if char == '/' {
-                    regexparse = true;
-                } else if char == '?' {
-                    if parsedrules.len() == 0 {
-                        return Err(ParseEbnfError {
-                            line: currentline,
-                            column: currentcolumn,
-                            errtype: ParseEbnfErrorType::EmptyRule,
-                        });
-                    }
-                    let lastrule = parsedrules.pop().expect("");
-
-                    parsedrules.push(EbnfStatement::Optional {
-                        rule: Box::new(lastrule),
-                    });
-                    matchstart = i + 1;
-                } else if char == '*' {
-                    if parsedrules.len() == 0 {
-                        return Err(ParseEbnfError {
-                            line: currentline,
-                            column: currentcolumn,
-                            errtype: ParseEbnfErrorType::EmptyRule,
-                        });
-                    }
-                    let lastrule = parsedrules.pop().expect("");
-
-                    parsedrules.push(EbnfStatement::ZeroOrMore {
-                        rule: Box::new(lastrule),
-                    });
-                    matchstart = i + 1;
-                } else if char == '+' {
-                    if parsedrules.len() == 0 {
-                        return Err(ParseEbnfError {
-                            line: currentline,
-                            column: currentcolumn,
-                            errtype: ParseEbnfErrorType::EmptyRule,
-                        });
-                    }
-                    let lastrule = parsedrules.pop().expect("");
-
-                    parsedrules.push(EbnfStatement::OneOrMore {
-                        rule: Box::new(lastrule),
-                    });
-                    matchstart = i + 1;
-                } else if char == '|' {
-                    let left: Box<EbnfStatement> = match parsedrules.len() {
-                        0 => {
-                            return Err(ParseEbnfError {
-                                line: currentline,
-                                column: currentcolumn,
-                                errtype: ParseEbnfErrorType::EmptyRule,
-                            });
-                        }
-                        1 => Box::new(parsedrules.pop().expect("")),
-                        _ => {
-                            let mut concatrules: Vec<Box<EbnfStatement>> = Vec::new();
-                            while parsedrules.len() > 0 {
-                                concatrules.push(Box::new(parsedrules.remove(0)));
-                            }
-                            Box::new(EbnfStatement::Concatenation { rules: concatrules })
-                        }
-                    };
-                    let right: Box<EbnfStatement> = Box::new(EbnfStatement::new(
-                        &s[i + 1..],
-                        currentline,
-                        currentcolumn + 1,
-                    )?);
-                    out = Some(EbnfStatement::Or {
-                        left: left,
-                        right: right,
-                    });
-                    outfound = true;
-                } else if char == ';' {
-                    parsebody = false;
-                }
-
-                if char == '\n' {
-                    currentcolumn = 0;
-                    currentline += 1;
-                }
-
-                currentcolumn += 1;
-
-                if parsebody == false {
-                    if matchstart < i {
-                        parsedrules.push(EbnfStatement::DefinedRule {
-                            rulename: &s[matchstart..i],
-                        });
-                    }
-
-                    if stringparse {
-                        return Err(ParseEbnfError {
-                            line: currentline,
-                            column: currentcolumn,
-                            errtype: ParseEbnfErrorType::UnclosedString,
-                        });
-                    }
-
-                    if bracketparse {
-                        return Err(ParseEbnfError {
-                            line: currentline,
-                            column: currentcolumn,
-                            errtype: ParseEbnfErrorType::UnclosedParen,
-                        });
-                    }
-
-                    if outfound {
-                    } else if parsedrules.len() == 1 {
-                        let rule = parsedrules.pop();
-                        out = Some(rule.expect("This is not supposed to happen"));
-                    } else if parsedrules.len() > 0 {
-                        let mut concatrules: Vec<Box<EbnfStatement>> = Vec::new();
-                        while parsedrules.len() > 0 {
-                            concatrules.push(Box::new(parsedrules.remove(0)));
-                        }
-                        out = Some(EbnfStatement::Concatenation { rules: concatrules });
-                    } else {
-                        return Err(ParseEbnfError {
-                            line: currentline,
-                            column: currentcolumn,
-                            errtype: ParseEbnfErrorType::EmptyRule,
-                        });
-                    }
-                    parsebody = false;
-                    let parserule = match out {
-                        Some(rule) => rule,
-                        None => {
-                            return Err(ParseEbnfError {
-                                line: currentline,
-                                column: currentcolumn,
-                                errtype: ParseEbnfErrorType::EmptyRule,
-                            });
-                        }
-                    };
-                    out = None;
-                    parsedstatements.insert(rulename.to_owned(), parserule);
-                }
-            } else {
-                if char.is_alphanumeric() {
-                    matchstart = i;
-                    parsename = true;
-                }
-                currentcolumn += 1;
-            }
-            if char == '\n' {
-                currentline += 1;
-                currentcolumn = 0;
-            }
-            i += 1;
-        }
-        if parsename {
-            return Err(ParseEbnfError {
-                line: currentline,
-                column: currentcolumn,
-                errtype: ParseEbnfErrorType::EmptyRule,
-            });
+//prints the simplified grammar, it reads back into a parser of the same language
+fn simplifygrammar(path: &str, rule: &str) -> i32 {
+    let Some(grammar) = readgrammar(path) else {
+        return 2;
+    };
+    let parser = match EbnfParser::from_str(&grammar) {
+        Ok(parser) => parser,
+        Err(err) => {
+            println!("{err}");
+            return 2;
         }
-
-        if parsebody {
-            return Err(ParseEbnfError {
-                line: currentline,
-                column: currentcolumn,
-                errtype: ParseEbnfErrorType::UnclosedRule,
-            });
+    };
+    match parser.simplify(rule) {
+        Ok(simplified) => {
+            print!("{}", simplified.togrammar(rule));
+            0
         }
-
-        Ok(EbnfParser {
-            currentline: 0,
-            currentcolumn: 0,
-            rules: parsedstatements,
-            charnum: 0,
-            partialparses: Vec::new(),
-        })
-    }
-
-    fn parse(
-        &'parser mut self,
-        input: &'input str,
-        startrule: &'startrule str,
-    ) -> Result<(), ParseCodeError> {
-        let startstatement = self.rules.get(startrule);
-        match startstatement {
-            Some(rule) => {
-                self.partialparses.push(Box::new(EbnfPartial {
-                    currentchar: 0,
-                    starttoken: 0,
-                    currenttoken: 0,
-                    isterminal: false,
-                    statement: rule,
-                }));
-            }
-            None => {
-                return Err(ParseCodeError {
-                    errtype: ParseCodeErrorType::UnknownRule,
-                    line: 0,
-                    column: 0,
-                });
-            }
-        };
-
-        for statement in &mut self.partialparses {
-            statement.trymatch(input);
-            println!("{}", *statement);
+        Err(err) => {
+            println!("{err}");
+            2
         }
-
-        Ok(())
     }
 }
 
-impl<'a> EbnfPartial<'a> {
-    fn trymatch(&mut self, s: &str) {
-        let tomatch = &s[self.currentchar as usize..];
-        match self.statement {
-            EbnfStatement::StringTerminal { string } => {
-                println!("matching string");
-                if tomatch.starts_with(string) {
-                    println!("matched {}", string);
-                    self.currenttoken += 1;
-                    self.currentchar += string.len() as i64;
-                }
+//prints what can follow the text, the rules it is inside of first
+fn completetext(path: &str, rule: &str, text: &str) -> i32 {
+    let Some(grammar) = readgrammar(path) else {
+        return 2;
+    };
+    let parser = match EbnfParser::from_str(&grammar) {
+        Ok(parser) => parser,
+        Err(err) => {
+            println!("{err}");
+            return 2;
+        }
+    };
+    match parser.completions(text, rule) {
+        Ok(completion) => {
+            let rules: Vec<String> = completion
+                .rules
+                .iter()
+                .map(|(rulename, start)| format!("{rulename}@{start}"))
+                .collect();
+            println!("in {}", rules.join(" > "));
+            for string in &completion.strings {
+                println!("\"{string}\"");
             }
-            EbnfStatement::RegexTerminal { string } => {
-                println!("matching regex /{}/", string);
-                let reg = Regex::new(format!("^({})", string).as_str());
-                match reg {
-                    Err(_) => {
-                        println!("invalid Regex");
-                        return;
-                    }
-                    Ok(exp) => {
-                        let matchtoken = exp.captures(tomatch);
-                        match matchtoken {
-                            Some(captures) => {
-                                println!("matched {}", &captures[1]);
-                                self.currenttoken += 1;
-                                self.currentchar += captures[1].len() as i64;
-                            }
-                            None => {
-                                println!("no match")
-                            }
-                        }
-                    }
-                }
+            for pattern in &completion.patterns {
+                println!("{pattern}");
             }
-            _ => {}
+            if completion.complete {
+                println!("end of input");
+            }
+            0
+        }
+        Err(err) => {
+            println!("{err}");
+            1
         }
     }
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if let [_, command, path] = args.as_slice()
+        && command == "test"
+    {
+        std::process::exit(testgrammar(path));
+    }
+    if let [_, command, path, rule, rest @ ..] = args.as_slice()
+        && command == "generate"
+    {
+        let count = rest.first().map_or("10", String::as_str);
+        let seed = rest.get(1).map_or("0", String::as_str);
+        std::process::exit(generatesentences(path, rule, count, seed));
+    }
+    if let [_, command, path, rule, directory] = args.as_slice()
+        && command == "coverage"
+    {
+        std::process::exit(coveragereport(path, rule, directory));
+    }
+    if let [_, command, path, rule, file, rest @ ..] = args.as_slice()
+        && command == "highlight"
+    {
+        let format = rest.first().map_or("ansi", String::as_str);
+        std::process::exit(highlightfile(path, rule, file, format));
+    }
+    if let [_, command, path, name] = args.as_slice()
+        && command == "tmlanguage"
+    {
+        std::process::exit(tmlanguage(path, name));
+    }
+    if let [_, command, path, rule, rest @ ..] = args.as_slice()
+        && command == "ambiguity"
+    {
+        let maxlength = rest.first().map_or("5", String::as_str);
+        std::process::exit(ambiguities(path, rule, maxlength));
+    }
+    if let [_, command, path, rule] = args.as_slice()
+        && command == "simplify"
+    {
+        std::process::exit(simplifygrammar(path, rule));
+    }
+    if let [_, command, path, rule, text] = args.as_slice()
+        && command == "complete"
+    {
+        std::process::exit(completetext(path, rule, text));
+    }
+
     println!();
 
     let parser = EbnfParser::from_str(
-        "identifier: /[a-zA-Z][0-9a-zA-Z_-]*/; number: /[0-9]+/; assign: identifier \"=\" number;",
+        "identifier: /[a-zA-Z][0-9a-zA-Z_-]*/; number: /[0-9]+/; assign: identifier \"=\" number; %ignore /[ \\t\\n]+/;",
     );
 
     let code = "123   ";
@@ -760,13 +300,29 @@ fn main() {
     match parser {
         Err(err) => {
             println!("{err}");
-            return;
         }
-        Ok(mut parser) => {
+        Ok(parser) => {
             for (name, rule) in &parser.rules {
                 println!("{}: {}", name, rule)
             }
-            parser.parse(code, "number");
+            //prefer the table driven parsers, the general one handles any grammar
+            let parsed = match parser.ll1("number") {
+                Ok(predictive) => predictive.parse(code),
+                Err(err) => {
+                    println!("{err}");
+                    match parser.lalr("number") {
+                        Ok(shiftreduce) => shiftreduce.parse(code),
+                        Err(err) => {
+                            println!("{err}");
+                            parser.parse(code, "number")
+                        }
+                    }
+                }
+            };
+            match parsed {
+                Err(err) => println!("{err}"),
+                Ok(tree) => print!("{tree}"),
+            }
         }
     }
 }
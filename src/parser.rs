@@ -0,0 +1,1669 @@
+use regex::Regex;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet, VecDeque},
+    fmt, io,
+    ops::Range,
+    rc::Rc,
+};
+
+use crate::grammar::{EbnfAssociativity, EbnfHighlight, EbnfStatement};
+use crate::input::EbnfInput;
+use crate::table::{EbnfMap, EbnfSet, EbnfTable, alternative, compiled};
+use crate::testcase::EbnfTest;
+use crate::trace::ParseObserver;
+use crate::tree::EbnfTree;
+
+pub struct EbnfParser<'a> {
+    pub rules: HashMap<String, EbnfStatement<'a>>,
+    pub ignore: Vec<EbnfStatement<'a>>,
+    pub sync: Vec<EbnfStatement<'a>>,
+    pub precedence: Vec<(EbnfAssociativity, Vec<EbnfStatement<'a>>)>,
+    //compiled regex terminals by pattern, shared so that their match caches are too
+    pub(crate) regexes: RefCell<HashMap<String, Rc<Regex>>>,
+    //told about every step of the Earley parses, if set
+    pub observer: Option<Rc<RefCell<dyn ParseObserver>>>,
+    //rules written as ?rule, replaced by their only child when transformed
+    pub inline: HashSet<String>,
+    //rules written as !rule, their string tokens are not filtered when transformed
+    pub keeptokens: HashSet<String>,
+    //`-> alias` names by rule and top-level alternative
+    pub aliases: HashMap<(String, usize), String>,
+    //examples from %test and %test-fail
+    pub tests: Vec<EbnfTest<'a>>,
+    //%highlight categories of terminals and rules
+    pub highlights: Vec<(EbnfHighlight, Vec<EbnfStatement<'a>>)>,
+    //rule templates by name with their parameters and body, expanded where they are used
+    pub templates: HashMap<String, (Vec<&'a str>, EbnfStatement<'a>)>,
+    //words from %reserved, see wholeword
+    pub reserved: Vec<&'a str>,
+}
+
+//the token partial recovery completes open partials with
+static SKIPPED: EbnfStatement = EbnfStatement::Empty;
+
+//an Earley item, statements are ids of the chart's table and positions byte offsets
+pub struct EbnfPartial {
+    pub starttoken: u32,
+    pub currenttoken: u32,
+    pub alternative: u32,
+    pub(crate) statement: u32,
+    pub isterminal: bool,
+    pub currentchar: u32,
+    pub error: bool,
+    pub previous: Option<u32>,
+    pub child: Option<u32>,
+}
+
+//a partial with its statement, as observers get to see it
+pub struct EbnfItem<'s> {
+    pub partial: &'s EbnfPartial,
+    pub statement: &'s EbnfStatement<'s>,
+}
+
+pub struct ParseCodeError {
+    pub line: i32,
+    pub column: i32,
+    pub errtype: ParseCodeErrorType,
+}
+
+pub enum ParseCodeErrorType {
+    InvalidRegex,
+    UnknownRule,
+    UnexpectedToken {
+        found: String,
+        expected: Vec<String>,
+    },
+    UnexpectedEof {
+        expected: Vec<String>,
+    },
+    //'&' and '!' in a grammar for the LL(1) or LALR(1) tables or the generated parser, only
+    //the Earley and PEG parsers understand them
+    UnsupportedPredicate(String),
+    //reading a streamed input failed, including input that is not UTF-8
+    Read(io::Error),
+    //no action for a rule or alias that does not have exactly one child, see EbnfActions
+    UnknownAction(String),
+    //an edit for `reparse` that is not within the input or splits a character
+    InvalidEdit(Range<usize>),
+}
+
+pub(crate) struct EbnfChart<'a> {
+    //where the start rule begins, after leading ignored input
+    start: u32,
+    //the statement of the partials for the start rule
+    root: u32,
+    table: EbnfTable<'a>,
+    partialparses: Vec<EbnfPartial>,
+    columns: Vec<EbnfColumn>,
+    //text of the scanned tokens by where it starts in `tokens`, the input may no longer be
+    //around when the tree is built
+    tokens: String,
+    texts: EbnfMap<u32, u32>,
+    //the furthest past its column that a scan, with the ignored input after the token, and a
+    //lookahead read, an edit only changes what was read this close before it
+    scanned: u32,
+    lookahead: u32,
+    //partials `reuse` dropped, they stay in `partialparses` until `compact`
+    dropped: usize,
+    //the furthest position a partial was added at
+    frontier: u32,
+}
+
+//the chart after an edit, as `reuse` took it off. the parse after the edit splices it back in
+//where it matches it again
+pub(crate) struct EbnfSuffix {
+    //the old columns from the cut on, their partials stay in `partialparses` with the
+    //positions of the old input
+    columns: Vec<EbnfColumn>,
+    cut: u32,
+    //the edited old input and how much later the positions after it are now
+    edit: Range<u32>,
+    shift: i64,
+}
+
+impl EbnfSuffix {
+    //where an old position is now, None inside the edit and where it inserted, since that
+    //position is now both before and after the inserted input
+    fn moved(&self, position: u32) -> Option<u32> {
+        if position <= self.edit.start && position < self.edit.end {
+            Some(position)
+        } else if position >= self.edit.end && position > self.edit.start {
+            Some((position as i64 + self.shift) as u32)
+        } else {
+            None
+        }
+    }
+}
+
+//the end of a partial that `reuse` dropped
+const DROPPED: u32 = u32::MAX;
+
+//the partials ending at one position, and what the parse looks up by it
+#[derive(Default)]
+struct EbnfColumn {
+    partials: Vec<u32>,
+    known: EbnfSet<u128>,
+    //partials with the statement they wait for, completion only advances these. a list and
+    //not a map by statement, which was most of the allocations of a parse
+    waiting: Vec<(u32, u32)>,
+    //partials that completed without consuming input, by statement
+    nullable: EbnfMap<u32, u32>,
+    //scanned terminals by statement, None if the terminal did not match
+    terminals: EbnfMap<u32, Option<u32>>,
+    //where the ignored input after the position ends
+    skipped: Option<u32>,
+    //where the input the lookaheads evaluated at the position read ends
+    reach: u32,
+    //the partials added before the position was processed, the ones after it were added by
+    //later positions
+    created: u32,
+    //no partial added before the position was processed ends after it
+    settled: bool,
+}
+
+impl fmt::Display for ParseCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.errtype {
+            ParseCodeErrorType::InvalidRegex => {
+                write!(
+                    f,
+                    "Invalid Regex at line {}, column {}",
+                    self.line, self.column
+                )
+            }
+            ParseCodeErrorType::UnknownRule => {
+                write!(
+                    f,
+                    "Unknown Rule at line {}, column {}",
+                    self.line, self.column
+                )
+            }
+            ParseCodeErrorType::UnexpectedToken { found, expected } => {
+                write!(
+                    f,
+                    "Unexpected '{found}' at line {}, column {}, expected one of: {}",
+                    self.line,
+                    self.column,
+                    expected.join(", ")
+                )
+            }
+            ParseCodeErrorType::UnexpectedEof { expected } => {
+                write!(
+                    f,
+                    "Unexpected end of input at line {}, column {}, expected one of: {}",
+                    self.line,
+                    self.column,
+                    expected.join(", ")
+                )
+            }
+            ParseCodeErrorType::Read(err) => {
+                write!(
+                    f,
+                    "Could not read the input at line {}, column {}: {err}",
+                    self.line, self.column
+                )
+            }
+            ParseCodeErrorType::UnknownAction(name) => {
+                write!(
+                    f,
+                    "No action for '{name}' at line {}, column {}",
+                    self.line, self.column
+                )
+            }
+            ParseCodeErrorType::InvalidEdit(edit) => {
+                write!(
+                    f,
+                    "Invalid edit {}..{} at line {}, column {}",
+                    edit.start, edit.end, self.line, self.column
+                )
+            }
+            ParseCodeErrorType::UnsupportedPredicate(predicate) => {
+                write!(
+                    f,
+                    "Lookahead '{predicate}' needs the Earley or PEG parser at line {}, column {}",
+                    self.line, self.column
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ParseCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+impl<'s> fmt::Display for EbnfItem<'s> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let statement = &self.statement;
+        let dot = self.partial.currenttoken;
+        if let EbnfStatement::Concatenation { rules } = statement {
+            let mut out = String::new();
+            for (i, rule) in rules.iter().enumerate() {
+                if i as u32 == dot {
+                    out += "°";
+                }
+                out.push_str(format!("{rule} ").as_str());
+            }
+            if dot as usize == rules.len() {
+                out += "°";
+            }
+            write!(f, "{out}")
+        } else if dot == 0 {
+            write!(f, "° {statement}")
+        } else {
+            write!(f, "{statement}°")
+        }
+    }
+}
+
+impl ParseCodeError {
+    pub(crate) fn unexpected(input: &str, position: i64, expected: Vec<String>) -> Self {
+        EbnfInput::new(input).unexpected(position, expected)
+    }
+
+    pub(crate) fn at(input: &str, position: i64, errtype: ParseCodeErrorType) -> Self {
+        EbnfInput::new(input).error(position, errtype)
+    }
+}
+
+impl<'a> EbnfChart<'a> {
+    fn new(table: EbnfTable<'a>, root: u32, length: usize, start: u32) -> Self {
+        EbnfChart {
+            start,
+            root,
+            table,
+            partialparses: Vec::new(),
+            columns: (0..=length).map(|_| EbnfColumn::default()).collect(),
+            tokens: String::new(),
+            texts: EbnfMap::default(),
+            scanned: 0,
+            lookahead: 0,
+            dropped: 0,
+            frontier: start,
+        }
+    }
+
+    //keeps the part of the chart that only depends on the input before an edit, for an input
+    //that is now `length` bytes long. None if nothing can be kept, otherwise the cut, the
+    //columns before it whose terminals have to be scanned again and the chart after the edit
+    pub(crate) fn reuse(
+        &mut self,
+        edit: Range<u32>,
+        length: usize,
+    ) -> Option<(u32, Vec<u32>, EbnfSuffix)> {
+        let position = edit.start;
+        //lookaheads that read the edited input are evaluated again, so the cut goes back to
+        //before the first of them
+        let limit = (position.saturating_sub(self.lookahead)..=position)
+            .find(|&column| self.columns[column as usize].reach > position)
+            .unwrap_or(position);
+        //the token before the edit is scanned again, ignored input after it may have changed
+        let cut = (self.start + 1..=limit)
+            .rev()
+            .find(|&column| self.columns[column as usize].skipped.is_some())?;
+
+        //the old columns keep their partials where they are until the parse after the edit
+        //either splices them back in or drops them
+        let suffix = EbnfSuffix {
+            shift: length as i64 - (self.columns.len() - 1) as i64,
+            columns: self.columns.split_off(cut as usize),
+            cut,
+            edit,
+        };
+
+        //only the columns this close to the cut scanned input past it
+        let first = cut.saturating_sub(self.scanned);
+        let mut rescan = Vec::new();
+        for position in first..cut {
+            let column = &mut self.columns[position as usize];
+            if column.skipped.is_some_and(|next| next >= cut) {
+                column.skipped = None;
+            }
+            //the tokens scanned again from here may end after the position
+            column.settled = false;
+        }
+        for position in first..cut {
+            let terminals = &self.columns[position as usize].terminals;
+            let stale = terminals.iter().any(|(&symbol, token)| match token {
+                //tokens are scanned again if they, or the ignored input after them, reach
+                //the cut
+                Some(token) => {
+                    let partial = &self.partialparses[*token as usize];
+                    partial.currentchar >= cut
+                        || partial.isterminal
+                            && self.columns[partial.currentchar as usize].skipped.is_none()
+                }
+                //a string that did not match is tried again if it reached the cut, regexes
+                //are taken to fail before the end of the next token
+                None => match self.table.statement(symbol) {
+                    EbnfStatement::StringTerminal { string } => {
+                        position as usize + string.len() >= cut as usize
+                    }
+                    _ => false,
+                },
+            });
+            //the other terminals there may have read as far, like a regex rejecting a
+            //reserved word, so all of them are scanned again
+            if stale {
+                let terminals = std::mem::take(&mut self.columns[position as usize].terminals);
+                for token in terminals.into_values().flatten() {
+                    let partial = &mut self.partialparses[token as usize];
+                    if partial.currentchar >= cut {
+                        partial.currentchar = DROPPED;
+                        self.dropped += 1;
+                    }
+                }
+                rescan.push(position);
+            }
+        }
+        self.columns.resize_with(length + 1, EbnfColumn::default);
+        self.frontier = cut;
+        Some((cut, rescan, suffix))
+    }
+
+    //splices the chart after the edit back in at `position` if the parse matches it again
+    //there: before the position is processed it has the partials of the old position, no
+    //partial added before either position ends after it, and the partials of the old position
+    //started before the cut, at the position or at a position that has the same partials in
+    //both charts, as do the partials there. the columns after it then only depend on those
+    //and the input after the edit, which did not change
+    pub(crate) fn splice(&mut self, suffix: &mut EbnfSuffix, position: u32) -> bool {
+        let old = position as i64 - suffix.shift;
+        let Some(offset) = (old - suffix.cut as i64)
+            .try_into()
+            .ok()
+            .filter(|&offset: &usize| offset < suffix.columns.len())
+        else {
+            return false;
+        };
+        let old = old as u32;
+        let column = &suffix.columns[offset];
+        if old < suffix.edit.end
+            || self.columns[position as usize].partials.is_empty()
+            || !column.settled
+            || self.frontier > position
+        {
+            return false;
+        }
+        //the old partials the spliced ones point to by the new ones that are the same
+        let mut remap = EbnfMap::default();
+        let kernel = column
+            .partials
+            .iter()
+            .take_while(|&&index| index < column.created);
+        if !self.same(suffix, position, kernel, &mut remap) {
+            return false;
+        }
+        //the partials spliced in complete the ones waiting where they started, and those
+        //complete the ones where they started
+        let cut = suffix.cut;
+        let starts = |column: &EbnfColumn, below: u32| {
+            column
+                .partials
+                .iter()
+                .map(|&index| self.partialparses[index as usize].starttoken)
+                .filter(move |&start| start >= cut && start < below)
+                .collect::<Vec<_>>()
+        };
+        let mut pending = starts(column, old);
+        let mut seen = EbnfSet::default();
+        while let Some(start) = pending.pop() {
+            if !seen.insert(start) {
+                continue;
+            }
+            let Some(now) = suffix.moved(start) else {
+                return false;
+            };
+            let column = &suffix.columns[(start - suffix.cut) as usize];
+            if !self.same(suffix, now, column.partials.iter(), &mut remap) {
+                return false;
+            }
+            pending.extend(starts(column, start));
+        }
+
+        let mut columns = std::mem::take(&mut suffix.columns).into_iter();
+        for column in columns.by_ref().take(offset) {
+            self.release(&column);
+        }
+        self.columns.truncate(position as usize);
+        //the spliced positions are after the edit even where it only inserted
+        let shift = suffix.shift;
+        let shifted = |at: u32| {
+            if at >= old {
+                (at as i64 + shift) as u32
+            } else {
+                suffix.moved(at).unwrap_or(position)
+            }
+        };
+        for mut column in columns {
+            for &index in &column.partials {
+                let partial = &mut self.partialparses[index as usize];
+                //only the partials started before the position point to partials before it
+                if partial.starttoken < old {
+                    let remapped = |index: u32| remap.get(&index).copied().unwrap_or(index);
+                    partial.previous = partial.previous.map(remapped);
+                    partial.child = partial.child.map(remapped);
+                }
+                partial.starttoken = shifted(partial.starttoken);
+                partial.currentchar = shifted(partial.currentchar);
+            }
+            //lookaheads that held are partials of the column too
+            for &token in column.terminals.values().flatten() {
+                let partial = &mut self.partialparses[token as usize];
+                if partial.isterminal {
+                    partial.starttoken = shifted(partial.starttoken);
+                    partial.currentchar = shifted(partial.currentchar);
+                }
+            }
+            column.skipped = column.skipped.map(shifted);
+            //unset if no lookahead was evaluated there
+            if column.reach >= old {
+                column.reach = shifted(column.reach);
+            }
+            //only filling a column adds to it, so `known` keeps the keys of the old position
+            self.columns.push(column);
+        }
+        //the new partials of the position replace the old ones it started with
+        let column = &mut self.columns[position as usize];
+        for index in column.partials.iter_mut() {
+            if let Some(&now) = remap.get(index) {
+                self.partialparses[*index as usize].currentchar = DROPPED;
+                self.dropped += 1;
+                *index = now;
+            }
+        }
+        for (_, index) in column.waiting.iter_mut() {
+            *index = remap.get(index).copied().unwrap_or(*index);
+        }
+        self.frontier = self.columns.len() as u32 - 1;
+        true
+    }
+
+    //adds the old partials to `remap` if they are the partials of the column at `position`
+    fn same<'p>(
+        &self,
+        suffix: &EbnfSuffix,
+        position: u32,
+        partials: impl Iterator<Item = &'p u32>,
+        remap: &mut EbnfMap<u32, u32>,
+    ) -> bool {
+        let mut current = EbnfMap::default();
+        for &index in &self.columns[position as usize].partials {
+            current.insert(self.partialparses[index as usize].key(), index);
+        }
+        let mut count = 0;
+        for &index in partials {
+            let partial = &self.partialparses[index as usize];
+            let Some(now) = suffix
+                .moved(partial.starttoken)
+                .and_then(|start| current.get(&partial.keyat(start)))
+            else {
+                return false;
+            };
+            remap.insert(index, *now);
+            count += 1;
+        }
+        count == current.len()
+    }
+
+    //drops what is left of the chart after the edit and compacts once the dropped partials
+    //are most of the chart
+    pub(crate) fn settle(&mut self, suffix: EbnfSuffix) {
+        for column in suffix.columns {
+            self.release(&column);
+        }
+        if self.dropped > self.partialparses.len() / 2 {
+            self.compact();
+        }
+    }
+
+    //drops the partials of a column that is no longer part of the chart and the tokens
+    //scanned there
+    fn release(&mut self, column: &EbnfColumn) {
+        let tokens = column.terminals.values().flatten();
+        for &index in column.partials.iter().chain(tokens) {
+            let partial = &mut self.partialparses[index as usize];
+            if partial.currentchar != DROPPED {
+                partial.currentchar = DROPPED;
+                self.dropped += 1;
+            }
+        }
+    }
+
+    //removes the partials `reuse` dropped once they are most of the chart
+    fn compact(&mut self) {
+        //the number of kept partials before each one, which is the new index of a kept one
+        let mut remap = Vec::with_capacity(self.partialparses.len() + 1);
+        let mut kept = Vec::with_capacity(self.partialparses.len() - self.dropped);
+        let tokens = std::mem::take(&mut self.tokens);
+        let texts = std::mem::take(&mut self.texts);
+        for (index, partial) in std::mem::take(&mut self.partialparses)
+            .into_iter()
+            .enumerate()
+        {
+            remap.push(kept.len() as u32);
+            if partial.currentchar != DROPPED {
+                if let Some(&start) = texts.get(&(index as u32)) {
+                    let start = start as usize;
+                    let end = start + (partial.currentchar - partial.starttoken) as usize;
+                    self.texts
+                        .insert(kept.len() as u32, self.tokens.len() as u32);
+                    self.tokens.push_str(&tokens[start..end]);
+                }
+                kept.push(partial);
+            }
+        }
+        remap.push(kept.len() as u32);
+        for partial in &mut kept {
+            //partials only point back to partials that ended no later than themselves
+            partial.previous = partial.previous.map(|previous| remap[previous as usize]);
+            partial.child = partial.child.map(|child| remap[child as usize]);
+        }
+        self.partialparses = kept;
+        self.dropped = 0;
+
+        let renumber = |index: &mut u32| *index = remap[*index as usize];
+        for column in &mut self.columns {
+            column.partials.iter_mut().for_each(renumber);
+            column
+                .waiting
+                .iter_mut()
+                .for_each(|(_, index)| renumber(index));
+            column.nullable.values_mut().for_each(renumber);
+            column.terminals.values_mut().flatten().for_each(renumber);
+            renumber(&mut column.created);
+        }
+    }
+
+    pub(crate) fn start(&self) -> u32 {
+        self.start
+    }
+
+    //the next column after `position` that has partials to process
+    pub(crate) fn nextcolumn(&self, position: u32) -> Option<u32> {
+        let next = position as usize + 1;
+        let offset = self
+            .columns
+            .get(next..)?
+            .iter()
+            .position(|column| !column.partials.is_empty())?;
+        Some((next + offset) as u32)
+    }
+
+    //the terminals the partials at `position` wait for, with the rule a terminal is the whole
+    //body of
+    pub(crate) fn expected(&self, position: u32) -> Vec<(&'a EbnfStatement<'a>, Option<&'a str>)> {
+        let Some(column) = self.columns.get(position as usize) else {
+            return Vec::new();
+        };
+        column
+            .partials
+            .iter()
+            .filter_map(|&index| {
+                let symbol = match self.symbol(index) {
+                    Ok(Some(symbol)) if self.table.isterminal(symbol) => symbol,
+                    _ => return None,
+                };
+                Some((self.table.statement(symbol), self.wrapper(index)))
+            })
+            .collect()
+    }
+
+    //the rule a terminal is all of, also through the `|` of the rule's body
+    fn wrapper(&self, index: u32) -> Option<&'a str> {
+        let whole = |index: u32| {
+            let partial = &self.partialparses[index as usize];
+            partial.currenttoken == 0
+                && matches!(
+                    self.table.symbol(partial.statement, partial.alternative, 1),
+                    Ok(None)
+                )
+        };
+        let mut current = index;
+        while whole(current) {
+            let partial = &self.partialparses[current as usize];
+            if let EbnfStatement::DefinedRule { rulename } = self.table.statement(partial.statement)
+            {
+                return Some(rulename);
+            }
+            current = self
+                .waiting(partial.starttoken, partial.statement)
+                .find(|&parent| whole(parent))?;
+        }
+        None
+    }
+
+    //the rules started before `position` that the partials waiting for a terminal there are
+    //part of with where they started, by start and then outermost first
+    pub(crate) fn enclosing(&self, position: u32) -> Vec<(&'a str, u32)> {
+        let Some(column) = self.columns.get(position as usize) else {
+            return Vec::new();
+        };
+        let mut pending: VecDeque<(u32, u32)> = column
+            .partials
+            .iter()
+            .filter(|&&index| {
+                matches!(self.symbol(index), Ok(Some(symbol)) if self.table.isterminal(symbol))
+            })
+            .map(|&index| (index, 0))
+            .collect();
+        let mut seen = EbnfSet::default();
+        //with how many partials away from a terminal they are, the outer ones are further
+        let mut rules = Vec::new();
+        while let Some((index, distance)) = pending.pop_front() {
+            if !seen.insert(index) {
+                continue;
+            }
+            let partial = &self.partialparses[index as usize];
+            if let EbnfStatement::DefinedRule { rulename } = self.table.statement(partial.statement)
+                && partial.starttoken < position
+            {
+                rules.push((*rulename, partial.starttoken, distance));
+            }
+            //the partials that predicted this one
+            pending.extend(
+                self.waiting(partial.starttoken, partial.statement)
+                    .map(|parent| (parent, distance + 1)),
+            );
+        }
+        rules.sort_by_key(|&(_, start, distance)| (start, std::cmp::Reverse(distance)));
+        rules
+            .into_iter()
+            .map(|(rulename, start, _)| (rulename, start))
+            .collect()
+    }
+
+    //the partials at `position` waiting for `statement`
+    fn waiting(&self, position: u32, statement: u32) -> impl Iterator<Item = u32> + '_ {
+        self.columns[position as usize]
+            .waiting
+            .iter()
+            .filter(move |&&(symbol, _)| symbol == statement)
+            .map(|&(_, index)| index)
+    }
+
+    //the statement after the dot, None if the partial is complete
+    fn symbol(&self, index: u32) -> Result<Option<u32>, ParseCodeErrorType> {
+        let partial = &self.partialparses[index as usize];
+        if partial.error {
+            return Ok(None);
+        }
+        self.table
+            .symbol(partial.statement, partial.alternative, partial.currenttoken)
+    }
+
+    fn item(&self, index: u32) -> EbnfItem<'_> {
+        let partial = &self.partialparses[index as usize];
+        EbnfItem {
+            partial,
+            statement: self.table.statement(partial.statement),
+        }
+    }
+
+    pub(crate) fn partial(&self, index: u32) -> &EbnfPartial {
+        &self.partialparses[index as usize]
+    }
+
+    pub(crate) fn statement(&self, index: u32) -> &'a EbnfStatement<'a> {
+        self.table
+            .statement(self.partialparses[index as usize].statement)
+    }
+
+    //the top-level alternative of the rule whose body the partial is
+    pub(crate) fn alternative(&self, body: u32) -> usize {
+        alternative(self.statement(body), self.partial(body).alternative)
+    }
+
+    //the text of a scanned token
+    pub(crate) fn text(&self, index: u32) -> &str {
+        let partial = &self.partialparses[index as usize];
+        let start = self.texts[&index] as usize;
+        &self.tokens[start..start + (partial.currentchar - partial.starttoken) as usize]
+    }
+
+    //false if the partial was already in the chart
+    //columns past the known end of a streamed input are added as they are reached
+    fn column(&mut self, position: u32) -> &mut EbnfColumn {
+        let position = position as usize;
+        if position >= self.columns.len() {
+            self.columns.resize_with(position + 1, EbnfColumn::default);
+        }
+        &mut self.columns[position]
+    }
+
+    fn add(&mut self, partial: EbnfPartial) -> bool {
+        let position = partial.currentchar;
+        if !self.column(position).known.insert(partial.key()) {
+            return false;
+        }
+        self.frontier = self.frontier.max(position);
+        let index = self.partialparses.len() as u32;
+        self.partialparses.push(partial);
+        let waiting = match self.symbol(index) {
+            Ok(Some(symbol)) if !self.table.isterminal(symbol) => Some(symbol),
+            _ => None,
+        };
+        let column = &mut self.columns[position as usize];
+        column.partials.push(index);
+        if let Some(symbol) = waiting {
+            column.waiting.push((symbol, index));
+        }
+        true
+    }
+
+    fn advance(&mut self, index: u32, child: u32, position: u32) {
+        let partial = &self.partialparses[index as usize];
+        self.add(EbnfPartial {
+            starttoken: partial.starttoken,
+            currenttoken: partial.currenttoken + 1,
+            alternative: partial.alternative,
+            statement: partial.statement,
+            isterminal: false,
+            currentchar: position,
+            error: false,
+            previous: Some(index),
+            child: Some(child),
+        });
+    }
+
+    fn scan(
+        &mut self,
+        symbol: u32,
+        input: &EbnfInput,
+        position: u32,
+        parser: &EbnfParser,
+    ) -> Result<Option<u32>, ParseCodeError> {
+        if let Some(&token) = self.columns[position as usize].terminals.get(&symbol) {
+            return Ok(token);
+        }
+        let statement = self.table.statement(symbol);
+        parser.notify(|observer| observer.tried(statement, position));
+        let length = matchterminal(
+            statement,
+            input.rest(position as i64),
+            self.table.regex(symbol),
+            &parser.reserved,
+        )
+        .map_err(|errtype| input.error(position as i64, errtype))?;
+        let token = match length {
+            Some(length) => {
+                let end = position + length as u32;
+                let text = input.slice(position as usize, end as usize);
+                parser.notify(|observer| observer.scanned(statement, position, text));
+                let index = self.partialparses.len() as u32;
+                self.texts.insert(index, self.tokens.len() as u32);
+                self.tokens.push_str(text);
+                self.partialparses.push(EbnfPartial {
+                    starttoken: position,
+                    currenttoken: 1,
+                    alternative: 0,
+                    statement: symbol,
+                    isterminal: true,
+                    currentchar: end,
+                    error: false,
+                    previous: None,
+                    child: None,
+                });
+                Some(index)
+            }
+            None => {
+                parser.notify(|observer| observer.failed(statement, position));
+                if let EbnfStatement::StringTerminal { string } = statement {
+                    self.scanned = self.scanned.max(string.len() as u32);
+                }
+                None
+            }
+        };
+        self.columns[position as usize]
+            .terminals
+            .insert(symbol, token);
+        Ok(token)
+    }
+}
+
+impl<'a> EbnfParser<'a> {
+    pub fn parse(&self, input: &str, startrule: &str) -> Result<EbnfTree, ParseCodeError> {
+        self.run(&EbnfInput::new(input), startrule, false)
+            .map(|(tree, _)| tree)
+    }
+
+    //keeps going after syntax errors by skipping ahead to the next %sync token,
+    //the tree contains error nodes for every skipped part of the input
+    pub fn parsewithrecovery(
+        &self,
+        input: &str,
+        startrule: &str,
+    ) -> Result<(EbnfTree, Vec<ParseCodeError>), ParseCodeError> {
+        self.run(&EbnfInput::new(input), startrule, true)
+    }
+
+    fn run(
+        &self,
+        input: &EbnfInput,
+        startrule: &str,
+        recover: bool,
+    ) -> Result<(EbnfTree, Vec<ParseCodeError>), ParseCodeError> {
+        let (rulename, body) = self.startrule(startrule)?;
+        let mut chart = self.startchart(input, body)?;
+        let resume = chart.start;
+        self.complete(&mut chart, input, rulename, resume, recover)
+    }
+
+    pub(crate) fn startrule(
+        &self,
+        startrule: &str,
+    ) -> Result<(&String, &EbnfStatement<'a>), ParseCodeError> {
+        self.rules.get_key_value(startrule).ok_or(ParseCodeError {
+            errtype: ParseCodeErrorType::UnknownRule,
+            line: 0,
+            column: 0,
+        })
+    }
+
+    //the chart starts with the body of the start rule, the root node is added when building the tree
+    pub(crate) fn startchart<'s>(
+        &'s self,
+        input: &EbnfInput,
+        body: &'s EbnfStatement<'s>,
+    ) -> Result<EbnfChart<'s>, ParseCodeError> {
+        let start = self.skip(input, 0)? as u32;
+        let mut table = EbnfTable::new(self, &[&SKIPPED]);
+        let root = table.start(body);
+        let mut chart = EbnfChart::new(table, root, input.end() as usize, start);
+        for alternative in 0..chart.table.alternatives(root) {
+            chart.add(EbnfPartial {
+                starttoken: start,
+                currenttoken: 0,
+                alternative,
+                statement: root,
+                isterminal: false,
+                currentchar: start,
+                error: false,
+                previous: None,
+                child: None,
+            });
+        }
+        Ok(chart)
+    }
+
+    //fills the chart from `resume` to the end of the input and builds the tree
+    pub(crate) fn complete(
+        &self,
+        chart: &mut EbnfChart,
+        input: &EbnfInput,
+        rulename: &str,
+        resume: u32,
+        recover: bool,
+    ) -> Result<(EbnfTree, Vec<ParseCodeError>), ParseCodeError> {
+        let end = input.end() as u32;
+        let mut errors = Vec::new();
+        let mut resume = resume;
+        loop {
+            for position in resume..=end {
+                self.process(chart, input, position)?;
+            }
+            let error = match self.finish(chart, input, rulename) {
+                Ok(tree) => return Ok((tree, errors)),
+                Err(error) => error,
+            };
+            if !recover {
+                return Err(error);
+            }
+            errors.push(error);
+
+            let failed = furthest(chart);
+            if failed == end {
+                self.recovereof(chart, input, end)?;
+                resume = end + 1;
+                continue;
+            }
+            //the error takes the sync token with it, so the parse goes on after it and
+            //the next failure is always further along
+            let sync = self.nextsync(input, failed)?;
+            let next = self.skip(input, sync as i64)? as u32;
+            self.recover(chart, failed, sync, next);
+            resume = next;
+        }
+    }
+
+    pub(crate) fn process(
+        &self,
+        chart: &mut EbnfChart,
+        input: &EbnfInput,
+        position: u32,
+    ) -> Result<(), ParseCodeError> {
+        let created = chart.partialparses.len() as u32;
+        let column = &mut chart.columns[position as usize];
+        if column.created == 0 {
+            column.created = created;
+        }
+        column.settled = chart.frontier <= position;
+        let mut i = 0;
+        while i < chart.columns[position as usize].partials.len() {
+            let index = chart.columns[position as usize].partials[i];
+            i += 1;
+            let symbol = chart
+                .symbol(index)
+                .map_err(|errtype| input.error(position as i64, errtype))?;
+
+            match symbol {
+                //completion, advance every partial that was waiting for this statement
+                None => {
+                    self.notify(|observer| observer.completed(&chart.item(index), position));
+                    let partial = &chart.partialparses[index as usize];
+                    let (statement, start) = (partial.statement, partial.starttoken as usize);
+                    if partial.starttoken == position {
+                        chart.columns[start].nullable.insert(statement, index);
+                    }
+                    let mut j = 0;
+                    while let Some(&(symbol, waiting)) = chart.columns[start].waiting.get(j) {
+                        j += 1;
+                        if symbol == statement {
+                            chart.advance(waiting, index, position);
+                        }
+                    }
+                }
+                Some(symbol) if chart.table.isterminal(symbol) => {
+                    self.scanfrom(chart, input, index, symbol, position)?;
+                }
+                //lookaheads are read as a PEG once per position, one that holds completes
+                //without consuming input and advances everything waiting for it
+                Some(symbol) if chart.table.ispredicate(symbol) => {
+                    let column = &chart.columns[position as usize];
+                    if let Some(&done) = column.nullable.get(&symbol) {
+                        chart.advance(index, done, position);
+                    } else if !column.terminals.contains_key(&symbol) {
+                        let statement = chart.table.statement(symbol);
+                        let (holds, reach) = self
+                            .lookahead(statement, input.rest(position as i64))
+                            .map_err(|error| input.error(position as i64, error.errtype))?;
+                        let column = &mut chart.columns[position as usize];
+                        column.reach = column.reach.max(position + reach as u32);
+                        chart.lookahead = chart.lookahead.max(reach as u32);
+                        let done = holds.then(|| {
+                            chart.add(EbnfPartial {
+                                starttoken: position,
+                                currenttoken: 0,
+                                alternative: 0,
+                                statement: symbol,
+                                isterminal: false,
+                                currentchar: position,
+                                error: false,
+                                previous: None,
+                                child: None,
+                            });
+                            chart.partialparses.len() as u32 - 1
+                        });
+                        chart.columns[position as usize]
+                            .terminals
+                            .insert(symbol, done);
+                    }
+                }
+                //prediction, the alternatives of a statement are always added together
+                Some(symbol) => {
+                    for alternative in 0..chart.table.alternatives(symbol) {
+                        let predicted = EbnfPartial {
+                            starttoken: position,
+                            currenttoken: 0,
+                            alternative,
+                            statement: symbol,
+                            isterminal: false,
+                            currentchar: position,
+                            error: false,
+                            previous: None,
+                            child: None,
+                        };
+                        if !chart.add(predicted) {
+                            break;
+                        }
+                        let predicted = chart.item(chart.partialparses.len() as u32 - 1);
+                        self.notify(|observer| observer.predicted(&predicted, position));
+                    }
+                    if let Some(&done) = chart.columns[position as usize].nullable.get(&symbol) {
+                        chart.advance(index, done, position);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn scanfrom(
+        &self,
+        chart: &mut EbnfChart,
+        input: &EbnfInput,
+        index: u32,
+        symbol: u32,
+        position: u32,
+    ) -> Result<(), ParseCodeError> {
+        if let Some(token) = chart.scan(symbol, input, position, self)? {
+            let end = chart.partialparses[token as usize].currentchar;
+            let next = match chart.column(end).skipped {
+                Some(next) => next,
+                None => {
+                    let next = self.skip(input, end as i64)? as u32;
+                    chart.column(end).skipped = Some(next);
+                    next
+                }
+            };
+            let start = chart.partialparses[token as usize].starttoken;
+            chart.scanned = chart.scanned.max(next - start);
+            chart.advance(index, token, next);
+        }
+        Ok(())
+    }
+
+    //scans again from the columns before the cut whose terminals were dropped by `reuse`
+    pub(crate) fn rescan(
+        &self,
+        chart: &mut EbnfChart,
+        input: &EbnfInput,
+        columns: &[u32],
+    ) -> Result<(), ParseCodeError> {
+        for &position in columns {
+            for index in chart.columns[position as usize].partials.clone() {
+                if let Ok(Some(symbol)) = chart.symbol(index)
+                    && chart.table.isterminal(symbol)
+                {
+                    self.scanfrom(chart, input, index, symbol, position)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    //the tree if the start rule spans the whole input, the syntax error otherwise
+    pub(crate) fn finish(
+        &self,
+        chart: &EbnfChart,
+        input: &EbnfInput,
+        rulename: &str,
+    ) -> Result<EbnfTree, ParseCodeError> {
+        let index = chart.accepted(input)?;
+        let children = buildnode(chart, index, input);
+        let alternative = chart.alternative(index);
+        Ok(rulenode(
+            rulename,
+            alternative,
+            children,
+            chart.start as usize,
+        ))
+    }
+
+    //completes every open partial of the column at `from` as an error spanning up to `to`,
+    //they continue at `next` after the ignored input following the error
+    fn recover(&self, chart: &mut EbnfChart, from: u32, to: u32, next: u32) {
+        let token = chart.partialparses.len() as u32;
+        chart.partialparses.push(EbnfPartial {
+            starttoken: from,
+            currenttoken: 1,
+            alternative: 0,
+            statement: chart.table.id(&SKIPPED),
+            isterminal: true,
+            currentchar: to,
+            error: true,
+            previous: None,
+            child: None,
+        });
+        for index in chart.columns[from as usize].partials.clone() {
+            if !matches!(chart.symbol(index), Ok(Some(_))) {
+                continue;
+            }
+            let partial = &chart.partialparses[index as usize];
+            chart.add(EbnfPartial {
+                starttoken: partial.starttoken,
+                currenttoken: partial.currenttoken,
+                alternative: partial.alternative,
+                statement: partial.statement,
+                isterminal: false,
+                currentchar: next,
+                error: true,
+                previous: Some(index),
+                child: Some(token),
+            });
+        }
+    }
+
+    //closes open partials at the end of the input until the start rule completes
+    fn recovereof(
+        &self,
+        chart: &mut EbnfChart,
+        input: &EbnfInput,
+        end: u32,
+    ) -> Result<(), ParseCodeError> {
+        loop {
+            let before = chart.partialparses.len();
+            self.recover(chart, end, end, end);
+            self.process(chart, input, end)?;
+            if chart.partialparses.len() == before + 1 {
+                break;
+            }
+        }
+        for position in 0..end {
+            self.recover(chart, position, end, end);
+        }
+        self.process(chart, input, end)
+    }
+
+    //where the next sync token from `from` on ends, the end of the input if there is none
+    fn nextsync(&self, input: &EbnfInput, from: u32) -> Result<u32, ParseCodeError> {
+        let rest = input.rest(from as i64);
+        for (offset, _) in rest.char_indices() {
+            let position = from as i64 + offset as i64;
+            for statement in &self.sync {
+                if let Some(length) = self.matchgreedy(statement, input, position)?
+                    && length > 0
+                {
+                    return Ok((position + length) as u32);
+                }
+            }
+        }
+        Ok(input.end() as u32)
+    }
+
+    fn notify(&self, step: impl FnOnce(&mut dyn ParseObserver)) {
+        if let Some(observer) = &self.observer {
+            step(&mut *observer.borrow_mut());
+        }
+    }
+
+    pub(crate) fn skipignored(&self, input: &str, position: i64) -> Result<i64, ParseCodeError> {
+        self.skip(&EbnfInput::new(input), position)
+    }
+
+    pub(crate) fn skip(&self, input: &EbnfInput, position: i64) -> Result<i64, ParseCodeError> {
+        let mut position = position;
+        'skipping: loop {
+            for statement in &self.ignore {
+                if let Some(length) = self.matchgreedy(statement, input, position)?
+                    && length > 0
+                {
+                    position += length;
+                    continue 'skipping;
+                }
+            }
+            return Ok(position);
+        }
+    }
+
+    //greedy match used for ignored and sync terminals, which never need the full chart
+    pub(crate) fn matchgreedy(
+        &self,
+        statement: &EbnfStatement,
+        input: &EbnfInput,
+        position: i64,
+    ) -> Result<Option<i64>, ParseCodeError> {
+        match statement {
+            EbnfStatement::StringTerminal { .. } | EbnfStatement::RegexTerminal { .. } => {
+                let regex = match statement {
+                    EbnfStatement::RegexTerminal { string } => compiled(string, &self.regexes),
+                    _ => None,
+                };
+                let length = matchterminal(
+                    statement,
+                    input.rest(position),
+                    regex.as_deref(),
+                    &self.reserved,
+                )
+                .map_err(|errtype| input.error(position, errtype))?;
+                Ok(length.map(|length| length as i64))
+            }
+            EbnfStatement::DefinedRule { rulename } => match self.rules.get(*rulename) {
+                Some(rule) => self.matchgreedy(rule, input, position),
+                None => Err(input.error(position, ParseCodeErrorType::UnknownRule)),
+            },
+            EbnfStatement::Concatenation { rules } => {
+                let mut length = 0;
+                for rule in rules {
+                    match self.matchgreedy(rule, input, position + length)? {
+                        Some(matched) => length += matched,
+                        None => return Ok(None),
+                    }
+                }
+                Ok(Some(length))
+            }
+            EbnfStatement::Or { left, right } => match self.matchgreedy(left, input, position)? {
+                Some(length) => Ok(Some(length)),
+                None => self.matchgreedy(right, input, position),
+            },
+            EbnfStatement::Optional { rule } => {
+                Ok(Some(self.matchgreedy(rule, input, position)?.unwrap_or(0)))
+            }
+            EbnfStatement::ZeroOrMore { rule } | EbnfStatement::OneOrMore { rule } => {
+                let mut length = 0;
+                let mut repetitions = 0;
+                while let Some(matched) = self.matchgreedy(rule, input, position + length)? {
+                    if matched == 0 {
+                        break;
+                    }
+                    length += matched;
+                    repetitions += 1;
+                }
+                if repetitions == 0 && matches!(statement, EbnfStatement::OneOrMore { .. }) {
+                    return Ok(None);
+                }
+                Ok(Some(length))
+            }
+            EbnfStatement::And { rule } => Ok(self.matchgreedy(rule, input, position)?.map(|_| 0)),
+            EbnfStatement::Not { rule } => Ok(match self.matchgreedy(rule, input, position)? {
+                Some(_) => None,
+                None => Some(0),
+            }),
+            EbnfStatement::Empty => Ok(Some(0)),
+        }
+    }
+}
+
+//the length of the terminal at the start of `rest`, `regex` is the compiled regex terminal
+fn matchterminal(
+    statement: &EbnfStatement,
+    rest: &str,
+    regex: Option<&Regex>,
+    reserved: &[&str],
+) -> Result<Option<usize>, ParseCodeErrorType> {
+    match statement {
+        EbnfStatement::StringTerminal { string } => Ok((rest.starts_with(string)
+            && wholeword(reserved, string, rest))
+        .then_some(string.len())),
+        EbnfStatement::RegexTerminal { .. } => match regex {
+            Some(exp) => Ok(exp
+                .find(rest)
+                .filter(|found| !reserved.contains(&found.as_str()))
+                .map(|found| found.end())),
+            None => Err(ParseCodeErrorType::InvalidRegex),
+        },
+        _ => Ok(None),
+    }
+}
+
+//reserved words only match as whole words, "if" is not the start of "iffy". Regex terminals
+//never match a reserved word
+pub(crate) fn wholeword(reserved: &[&str], string: &str, rest: &str) -> bool {
+    let wordchar = |char: char| char.is_alphanumeric() || char == '_';
+    !reserved.contains(&string)
+        || !string.ends_with(wordchar)
+        || !rest[string.len()..].starts_with(wordchar)
+}
+
+impl<'a> EbnfChart<'a> {
+    //the partial of the start rule body spanning the whole input, the syntax error otherwise
+    pub(crate) fn accepted(&self, input: &EbnfInput) -> Result<u32, ParseCodeError> {
+        accepted(self, self.root, input.end() as u32)
+            .ok_or_else(|| syntaxerror(self, self.root, input))
+    }
+
+    //the partials a partial was advanced over, in input order
+    pub(crate) fn children(&self, index: u32) -> Vec<u32> {
+        let mut children = Vec::new();
+        let mut current = Some(index);
+        while let Some(i) = current {
+            let partial = &self.partialparses[i as usize];
+            if let Some(child) = partial.child {
+                children.push(child);
+            }
+            current = partial.previous;
+        }
+        children.reverse();
+        children
+    }
+}
+
+fn accepted(chart: &EbnfChart, root: u32, end: u32) -> Option<u32> {
+    let column = chart.columns.get(end as usize)?;
+    column.partials.iter().copied().find(|&index| {
+        let partial = &chart.partialparses[index as usize];
+        partial.statement == root
+            && partial.starttoken == chart.start
+            && matches!(chart.symbol(index), Ok(None))
+    })
+}
+
+//reports the furthest position any partial reached and what it would have accepted there
+fn syntaxerror(chart: &EbnfChart, root: u32, input: &EbnfInput) -> ParseCodeError {
+    let position = furthest(chart);
+
+    let mut expected: Vec<String> = Vec::new();
+    for &index in &chart.columns[position as usize].partials {
+        let partial = &chart.partialparses[index as usize];
+        let description = match chart.symbol(index) {
+            //terminals wrapped in a rule are reported by the rule name
+            Ok(Some(symbol)) if chart.table.isterminal(symbol) => {
+                match chart.table.statement(partial.statement) {
+                    EbnfStatement::DefinedRule { rulename } => rulename.to_string(),
+                    _ => chart.table.statement(symbol).to_string(),
+                }
+            }
+            Ok(None) if partial.statement == root && partial.starttoken == chart.start => {
+                "end of input".to_owned()
+            }
+            //a lookahead that did not hold
+            Ok(Some(symbol)) if chart.table.ispredicate(symbol) => {
+                chart.table.statement(symbol).to_string()
+            }
+            _ => continue,
+        };
+        if !expected.contains(&description) {
+            expected.push(description);
+        }
+    }
+    expected.sort();
+    input.unexpected(position as i64, expected)
+}
+
+//a step of building a tree: the nodes of a partial, or a rule taking the nodes built since
+//`offset` as its children
+enum EbnfBuild<'a> {
+    Partial(u32),
+    Rule {
+        rulename: &'a str,
+        alternative: usize,
+        start: usize,
+        offset: usize,
+    },
+}
+
+//tokens and defined rules become tree nodes, every other statement is flattened into its parent.
+//repetitions nest as deep as they are long, so the partials are walked with a stack and every
+//node is moved into its parent once
+fn buildnode(chart: &EbnfChart, index: u32, input: &EbnfInput) -> Vec<EbnfTree> {
+    let mut out = Vec::new();
+    let mut stack = vec![EbnfBuild::Partial(index)];
+    while let Some(step) = stack.pop() {
+        let index = match step {
+            EbnfBuild::Partial(index) => index,
+            EbnfBuild::Rule {
+                rulename,
+                alternative,
+                start,
+                offset,
+            } => {
+                let children = out.split_off(offset);
+                out.push(rulenode(rulename, alternative, children, start));
+                continue;
+            }
+        };
+        let partial = &chart.partialparses[index as usize];
+        let start = partial.starttoken as usize;
+        let end = partial.currentchar as usize;
+        if partial.isterminal && partial.error {
+            out.push(EbnfTree::Error {
+                text: input.slice(start, end).to_owned(),
+                start,
+                end,
+            });
+        } else if partial.isterminal {
+            out.push(EbnfTree::Token {
+                text: chart.text(index).to_owned(),
+                string: matches!(chart.statement(index), EbnfStatement::StringTerminal { .. }),
+                start,
+                end,
+            });
+        } else {
+            let children = chart.children(index);
+            if let EbnfStatement::DefinedRule { rulename } =
+                chart.table.statement(partial.statement)
+            {
+                //the only child of a rule partial is the partial of its body
+                let alternative = children.first().map_or(0, |&body| chart.alternative(body));
+                stack.push(EbnfBuild::Rule {
+                    rulename,
+                    alternative,
+                    start,
+                    offset: out.len(),
+                });
+            }
+            stack.extend(children.into_iter().rev().map(EbnfBuild::Partial));
+        }
+    }
+    out
+}
+
+fn rulenode(
+    rulename: &str,
+    alternative: usize,
+    children: Vec<EbnfTree>,
+    position: usize,
+) -> EbnfTree {
+    let (start, end) = match (children.first(), children.last()) {
+        (Some(first), Some(last)) => (first.start(), last.end()),
+        _ => (position, position),
+    };
+    EbnfTree::Rule {
+        name: rulename.to_string(),
+        alternative,
+        children,
+        start,
+        end,
+    }
+}
+
+impl EbnfPartial {
+    //identifies partials in their column, the same item is never added twice
+    fn key(&self) -> u128 {
+        self.keyat(self.starttoken)
+    }
+
+    //the key the partial has if it started at `starttoken`
+    fn keyat(&self, starttoken: u32) -> u128 {
+        (self.statement as u128) << 96
+            | (starttoken as u128) << 64
+            | (self.currenttoken as u128) << 32
+            | (self.alternative as u128) << 1
+            | self.error as u128
+    }
+}
+
+fn furthest(chart: &EbnfChart) -> u32 {
+    chart
+        .columns
+        .iter()
+        .rposition(|column| !column.partials.is_empty())
+        .unwrap_or(0) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATEMENTS: &str = r#"
+list: item*;
+item: identifier "=" number ";";
+identifier: /[a-z]+/;
+number: /[0-9]+/;
+%ignore /[ ]+/;
+%sync ";";
+"#;
+
+    fn recovered(input: &str) -> (String, Vec<String>) {
+        let parser = EbnfParser::from_str(STATEMENTS).expect("grammar");
+        let (tree, errors) = parser.parsewithrecovery(input, "list").expect("recovered");
+        (
+            tree.tosexp(),
+            errors.iter().map(|error| error.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn recoveryresumesafterthesynctoken() {
+        let (tree, errors) = recovered("a = ; b = 2;");
+        assert_eq!(
+            tree,
+            r#"(list (item (identifier "a") "=" (error ";")) (item (identifier "b") "=" (number "2") ";"))"#
+        );
+        assert_eq!(
+            errors,
+            ["Unexpected ';' at line 1, column 5, expected one of: number"]
+        );
+    }
+
+    #[test]
+    fn recoverykeepsthestatementsafteranerror() {
+        let (tree, errors) = recovered("a = 1; b 2; c = 3;");
+        assert_eq!(
+            tree,
+            r#"(list (item (identifier "a") "=" (number "1") ";") (item (identifier "b") (error "2;")) (item (identifier "c") "=" (number "3") ";"))"#
+        );
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn recoveryreportsoneerrorperfailure() {
+        let (tree, errors) = recovered("x; a = 1; b = 2;");
+        assert_eq!(
+            tree,
+            r#"(list (item (identifier "x") (error ";")) (item (identifier "a") "=" (number "1") ";") (item (identifier "b") "=" (number "2") ";"))"#
+        );
+        assert_eq!(
+            errors,
+            ["Unexpected ';' at line 1, column 2, expected one of: \"=\""]
+        );
+
+        let (_, errors) = recovered("a 1; b = ; c = 3 d = 4;");
+        assert_eq!(errors.len(), 3);
+    }
+
+    #[test]
+    fn recoveryclosesopenrulesattheend() {
+        let (tree, errors) = recovered("a = 1; b = 2");
+        assert_eq!(
+            tree,
+            r#"(list (item (identifier "a") "=" (number "1") ";") (item (identifier "b") "=" (number "2") (error "")))"#
+        );
+        assert_eq!(
+            errors,
+            ["Unexpected end of input at line 1, column 13, expected one of: \";\""]
+        );
+    }
+
+    #[test]
+    fn startrulesmaybeasingleterminal() {
+        let parser = EbnfParser::from_str(r#"number: /[0-9]+/; end: !/./;"#).expect("grammar");
+        let tree = parser.parse("42", "number").map(|tree| tree.tosexp());
+        assert_eq!(tree.ok().as_deref(), Some(r#"(number "42")"#));
+        assert!(parser.parse("", "number").is_err());
+        assert!(parser.parse("4 2", "number").is_err());
+        assert!(parser.parse("", "end").is_ok());
+        assert!(parser.parse("a", "end").is_err());
+    }
+
+    const NUMBERS: &str = r#"
+list: number+;
+number: /[0-9]+/;
+%ignore /[ ]+/;
+%ignore /#[^\n]*\n/;
+"#;
+
+    fn numbers(input: &str) -> Result<String, String> {
+        let parser = EbnfParser::from_str(NUMBERS).expect("grammar");
+        parser
+            .parse(input, "list")
+            .map(|tree| tree.tosexp())
+            .map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn ignoredinputisskippedbetweentokens() {
+        let tree = r#"(list (number "1") (number "23"))"#;
+        assert_eq!(numbers("1 23").as_deref(), Ok(tree));
+        assert_eq!(numbers("  1 # one\n 23   ").as_deref(), Ok(tree));
+        assert_eq!(numbers("123   ").as_deref(), Ok(r#"(list (number "123"))"#));
+    }
+
+    #[test]
+    fn ignoredinputdoesnotsplittokens() {
+        let parser = EbnfParser::from_str(r#"word: "if" "x"; %ignore /[ ]+/;"#).expect("grammar");
+        assert!(parser.parse("if x", "word").is_ok());
+        assert!(parser.parse("i f x", "word").is_err());
+        assert!(parser.parse("ifx", "word").is_ok());
+    }
+
+    #[test]
+    fn longrepetitionsbuildaflattree() {
+        let parser = EbnfParser::from_str(r#"list: number*; number: /[0-9]+/; %ignore /[ ]+/;"#)
+            .expect("grammar");
+        let Ok(EbnfTree::Rule { children, end, .. }) = parser.parse(&"1 ".repeat(100_000), "list")
+        else {
+            panic!("the input parses");
+        };
+        assert_eq!(children.len(), 100_000);
+        assert_eq!((children[99_999].start(), end), (199_998, 199_999));
+    }
+
+    #[test]
+    fn errorslisttheexpectedterminalsatthefurthestposition() {
+        let parser = EbnfParser::from_str(STATEMENTS).expect("grammar");
+        let error = parser
+            .parse("a = 1; b = 2 c", "list")
+            .err()
+            .expect("missing ;");
+        assert_eq!(
+            error.to_string(),
+            "Unexpected 'c' at line 1, column 14, expected one of: \";\""
+        );
+        assert_eq!(
+            numbers("1 # one\n 2 x").err().as_deref(),
+            Some("Unexpected 'x' at line 2, column 4, expected one of: end of input, number")
+        );
+        let error = parser
+            .parse("a = 1; b", "list")
+            .err()
+            .expect("unfinished item");
+        assert!(matches!(
+            error.errtype,
+            ParseCodeErrorType::UnexpectedEof { .. }
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Unexpected end of input at line 1, column 9, expected one of: \"=\""
+        );
+    }
+
+    #[test]
+    fn predicateslookaheadwithoutconsuming() {
+        let parser = EbnfParser::from_str(
+            r#"list: item+; item: &/[0-9]/ number | !"end" name | "end"; number: /[0-9]+/; name: /[a-z0-9]+/; %ignore /[ ]+/;"#,
+        )
+        .expect("grammar");
+        let tree = parser.parse("1 b2 end", "list").map(|tree| tree.tosexp());
+        assert_eq!(
+            tree.ok().as_deref(),
+            Some(r#"(list (item (number "1")) (item (name "b2")) (item "end"))"#)
+        );
+        //"end" is not reserved, so the name cannot start with it
+        let tree = parser.parse("endless", "list").map(|tree| tree.tosexp());
+        assert_eq!(
+            tree.ok().as_deref(),
+            Some(r#"(list (item "end") (item (name "less")))"#)
+        );
+    }
+
+    #[test]
+    fn lookaheadsusetheparsersregexcache() {
+        let parser = EbnfParser::from_str(r#"list: (!/[0-9]+x/ /[0-9]+/)*; %ignore /[ ]+/;"#)
+            .expect("grammar");
+        assert!(parser.parse("1 2 3", "list").is_ok());
+        //a cached regex that differs from its pattern shows that predicates do not compile
+        //their own
+        let digits = compiled("[0-9]+", &parser.regexes).expect("regex");
+        parser
+            .regexes
+            .borrow_mut()
+            .insert("[0-9]+x".to_owned(), digits);
+        assert!(parser.parse("1 2 3", "list").is_err());
+    }
+
+    #[test]
+    fn reservedwordsarenotnames() {
+        let parser = EbnfParser::from_str(
+            r#"statement: "if" name | name "=" name; name: /[a-z]+/; %reserved "if"; %ignore /[ ]+/;"#,
+        )
+        .expect("grammar");
+        assert!(parser.parse("if x", "statement").is_ok());
+        assert!(parser.parse("if = x", "statement").is_err());
+        assert!(parser.parse("iffy = x", "statement").is_ok());
+        assert!(parser.parse("iffy", "statement").is_err());
+    }
+}
@@ -0,0 +1,342 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::grammar::EbnfStatement;
+use crate::parser::{EbnfParser, ParseCodeError, ParseCodeErrorType, wholeword};
+use crate::table::{alternatives, compiled};
+use crate::tree::EbnfTree;
+
+//end position and trees of a successful match
+type PegMatch = Option<(usize, Vec<EbnfTree>)>;
+
+//packrat parser reading the grammar as a PEG, '|' is ordered choice and repetitions are greedy
+struct PegState<'p, 'a> {
+    parser: &'p EbnfParser<'a>,
+    input: &'p str,
+    //results of defined rules by (rule, position, inside a predicate), None while a rule is
+    //being tried so left recursion fails instead of looping
+    memo: HashMap<(&'p str, usize, bool), PegMatch>,
+    //the furthest position a terminal failed at and what it expected there
+    furthest: usize,
+    expected: BTreeSet<String>,
+    //inside a predicate, failures are not syntax errors
+    quiet: usize,
+    //where the input the terminals read ends, a regex that failed may have read all of it
+    reach: usize,
+}
+
+impl<'a> EbnfParser<'a> {
+    pub fn parsepeg(&self, input: &str, startrule: &str) -> Result<EbnfTree, ParseCodeError> {
+        let Some((rulename, _)) = self.rules.get_key_value(startrule) else {
+            return Err(ParseCodeError {
+                errtype: ParseCodeErrorType::UnknownRule,
+                line: 0,
+                column: 0,
+            });
+        };
+        let mut state = PegState {
+            parser: self,
+            input,
+            memo: HashMap::new(),
+            furthest: 0,
+            expected: BTreeSet::new(),
+            quiet: 0,
+            reach: 0,
+        };
+        let start = self.skipignored(input, 0)? as usize;
+        if let Some((end, mut trees)) = state.rule(rulename, start)? {
+            if end == input.len() {
+                return Ok(trees.pop().expect("a defined rule always builds a node"));
+            }
+            state.expect(end, "end of input".to_owned());
+        }
+        Err(ParseCodeError::unexpected(
+            input,
+            state.furthest as i64,
+            state.expected.into_iter().collect(),
+        ))
+    }
+}
+
+impl<'a> EbnfParser<'a> {
+    //whether a lookahead of the Earley parser holds at the start of the input and how much of
+    //the input it read, it is read as a PEG so `|` inside it is ordered choice
+    pub(crate) fn lookahead(
+        &self,
+        predicate: &EbnfStatement<'a>,
+        input: &str,
+    ) -> Result<(bool, usize), ParseCodeError> {
+        let mut state = PegState {
+            parser: self,
+            input,
+            memo: HashMap::new(),
+            furthest: 0,
+            expected: BTreeSet::new(),
+            quiet: 1,
+            reach: 0,
+        };
+        let holds = state.statement(predicate, 0)?.is_some();
+        Ok((holds, state.reach))
+    }
+}
+
+impl<'p, 'a> PegState<'p, 'a> {
+    fn expect(&mut self, position: usize, description: String) {
+        if self.quiet > 0 || position < self.furthest {
+            return;
+        }
+        if position > self.furthest {
+            self.furthest = position;
+            self.expected.clear();
+        }
+        self.expected.insert(description);
+    }
+
+    fn rule(&mut self, rulename: &'p str, position: usize) -> Result<PegMatch, ParseCodeError> {
+        let Some((rulename, body)) = self.parser.rules.get_key_value(rulename) else {
+            return Err(ParseCodeError::at(
+                self.input,
+                position as i64,
+                ParseCodeErrorType::UnknownRule,
+            ));
+        };
+        //failures inside predicates report no expectations, so their results are kept apart,
+        //while the results from outside can be used inside as well
+        let quiet = self.quiet > 0;
+        if let Some(result) = self
+            .memo
+            .get(&(rulename.as_str(), position, false))
+            .or_else(|| self.memo.get(&(rulename.as_str(), position, quiet)))
+        {
+            return Ok(result.clone());
+        }
+        self.memo.insert((rulename, position, quiet), None);
+
+        //terminals that make up a whole rule are reported by the rule name
+        let (alternative, result) = match body {
+            EbnfStatement::StringTerminal { .. } | EbnfStatement::RegexTerminal { .. } => {
+                (0, self.terminal(body, position, rulename.to_owned())?)
+            }
+            _ => self.alternatives(body, position)?,
+        };
+        let result = result.map(|(end, children)| {
+            let (start, last) = match (children.first(), children.last()) {
+                (Some(first), Some(last)) => (first.start(), last.end()),
+                _ => (position, position),
+            };
+            let node = EbnfTree::Rule {
+                name: rulename.to_owned(),
+                alternative,
+                children,
+                start,
+                end: last,
+            };
+            (end, vec![node])
+        });
+        self.memo
+            .insert((rulename, position, quiet), result.clone());
+        Ok(result)
+    }
+
+    fn terminal(
+        &mut self,
+        statement: &EbnfStatement,
+        position: usize,
+        description: String,
+    ) -> Result<PegMatch, ParseCodeError> {
+        let rest = &self.input[position..];
+        let length = match statement {
+            EbnfStatement::StringTerminal { string } => (rest.starts_with(string)
+                && wholeword(&self.parser.reserved, string, rest))
+            .then_some(string.len()),
+            EbnfStatement::RegexTerminal { string } => {
+                let exp = compiled(string, &self.parser.regexes).ok_or_else(|| {
+                    ParseCodeError::at(
+                        self.input,
+                        position as i64,
+                        ParseCodeErrorType::InvalidRegex,
+                    )
+                })?;
+                exp.find(rest)
+                    .filter(|found| !self.parser.reserved.contains(&found.as_str()))
+                    .map(|found| found.end())
+            }
+            _ => None,
+        };
+        //strings also look at the character after them for %reserved words
+        self.reach = self.reach.max(match (statement, length) {
+            (EbnfStatement::StringTerminal { string }, _) => position + string.len() + 1,
+            (_, Some(length)) => position + length + 1,
+            (_, None) => self.input.len() + 1,
+        });
+        let Some(length) = length else {
+            self.expect(position, description);
+            return Ok(None);
+        };
+        let end = position + length;
+        let token = EbnfTree::Token {
+            text: self.input[position..end].to_owned(),
+            string: matches!(statement, EbnfStatement::StringTerminal { .. }),
+            start: position,
+            end,
+        };
+        let next = self.parser.skipignored(self.input, end as i64)? as usize;
+        self.reach = self.reach.max(next + 1);
+        Ok(Some((next, vec![token])))
+    }
+
+    //the top-level alternatives of a rule body are tried in order like `|` does, so the node
+    //knows which one matched
+    fn alternatives(
+        &mut self,
+        body: &'p EbnfStatement<'a>,
+        position: usize,
+    ) -> Result<(usize, PegMatch), ParseCodeError> {
+        for (index, alternative) in alternatives(body).into_iter().enumerate() {
+            if let Some(matched) = self.statement(alternative, position)? {
+                return Ok((index, Some(matched)));
+            }
+        }
+        Ok((0, None))
+    }
+
+    fn statement(
+        &mut self,
+        statement: &'p EbnfStatement<'a>,
+        position: usize,
+    ) -> Result<PegMatch, ParseCodeError> {
+        match statement {
+            EbnfStatement::StringTerminal { .. } | EbnfStatement::RegexTerminal { .. } => {
+                self.terminal(statement, position, statement.to_string())
+            }
+            EbnfStatement::DefinedRule { rulename } => self.rule(rulename, position),
+            EbnfStatement::Concatenation { rules } => {
+                let mut current = position;
+                let mut trees = Vec::new();
+                for rule in rules {
+                    match self.statement(rule, current)? {
+                        Some((end, matched)) => {
+                            current = end;
+                            trees.extend(matched);
+                        }
+                        None => return Ok(None),
+                    }
+                }
+                Ok(Some((current, trees)))
+            }
+            //ordered choice, the right side is only tried if the left one fails
+            EbnfStatement::Or { left, right } => match self.statement(left, position)? {
+                Some(matched) => Ok(Some(matched)),
+                None => self.statement(right, position),
+            },
+            EbnfStatement::Optional { rule } => Ok(Some(
+                self.statement(rule, position)?
+                    .unwrap_or((position, Vec::new())),
+            )),
+            EbnfStatement::ZeroOrMore { rule } | EbnfStatement::OneOrMore { rule } => {
+                let mut current = position;
+                let mut trees = Vec::new();
+                let mut repetitions = 0;
+                while let Some((end, matched)) = self.statement(rule, current)? {
+                    if end == current {
+                        break;
+                    }
+                    current = end;
+                    trees.extend(matched);
+                    repetitions += 1;
+                }
+                if repetitions == 0 && matches!(statement, EbnfStatement::OneOrMore { .. }) {
+                    return Ok(None);
+                }
+                Ok(Some((current, trees)))
+            }
+            EbnfStatement::And { rule } | EbnfStatement::Not { rule } => {
+                self.quiet += 1;
+                let matched = self.statement(rule, position);
+                self.quiet -= 1;
+                if matched?.is_some() != matches!(statement, EbnfStatement::And { .. }) {
+                    self.expect(position, statement.to_string());
+                    return Ok(None);
+                }
+                Ok(Some((position, Vec::new())))
+            }
+            EbnfStatement::Empty => Ok(Some((position, Vec::new()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    fn peg(grammar: &str, input: &str, startrule: &str) -> Option<String> {
+        let parser = EbnfParser::from_str(grammar).expect("grammar");
+        parser
+            .parsepeg(input, startrule)
+            .map(|tree| tree.tosexp())
+            .ok()
+    }
+
+    #[test]
+    fn choicetakesthefirstalternativethatmatches() {
+        let grammar = r#"start: word; word: "a" | "ab";"#;
+        assert!(peg(grammar, "a", "start").is_some());
+        assert_eq!(peg(grammar, "ab", "start"), None);
+        let parser = EbnfParser::from_str(grammar).expect("grammar");
+        assert!(parser.parse("ab", "start").is_ok());
+    }
+
+    #[test]
+    fn repetitionisgreedy() {
+        let grammar = r#"start: "a"* "a";"#;
+        assert_eq!(peg(grammar, "aaa", "start"), None);
+        assert!(peg(r#"start: "a"* "b";"#, "aaab", "start").is_some());
+    }
+
+    #[test]
+    fn predicatesdonotconsumeinput() {
+        let grammar = r#"
+start: keyword | identifier;
+keyword: "if" !/[a-z]/;
+identifier: !keyword /[a-z]+/;
+"#;
+        assert_eq!(
+            peg(grammar, "if", "start").as_deref(),
+            Some(r#"(start (keyword "if"))"#)
+        );
+        assert_eq!(
+            peg(grammar, "iffy", "start").as_deref(),
+            Some(r#"(start (identifier "iffy"))"#)
+        );
+        assert_eq!(
+            peg(r#"start: &"a" /[a-z]+/;"#, "abc", "start").as_deref(),
+            Some(r#"(start "abc")"#)
+        );
+        assert_eq!(peg(r#"start: &"a" /[a-z]+/;"#, "bc", "start"), None);
+    }
+
+    #[test]
+    fn rulesinsidepredicatesarememoized() {
+        //every level tries the next one twice, once inside the predicate, which is 2^40 tries
+        //unless the results inside predicates are kept too
+        let mut grammar: String = (0..40)
+            .map(|level| format!("r{level}: &r{next} r{next};\n", next = level + 1))
+            .collect();
+        grammar.push_str("r40: /x/;");
+        let expected: String = (0..=40).map(|level| format!("(r{level} ")).collect();
+        assert_eq!(
+            peg(&grammar, "x", "r0"),
+            Some(format!("{expected}\"x\"{}", ")".repeat(41)))
+        );
+    }
+
+    #[test]
+    fn regexesarecompiledonceperparser() {
+        let parser = EbnfParser::from_str(r#"start: /[a-z]+/ ("," /[a-z]+/)*;"#).expect("grammar");
+        assert!(parser.parsepeg("a,b", "start").is_ok());
+        assert!(parser.regexes.borrow().contains_key("[a-z]+"));
+        let cached = parser.regexes.borrow()["[a-z]+"].clone();
+        assert!(parser.parsepeg("c,d", "start").is_ok());
+        assert!(Rc::ptr_eq(&cached, &parser.regexes.borrow()["[a-z]+"]));
+    }
+}
@@ -0,0 +1,544 @@
+use std::{cell::RefCell, collections::HashSet, fmt::Write};
+
+use crate::grammar::{EbnfAssociativity, EbnfStatement, written};
+use crate::parser::{EbnfParser, ParseCodeError, ParseCodeErrorType};
+
+impl<'a> EbnfParser<'a> {
+    //a smaller grammar of the same language: rules that only name another rule are replaced by
+    //it, rules used once are inlined, duplicate alternatives dropped and common prefixes of
+    //alternatives factored out, then unreachable rules are removed. It is meant for the Earley
+    //parser, the order of alternatives a PEG depends on is not kept
+    pub fn simplify(&self, startrule: &str) -> Result<EbnfParser<'a>, ParseCodeError> {
+        if !self.rules.contains_key(startrule) {
+            return Err(ParseCodeError {
+                line: 0,
+                column: 0,
+                errtype: ParseCodeErrorType::UnknownRule,
+            });
+        }
+        //rules other parts of the grammar refer to by name keep it
+        let mut pinned = vec![startrule];
+        pinned.extend(self.tests.iter().map(|test| test.rule));
+        pinned.extend(self.aliases.keys().map(|(rulename, _)| rulename.as_str()));
+        let directives = self
+            .ignore
+            .iter()
+            .chain(&self.sync)
+            .chain(
+                self.precedence
+                    .iter()
+                    .flat_map(|(_, statements)| statements),
+            )
+            .chain(
+                self.highlights
+                    .iter()
+                    .flat_map(|(_, statements)| statements),
+            );
+        for statement in directives {
+            references(statement, &mut pinned);
+        }
+        let pinned: HashSet<&str> = pinned.into_iter().collect();
+        let aliased: HashSet<&str> = self
+            .aliases
+            .keys()
+            .map(|(rulename, _)| rulename.as_str())
+            .collect();
+
+        let mut rules = self.rules.clone();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (rulename, body) in rules.iter_mut() {
+                let statement = std::mem::replace(body, EbnfStatement::Empty);
+                *body = if aliased.contains(rulename.as_str()) {
+                    //the aliases count the top-level alternatives
+                    let mut alternatives = spine(statement);
+                    let last = alternatives.pop().map(normalize);
+                    alternatives.into_iter().map(normalize).rfold(
+                        last.expect("a rule has an alternative"),
+                        |right, left| EbnfStatement::Or {
+                            left: Box::new(left),
+                            right: Box::new(right),
+                        },
+                    )
+                } else {
+                    normalize(statement)
+                };
+            }
+            let mut names: Vec<String> = rules.keys().cloned().collect();
+            names.sort();
+            for rulename in names {
+                if pinned.contains(rulename.as_str()) || !rules.contains_key(&rulename) {
+                    continue;
+                }
+                let mut used = Vec::new();
+                references(&rules[&rulename], &mut used);
+                if used.contains(&rulename.as_str()) {
+                    continue;
+                }
+                //`rule: other;` is replaced by other everywhere
+                if let EbnfStatement::DefinedRule { rulename: other } = rules[&rulename]
+                    && rules.contains_key(other)
+                {
+                    rules.remove(&rulename);
+                    for body in rules.values_mut() {
+                        rename(body, &rulename, other);
+                    }
+                    changed = true;
+                    continue;
+                }
+                let mut users = Vec::new();
+                for (user, body) in &rules {
+                    let mut used = Vec::new();
+                    references(body, &mut used);
+                    let uses = used.iter().filter(|used| **used == rulename).count();
+                    users.extend(std::iter::repeat_n(user.clone(), uses));
+                }
+                let [user] = users.as_slice() else {
+                    continue;
+                };
+                let body = &rules[&rulename];
+                //an alias of the last alternative would only name the first alternative of
+                //the inlined rule
+                if aliased.contains(user.as_str())
+                    && matches!(body, EbnfStatement::Or { .. })
+                    && spine(rules[user].clone()).last()
+                        == Some(&EbnfStatement::DefinedRule {
+                            rulename: &rulename,
+                        })
+                {
+                    continue;
+                }
+                let body = rules.remove(&rulename).expect("the rule exists");
+                let user = rules.get_mut(user).expect("the rule exists");
+                substitute(user, &rulename, &body);
+                changed = true;
+            }
+        }
+
+        let mut reachable = Vec::new();
+        let mut pending: Vec<&str> = pinned.iter().copied().collect();
+        while let Some(rulename) = pending.pop() {
+            if let Some((rulename, body)) = rules.get_key_value(rulename)
+                && !reachable.contains(&rulename.as_str())
+            {
+                reachable.push(rulename);
+                references(body, &mut pending);
+            }
+        }
+        let reachable: HashSet<String> = reachable.into_iter().map(str::to_owned).collect();
+        rules.retain(|rulename, _| reachable.contains(rulename));
+
+        Ok(EbnfParser {
+            ignore: self.ignore.clone(),
+            sync: self.sync.clone(),
+            precedence: self.precedence.clone(),
+            regexes: RefCell::new(self.regexes.borrow().clone()),
+            observer: self.observer.clone(),
+            inline: self.inline.intersection(&reachable).cloned().collect(),
+            keeptokens: self.keeptokens.intersection(&reachable).cloned().collect(),
+            aliases: self.aliases.clone(),
+            tests: self.tests.clone(),
+            highlights: self.highlights.clone(),
+            templates: self.templates.clone(),
+            reserved: self.reserved.clone(),
+            rules,
+        })
+    }
+
+    //the grammar as text that reads back into the same parser, the rules in the order they are
+    //used from the start rule, then the directives
+    pub fn togrammar(&self, startrule: &str) -> String {
+        let mut order = Vec::new();
+        self.usedrules(startrule, &mut order);
+        let mut unreachable: Vec<&str> = self
+            .rules
+            .keys()
+            .map(String::as_str)
+            .filter(|rule| !order.contains(rule))
+            .collect();
+        unreachable.sort();
+        order.extend(unreachable);
+
+        let mut out = String::new();
+        for rulename in order {
+            let prefix = if self.inline.contains(rulename) {
+                "?"
+            } else if self.keeptokens.contains(rulename) {
+                "!"
+            } else {
+                ""
+            };
+            let header = format!("{prefix}{rulename}: ");
+            let alternatives: Vec<String> = spine(self.rules[rulename].clone())
+                .iter()
+                .enumerate()
+                .map(|(i, alternative)| {
+                    let text =
+                        written(alternative, matches!(alternative, EbnfStatement::Or { .. }));
+                    match self.aliases.get(&(rulename.to_owned(), i)) {
+                        Some(alias) => format!("{text} -> {alias}"),
+                        None => text,
+                    }
+                })
+                .collect();
+            let separator = format!("\n{}| ", " ".repeat(header.len() - 2));
+            let _ = writeln!(out, "{header}{};", alternatives.join(&separator));
+        }
+
+        let list = |statements: &[EbnfStatement]| {
+            statements
+                .iter()
+                .map(|statement| written(statement, true))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let mut directives = Vec::new();
+        for statement in &self.ignore {
+            directives.push(format!("%ignore {};", written(statement, false)));
+        }
+        if !self.reserved.is_empty() {
+            let words: Vec<String> = self
+                .reserved
+                .iter()
+                .map(|word| format!("\"{word}\""))
+                .collect();
+            directives.push(format!("%reserved {};", words.join(" ")));
+        }
+        if !self.sync.is_empty() {
+            directives.push(format!("%sync {};", list(&self.sync)));
+        }
+        for (associativity, statements) in &self.precedence {
+            let name = match associativity {
+                EbnfAssociativity::Left => "left",
+                EbnfAssociativity::Right => "right",
+                EbnfAssociativity::Nonassoc => "nonassoc",
+            };
+            directives.push(format!("%{name} {};", list(statements)));
+        }
+        for (highlight, statements) in &self.highlights {
+            directives.push(format!(
+                "%highlight {} {};",
+                highlight.name(),
+                list(statements)
+            ));
+        }
+        for test in &self.tests {
+            let directive = if test.fail { "test-fail" } else { "test" };
+            let mut input = String::new();
+            for char in test.input.chars() {
+                match char {
+                    '\n' => input.push_str("\\n"),
+                    '\t' => input.push_str("\\t"),
+                    '\r' => input.push_str("\\r"),
+                    '"' | '\\' => {
+                        input.push('\\');
+                        input.push(char);
+                    }
+                    _ => input.push(char),
+                }
+            }
+            directives.push(format!("%{directive} {} \"{input}\"", test.rule));
+        }
+        if !directives.is_empty() {
+            let _ = writeln!(out, "\n{}", directives.join("\n"));
+        }
+        out
+    }
+}
+
+//the rule names a statement uses, once per use
+fn references<'a>(statement: &EbnfStatement<'a>, out: &mut Vec<&'a str>) {
+    match statement {
+        EbnfStatement::DefinedRule { rulename } => out.push(rulename),
+        EbnfStatement::Concatenation { rules } => {
+            for rule in rules {
+                references(rule, out);
+            }
+        }
+        EbnfStatement::Or { left, right } => {
+            references(left, out);
+            references(right, out);
+        }
+        EbnfStatement::Optional { rule }
+        | EbnfStatement::OneOrMore { rule }
+        | EbnfStatement::ZeroOrMore { rule }
+        | EbnfStatement::And { rule }
+        | EbnfStatement::Not { rule } => references(rule, out),
+        _ => {}
+    }
+}
+
+fn rename<'a>(statement: &mut EbnfStatement<'a>, from: &str, to: &'a str) {
+    match statement {
+        EbnfStatement::DefinedRule { rulename } if *rulename == from => *rulename = to,
+        EbnfStatement::Concatenation { rules } => {
+            for rule in rules {
+                rename(rule, from, to);
+            }
+        }
+        EbnfStatement::Or { left, right } => {
+            rename(left, from, to);
+            rename(right, from, to);
+        }
+        EbnfStatement::Optional { rule }
+        | EbnfStatement::OneOrMore { rule }
+        | EbnfStatement::ZeroOrMore { rule }
+        | EbnfStatement::And { rule }
+        | EbnfStatement::Not { rule } => rename(rule, from, to),
+        _ => {}
+    }
+}
+
+fn substitute<'a>(statement: &mut EbnfStatement<'a>, rulename: &str, body: &EbnfStatement<'a>) {
+    match statement {
+        EbnfStatement::DefinedRule { rulename: name } if *name == rulename => {
+            *statement = body.clone()
+        }
+        EbnfStatement::Concatenation { rules } => {
+            for rule in rules {
+                substitute(rule, rulename, body);
+            }
+        }
+        EbnfStatement::Or { left, right } => {
+            substitute(left, rulename, body);
+            substitute(right, rulename, body);
+        }
+        EbnfStatement::Optional { rule }
+        | EbnfStatement::OneOrMore { rule }
+        | EbnfStatement::ZeroOrMore { rule }
+        | EbnfStatement::And { rule }
+        | EbnfStatement::Not { rule } => substitute(rule, rulename, body),
+        _ => {}
+    }
+}
+
+//the top-level alternatives of a rule, as the aliases number them
+fn spine(statement: EbnfStatement) -> Vec<EbnfStatement> {
+    let mut alternatives = Vec::new();
+    let mut current = statement;
+    while let EbnfStatement::Or { left, right } = current {
+        alternatives.push(*left);
+        current = *right;
+    }
+    alternatives.push(current);
+    alternatives
+}
+
+fn sequence(mut rules: Vec<EbnfStatement>) -> EbnfStatement {
+    match rules.len() {
+        0 => EbnfStatement::Empty,
+        1 => rules.pop().expect("one rule"),
+        _ => EbnfStatement::Concatenation { rules },
+    }
+}
+
+fn choice(choices: Vec<EbnfStatement>) -> EbnfStatement {
+    choices
+        .into_iter()
+        .rev()
+        .reduce(|right, left| EbnfStatement::Or {
+            left: Box::new(left),
+            right: Box::new(right),
+        })
+        .unwrap_or(EbnfStatement::Empty)
+}
+
+//the alternatives of nested `|`, normalized
+fn alternatives<'a>(statement: EbnfStatement<'a>, out: &mut Vec<EbnfStatement<'a>>) {
+    match statement {
+        EbnfStatement::Or { left, right } => {
+            alternatives(*left, out);
+            alternatives(*right, out);
+        }
+        statement => match normalize(statement) {
+            statement @ EbnfStatement::Or { .. } => alternatives(statement, out),
+            statement => out.push(statement),
+        },
+    }
+}
+
+//flattens nested concatenations and alternatives, merges repetitions of repetitions, drops
+//duplicate alternatives and factors out the common prefixes of alternatives
+fn normalize(statement: EbnfStatement) -> EbnfStatement {
+    match statement {
+        EbnfStatement::Concatenation { rules } => {
+            let mut flat = Vec::new();
+            for rule in rules {
+                match normalize(rule) {
+                    EbnfStatement::Concatenation { rules } => flat.extend(rules),
+                    EbnfStatement::Empty => {}
+                    rule => flat.push(rule),
+                }
+            }
+            sequence(flat)
+        }
+        EbnfStatement::Or { .. } => {
+            let mut choices = Vec::new();
+            alternatives(statement, &mut choices);
+            let mut unique: Vec<EbnfStatement> = Vec::new();
+            for choice in choices {
+                if !unique.contains(&choice) {
+                    unique.push(choice);
+                }
+            }
+            factor(unique)
+        }
+        EbnfStatement::Optional { rule } => match normalize(*rule) {
+            rule @ (EbnfStatement::Optional { .. } | EbnfStatement::ZeroOrMore { .. }) => rule,
+            EbnfStatement::OneOrMore { rule } => EbnfStatement::ZeroOrMore { rule },
+            rule => EbnfStatement::Optional {
+                rule: Box::new(rule),
+            },
+        },
+        EbnfStatement::ZeroOrMore { rule } => match normalize(*rule) {
+            EbnfStatement::Optional { rule }
+            | EbnfStatement::ZeroOrMore { rule }
+            | EbnfStatement::OneOrMore { rule } => EbnfStatement::ZeroOrMore { rule },
+            rule => EbnfStatement::ZeroOrMore {
+                rule: Box::new(rule),
+            },
+        },
+        EbnfStatement::OneOrMore { rule } => match normalize(*rule) {
+            rule @ (EbnfStatement::OneOrMore { .. } | EbnfStatement::ZeroOrMore { .. }) => rule,
+            EbnfStatement::Optional { rule } => EbnfStatement::ZeroOrMore { rule },
+            rule => EbnfStatement::OneOrMore {
+                rule: Box::new(rule),
+            },
+        },
+        EbnfStatement::And { rule } => EbnfStatement::And {
+            rule: Box::new(normalize(*rule)),
+        },
+        EbnfStatement::Not { rule } => EbnfStatement::Not {
+            rule: Box::new(normalize(*rule)),
+        },
+        statement => statement,
+    }
+}
+
+//`a b | a c | a` becomes `a (b | c)?`, alternatives are grouped by their first statement in the
+//order they first appear
+fn factor(choices: Vec<EbnfStatement>) -> EbnfStatement {
+    let mut groups: Vec<Vec<Vec<EbnfStatement>>> = Vec::new();
+    for choice in choices {
+        let rules = match choice {
+            EbnfStatement::Concatenation { rules } => rules,
+            choice => vec![choice],
+        };
+        match groups.iter_mut().find(|group| group[0][0] == rules[0]) {
+            Some(group) => group.push(rules),
+            None => groups.push(vec![rules]),
+        }
+    }
+    let mut out = Vec::new();
+    for mut group in groups {
+        if group.len() == 1 {
+            out.push(sequence(group.pop().expect("one alternative")));
+            continue;
+        }
+        let common = (0..)
+            .take_while(|&i| {
+                group
+                    .iter()
+                    .all(|rules| rules.len() > i && rules[i] == group[0][i])
+            })
+            .count();
+        let mut prefix = group[0][..common].to_vec();
+        let mut rests: Vec<Vec<EbnfStatement>> = group
+            .into_iter()
+            .map(|mut rules| rules.split_off(common))
+            .collect();
+        let optional = rests.iter().any(Vec::is_empty);
+        rests.retain(|rest| !rest.is_empty());
+        //`a b d | a c d` becomes `a (b | c) d`, every alternative keeps a statement
+        let mut suffix = Vec::new();
+        if !optional {
+            let shortest = rests.iter().map(Vec::len).min().unwrap_or(0);
+            let common = (1..shortest)
+                .take_while(|&i| {
+                    let last = &rests[0][rests[0].len() - i];
+                    rests.iter().all(|rest| rest[rest.len() - i] == *last)
+                })
+                .count();
+            suffix = rests[0][rests[0].len() - common..].to_vec();
+            for rest in &mut rests {
+                rest.truncate(rest.len() - common);
+            }
+        }
+        let mut rest = normalize(choice(rests.into_iter().map(sequence).collect()));
+        if optional {
+            rest = normalize(EbnfStatement::Optional {
+                rule: Box::new(rest),
+            });
+        }
+        match rest {
+            EbnfStatement::Concatenation { rules } => prefix.extend(rules),
+            rest => prefix.push(rest),
+        }
+        prefix.extend(suffix);
+        out.push(sequence(prefix));
+    }
+    choice(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAMMAR: &str = r#"
+program: statement+;
+statement: "print" value ";" | "print" value "," value ";" | "print" value ";";
+value: number;
+number: digits;
+digits: /[0-9]+/;
+unused: "x";
+%ignore /[ ]+/;
+%test program "print 1, 2; print 3;";
+%test-fail program "print;";
+"#;
+
+    #[test]
+    fn simplifiedgrammarsarewrittenback() {
+        let parser = EbnfParser::from_str(GRAMMAR).expect("grammar");
+        let simplified = parser.simplify("program").expect("simplify");
+        assert_eq!(
+            simplified.togrammar("program"),
+            concat!(
+                "program: (\"print\" value (\";\" | (\",\" value \";\")))+;\n",
+                "value: /[0-9]+/;\n",
+                "\n",
+                "%ignore /[ ]+/;\n",
+                "%test program \"print 1, 2; print 3;\"\n",
+                "%test-fail program \"print;\"\n",
+            )
+        );
+    }
+
+    #[test]
+    fn writtengrammarsreadbackthesame() {
+        let parser = EbnfParser::from_str(GRAMMAR).expect("grammar");
+        let written = parser.togrammar("program");
+        let reread = EbnfParser::from_str(&written).expect("written grammar");
+        assert_eq!(reread.togrammar("program"), written);
+        assert!(reread.runtests().iter().all(|result| result.passed));
+    }
+
+    #[test]
+    fn simplifiedgrammarsacceptthesameinputs() {
+        let parser = EbnfParser::from_str(GRAMMAR).expect("grammar");
+        let simplified = parser.simplify("program").expect("simplify");
+        for input in [
+            "print 1;",
+            "print 1, 2; print 3;",
+            "print;",
+            "print 1, 2, 3;",
+            "",
+        ] {
+            assert_eq!(
+                simplified.parse(input, "program").is_ok(),
+                parser.parse(input, "program").is_ok(),
+                "{input}"
+            );
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use std::io::{self, Read};
+
+use crate::input::EbnfInput;
+use crate::parser::{EbnfParser, ParseCodeError, ParseCodeErrorType};
+use crate::tree::EbnfTree;
+
+//how far ahead of the parse position the input is buffered, tokens and ignored runs
+//have to fit into this
+const WINDOW: usize = 1 << 16;
+const CHUNK: usize = 1 << 13;
+
+//buffered text of a streamed input, from somewhere before the parse position to a bit past it
+struct EbnfStream<I> {
+    chunks: I,
+    buffer: String,
+    offset: usize,
+    lines: usize,
+    linechars: usize,
+    complete: bool,
+}
+
+//reads UTF-8 text in chunks, characters split between reads are carried over
+struct EbnfChunks<R> {
+    reader: R,
+    pending: Vec<u8>,
+}
+
+impl<R: Read> Iterator for EbnfChunks<R> {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut bytes = vec![0; CHUNK];
+        let read = loop {
+            match self.reader.read(&mut bytes) {
+                Ok(read) => break read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        };
+        if read == 0 {
+            if self.pending.is_empty() {
+                return None;
+            }
+            let err = io::Error::new(io::ErrorKind::InvalidData, "incomplete UTF-8 at the end");
+            return Some(Err(err));
+        }
+        self.pending.extend_from_slice(&bytes[..read]);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            //the rest may be completed by the next read
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            Err(err) => return Some(Err(io::Error::new(io::ErrorKind::InvalidData, err))),
+        };
+        let rest = self.pending.split_off(valid);
+        let text =
+            String::from_utf8(std::mem::replace(&mut self.pending, rest)).expect("checked above");
+        Some(Ok(text))
+    }
+}
+
+impl<I: Iterator<Item = io::Result<String>>> EbnfStream<I> {
+    fn end(&self) -> usize {
+        self.offset + self.buffer.len()
+    }
+
+    fn input(&self) -> EbnfInput<'_> {
+        EbnfInput::window(&self.buffer, self.offset, self.lines, self.linechars)
+    }
+
+    //reads until `position` is buffered or the input ends
+    fn fill(&mut self, position: usize) -> Result<(), ParseCodeError> {
+        while !self.complete && self.end() < position {
+            match self.chunks.next() {
+                Some(Ok(chunk)) => self.buffer.push_str(&chunk),
+                Some(Err(err)) => {
+                    let end = self.end() as i64;
+                    return Err(self.input().error(end, ParseCodeErrorType::Read(err)));
+                }
+                None => self.complete = true,
+            }
+        }
+        Ok(())
+    }
+
+    //drops the text before `position` once there is enough of it to be worth moving the rest
+    fn discard(&mut self, position: usize) {
+        let drop = position - self.offset;
+        if drop < WINDOW {
+            return;
+        }
+        let dropped = &self.buffer[..drop];
+        self.lines += dropped.matches('\n').count();
+        self.linechars = match dropped.rfind('\n') {
+            Some(newline) => dropped[newline + 1..].chars().count(),
+            None => self.linechars + dropped.chars().count(),
+        };
+        self.buffer.drain(..drop);
+        self.offset = position;
+    }
+}
+
+impl<'a> EbnfParser<'a> {
+    //parses text read from `reader` without reading all of it first, the tree and error
+    //positions are the same as for parse
+    pub fn parsereader(
+        &self,
+        reader: impl Read,
+        startrule: &str,
+    ) -> Result<EbnfTree, ParseCodeError> {
+        let chunks = EbnfChunks {
+            reader,
+            pending: Vec::new(),
+        };
+        self.parsestream(chunks, startrule)
+    }
+
+    pub fn parsechunks<S: AsRef<str>>(
+        &self,
+        chunks: impl IntoIterator<Item = S>,
+        startrule: &str,
+    ) -> Result<EbnfTree, ParseCodeError> {
+        let chunks = chunks
+            .into_iter()
+            .map(|chunk| Ok(chunk.as_ref().to_owned()));
+        self.parsestream(chunks, startrule)
+    }
+
+    fn parsestream(
+        &self,
+        chunks: impl Iterator<Item = io::Result<String>>,
+        startrule: &str,
+    ) -> Result<EbnfTree, ParseCodeError> {
+        let (rulename, body) = self.startrule(startrule)?;
+        let mut stream = EbnfStream {
+            chunks,
+            buffer: String::new(),
+            offset: 0,
+            lines: 0,
+            linechars: 0,
+            complete: false,
+        };
+        stream.fill(WINDOW)?;
+        let mut chart = self.startchart(&stream.input(), body)?;
+
+        //columns without partials can be skipped, once none are left the parse has failed
+        let mut position = chart.start();
+        loop {
+            stream.fill(position as usize + WINDOW)?;
+            stream.discard(position as usize);
+            self.process(&mut chart, &stream.input(), position)?;
+            if stream.complete && position as usize == stream.end() {
+                break;
+            }
+            match chart.nextcolumn(position) {
+                Some(next) => position = next,
+                None => break,
+            }
+        }
+        stream.fill(position as usize + WINDOW)?;
+        self.finish(&chart, &stream.input(), rulename)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORDS: &str = r#"
+words: word*;
+word: /\w+/;
+%ignore /\s+/;
+"#;
+
+    //hands out one byte per read, splitting every multi-byte character
+    struct Bytes<'b>(&'b [u8]);
+
+    impl Read for Bytes<'_> {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            let Some((&first, rest)) = self.0.split_first() else {
+                return Ok(0);
+            };
+            buffer[0] = first;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn streamedtreesmatchtheinmemorytree() {
+        let parser = EbnfParser::from_str(WORDS).expect("grammar");
+        let input = "grüße an\n alle  ";
+        let tree = parser.parse(input, "words").map(|tree| tree.tosexp()).ok();
+        assert!(tree.is_some());
+        let chunks = input.chars().map(String::from);
+        assert_eq!(
+            parser
+                .parsechunks(chunks, "words")
+                .map(|tree| tree.tosexp())
+                .ok(),
+            tree
+        );
+        assert_eq!(
+            parser
+                .parsereader(Bytes(input.as_bytes()), "words")
+                .map(|tree| tree.tosexp())
+                .ok(),
+            tree
+        );
+    }
+
+    #[test]
+    fn errorpositionssurvivediscardedinput() {
+        let parser = EbnfParser::from_str(WORDS).expect("grammar");
+        //several windows of lines before the error, so the start of the input is dropped
+        let lines = 3 * WINDOW / 18;
+        let input = "wörter und zeilen\n".repeat(lines) + "ende ?";
+        let error = parser.parse(&input, "words").err().expect("'?' is no word");
+        let streamed = parser
+            .parsereader(input.as_bytes(), "words")
+            .err()
+            .expect("'?' is no word");
+        assert_eq!(streamed.to_string(), error.to_string());
+        assert_eq!(streamed.line as usize, lines + 1);
+        assert_eq!(streamed.column, 6);
+    }
+
+    #[test]
+    fn invalidutf8isareaderror() {
+        let parser = EbnfParser::from_str(WORDS).expect("grammar");
+        let error = parser
+            .parsereader(&b"abc \xff"[..], "words")
+            .err()
+            .expect("not UTF-8");
+        assert!(matches!(error.errtype, ParseCodeErrorType::Read(_)));
+        let error = parser
+            .parsereader(&b"abc \xc3"[..], "words")
+            .err()
+            .expect("cut off character");
+        assert!(matches!(error.errtype, ParseCodeErrorType::Read(_)));
+    }
+}
@@ -0,0 +1,339 @@
+use regex::Regex;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    hash::{BuildHasherDefault, Hasher},
+    rc::Rc,
+};
+
+use crate::grammar::EbnfStatement;
+use crate::parser::{EbnfParser, ParseCodeErrorType};
+
+//the keys of the chart are packed integers, they do not need a DoS resistant hash
+#[derive(Default)]
+pub(crate) struct EbnfHasher(u64);
+
+impl Hasher for EbnfHasher {
+    //maps use the low bits, which a multiplication only fills from the low bits of the keys
+    fn finish(&self) -> u64 {
+        let mut hash = self.0;
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(0xff_51_af_d7_ed_55_8c_cd);
+        hash ^ hash >> 33
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_u64(byte as u64);
+        }
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.write_u64(value as u64);
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.0 = (self.0.rotate_left(5) ^ value).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+
+    fn write_u128(&mut self, value: u128) {
+        self.write_u64(value as u64);
+        self.write_u64((value >> 64) as u64);
+    }
+
+    fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+}
+
+pub(crate) type EbnfMap<K, V> = HashMap<K, V, BuildHasherDefault<EbnfHasher>>;
+pub(crate) type EbnfSet<K> = HashSet<K, BuildHasherDefault<EbnfHasher>>;
+
+//a statement with its parts replaced by their ids
+enum EbnfKind {
+    Terminal,
+    Concatenation(Vec<u32>),
+    //a chain of `|` is one statement with an alternative for every choice
+    Or(Vec<u32>),
+    Optional(u32),
+    ZeroOrMore(u32),
+    OneOrMore(u32),
+    //the body of the rule, None if there is no such rule
+    Rule(Option<u32>),
+    Predicate,
+    Empty,
+}
+
+//every statement of a grammar by id, rule references resolved and regexes compiled once
+pub(crate) struct EbnfTable<'a> {
+    statements: Vec<&'a EbnfStatement<'a>>,
+    kinds: Vec<EbnfKind>,
+    //ids by statement address
+    ids: EbnfMap<usize, u32>,
+    //None for invalid regexes and string terminals
+    regexes: Vec<Option<Rc<Regex>>>,
+}
+
+impl<'a> EbnfTable<'a> {
+    //`extra` are statements outside the rules the parse uses, like the token of skipped input
+    pub(crate) fn new(parser: &'a EbnfParser<'a>, extra: &[&'a EbnfStatement<'a>]) -> Self {
+        let mut table = EbnfTable {
+            statements: Vec::new(),
+            kinds: Vec::new(),
+            ids: EbnfMap::default(),
+            regexes: Vec::new(),
+        };
+        let mut rules = Vec::new();
+        for (rulename, body) in &parser.rules {
+            rules.push((rulename.as_str(), table.intern(body, &parser.regexes)));
+        }
+        for statement in parser.ignore.iter().chain(&parser.sync) {
+            table.intern(statement, &parser.regexes);
+        }
+        for statement in extra {
+            table.intern(statement, &parser.regexes);
+        }
+        let bodies: HashMap<&str, u32> = rules.into_iter().collect();
+        for (id, statement) in table.statements.iter().enumerate() {
+            if let EbnfStatement::DefinedRule { rulename } = statement {
+                table.kinds[id] = EbnfKind::Rule(bodies.get(rulename).copied());
+            }
+        }
+        table
+    }
+
+    fn intern(
+        &mut self,
+        statement: &'a EbnfStatement<'a>,
+        regexes: &RefCell<HashMap<String, Rc<Regex>>>,
+    ) -> u32 {
+        if let Some(&id) = self.ids.get(&address(statement)) {
+            return id;
+        }
+        let id = self.statements.len() as u32;
+        self.statements.push(statement);
+        self.kinds.push(EbnfKind::Empty);
+        self.regexes.push(None);
+        self.ids.insert(address(statement), id);
+
+        let kind = match statement {
+            EbnfStatement::StringTerminal { .. } => EbnfKind::Terminal,
+            EbnfStatement::RegexTerminal { string } => {
+                self.regexes[id as usize] = compiled(string, regexes);
+                EbnfKind::Terminal
+            }
+            //resolved once every rule body has an id
+            EbnfStatement::DefinedRule { .. } => EbnfKind::Rule(None),
+            EbnfStatement::Concatenation { rules } => EbnfKind::Concatenation(
+                rules
+                    .iter()
+                    .map(|rule| self.intern(rule, regexes))
+                    .collect(),
+            ),
+            EbnfStatement::Or { .. } => {
+                let mut choices = Vec::new();
+                choose(statement, &mut choices);
+                EbnfKind::Or(
+                    choices
+                        .into_iter()
+                        .map(|choice| self.intern(choice, regexes))
+                        .collect(),
+                )
+            }
+            EbnfStatement::Optional { rule } => EbnfKind::Optional(self.intern(rule, regexes)),
+            EbnfStatement::ZeroOrMore { rule } => EbnfKind::ZeroOrMore(self.intern(rule, regexes)),
+            EbnfStatement::OneOrMore { rule } => EbnfKind::OneOrMore(self.intern(rule, regexes)),
+            EbnfStatement::And { rule } | EbnfStatement::Not { rule } => {
+                self.intern(rule, regexes);
+                EbnfKind::Predicate
+            }
+            EbnfStatement::Empty => EbnfKind::Empty,
+        };
+        self.kinds[id as usize] = kind;
+        id
+    }
+
+    //the statement the partials of the start rule are for, a terminal or lookahead body is
+    //wrapped, its own partial would be complete before anything was scanned
+    pub(crate) fn start(&mut self, body: &'a EbnfStatement<'a>) -> u32 {
+        let id = self.id(body);
+        if !matches!(
+            self.kinds[id as usize],
+            EbnfKind::Terminal | EbnfKind::Predicate
+        ) {
+            return id;
+        }
+        self.statements.push(body);
+        self.kinds.push(EbnfKind::Concatenation(vec![id]));
+        self.regexes.push(None);
+        self.statements.len() as u32 - 1
+    }
+
+    //only statements of the grammar the table was built from have an id
+    pub(crate) fn id(&self, statement: &EbnfStatement) -> u32 {
+        self.ids[&address(statement)]
+    }
+
+    pub(crate) fn statement(&self, id: u32) -> &'a EbnfStatement<'a> {
+        self.statements[id as usize]
+    }
+
+    pub(crate) fn regex(&self, id: u32) -> Option<&Regex> {
+        self.regexes[id as usize].as_deref()
+    }
+
+    pub(crate) fn isterminal(&self, id: u32) -> bool {
+        matches!(self.kinds[id as usize], EbnfKind::Terminal)
+    }
+
+    pub(crate) fn ispredicate(&self, id: u32) -> bool {
+        matches!(self.kinds[id as usize], EbnfKind::Predicate)
+    }
+
+    //number of ways a statement can be expanded, see symbol
+    pub(crate) fn alternatives(&self, id: u32) -> u32 {
+        match &self.kinds[id as usize] {
+            EbnfKind::Or(choices) => choices.len() as u32,
+            EbnfKind::Optional(_) | EbnfKind::ZeroOrMore(_) | EbnfKind::OneOrMore(_) => 2,
+            _ => 1,
+        }
+    }
+
+    //the statement after the dot in the alternative of `id`, None if it is complete
+    pub(crate) fn symbol(
+        &self,
+        id: u32,
+        alternative: u32,
+        dot: u32,
+    ) -> Result<Option<u32>, ParseCodeErrorType> {
+        let symbol = match (&self.kinds[id as usize], alternative, dot) {
+            (EbnfKind::Concatenation(rules), _, _) => rules.get(dot as usize).copied(),
+            (EbnfKind::Or(choices), _, 0) => choices.get(alternative as usize).copied(),
+            (EbnfKind::Optional(rule), 1, 0) => Some(*rule),
+            (EbnfKind::OneOrMore(rule), 0, 0) => Some(*rule),
+            (EbnfKind::ZeroOrMore(_) | EbnfKind::OneOrMore(_), 1, 0) => Some(id),
+            (EbnfKind::ZeroOrMore(rule) | EbnfKind::OneOrMore(rule), 1, 1) => Some(*rule),
+            (EbnfKind::Rule(body), _, 0) => match body {
+                Some(body) => Some(*body),
+                None => return Err(ParseCodeErrorType::UnknownRule),
+            },
+            _ => None,
+        };
+        Ok(symbol)
+    }
+}
+
+//the anchored regex for the pattern, cached in the parser across parses
+pub(crate) fn compiled(
+    pattern: &str,
+    regexes: &RefCell<HashMap<String, Rc<Regex>>>,
+) -> Option<Rc<Regex>> {
+    if let Some(exp) = regexes.borrow().get(pattern) {
+        return Some(exp.clone());
+    }
+    let exp = Rc::new(Regex::new(&format!("^(?:{pattern})")).ok()?);
+    regexes.borrow_mut().insert(pattern.to_owned(), exp.clone());
+    Some(exp)
+}
+
+//the choices of nested `|`, in order
+pub(crate) fn choose<'a>(
+    statement: &'a EbnfStatement<'a>,
+    choices: &mut Vec<&'a EbnfStatement<'a>>,
+) {
+    match statement {
+        EbnfStatement::Or { left, right } => {
+            choose(left, choices);
+            choose(right, choices);
+        }
+        _ => choices.push(statement),
+    }
+}
+
+//the top-level alternatives of a rule body, the ones `-> alias` names
+pub(crate) fn alternatives<'s, 'a>(body: &'s EbnfStatement<'a>) -> Vec<&'s EbnfStatement<'a>> {
+    let mut alternatives = Vec::new();
+    let mut current = body;
+    while let EbnfStatement::Or { left, right } = current {
+        alternatives.push(left.as_ref());
+        current = right;
+    }
+    alternatives.push(current);
+    alternatives
+}
+
+//the top-level alternative of a rule body that a choice of its `|` chain belongs to
+pub(crate) fn alternative(body: &EbnfStatement, choice: u32) -> usize {
+    let mut first = 0;
+    alternatives(body)
+        .into_iter()
+        .position(|alternative| {
+            let mut choices = Vec::new();
+            choose(alternative, &mut choices);
+            first += choices.len();
+            (choice as usize) < first
+        })
+        .unwrap_or(0)
+}
+
+pub(crate) fn address(statement: &EbnfStatement) -> usize {
+    statement as *const EbnfStatement as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rulereferencesresolvetotheirbody() {
+        let parser = EbnfParser::from_str(r#"list: item ("," item)*; item: "a" | "b" | "c";"#)
+            .expect("grammar");
+        let table = EbnfTable::new(&parser, &[]);
+        let list = table.id(&parser.rules["list"]);
+        let item = table.id(&parser.rules["item"]);
+        let reference = table.symbol(list, 0, 0).ok().flatten().expect("item");
+        assert_eq!(table.symbol(reference, 0, 0).ok(), Some(Some(item)));
+        assert_eq!(table.symbol(reference, 0, 1).ok(), Some(None));
+        //the chain of `|` is one statement with three choices
+        assert_eq!(table.alternatives(item), 3);
+        let last = table.symbol(item, 2, 0).ok().flatten().expect("c");
+        assert!(table.isterminal(last));
+        assert_eq!(table.statement(last).to_string(), "\"c\"");
+        assert_eq!(table.symbol(item, 2, 1).ok(), Some(None));
+    }
+
+    #[test]
+    fn repetitionsrepeatthemselves() {
+        let parser = EbnfParser::from_str(r#"list: "a"+;"#).expect("grammar");
+        let table = EbnfTable::new(&parser, &[]);
+        let list = table.id(&parser.rules["list"]);
+        let item = table.symbol(list, 0, 0).ok().flatten().expect("a");
+        assert_eq!(table.alternatives(list), 2);
+        assert_eq!(table.symbol(list, 1, 0).ok(), Some(Some(list)));
+        assert_eq!(table.symbol(list, 1, 1).ok(), Some(Some(item)));
+        assert_eq!(table.symbol(list, 1, 2).ok(), Some(None));
+    }
+
+    #[test]
+    fn unknownrulesareanerrorwhenreached() {
+        let parser = EbnfParser::from_str("start: missing;").expect("grammar");
+        let table = EbnfTable::new(&parser, &[]);
+        let start = table.id(&parser.rules["start"]);
+        assert!(matches!(
+            table.symbol(start, 0, 0),
+            Err(ParseCodeErrorType::UnknownRule)
+        ));
+    }
+
+    #[test]
+    fn terminalstartrulesarewrapped() {
+        let parser = EbnfParser::from_str(r#"word: "a"; list: "a" "b";"#).expect("grammar");
+        let mut table = EbnfTable::new(&parser, &[]);
+        let word = table.id(&parser.rules["word"]);
+        let root = table.start(&parser.rules["word"]);
+        assert_ne!(root, word);
+        assert_eq!(table.symbol(root, 0, 0).ok(), Some(Some(word)));
+        let list = table.id(&parser.rules["list"]);
+        assert_eq!(table.start(&parser.rules["list"]), list);
+    }
+}
@@ -0,0 +1,170 @@
+use std::fmt;
+
+use crate::grammar::{ParseEbnfError, ParseEbnfErrorType};
+use crate::parser::{EbnfParser, ParseCodeError, ParseCodeErrorType};
+
+//an example from a %test or %test-fail directive, parsed from `rule`
+#[derive(Clone)]
+pub struct EbnfTest<'a> {
+    pub rule: &'a str,
+    pub input: String,
+    //the example has to be rejected with a syntax error
+    pub fail: bool,
+    //1-based line of the directive
+    pub line: i32,
+}
+
+pub struct EbnfTestResult<'t, 'a> {
+    pub test: &'t EbnfTest<'a>,
+    pub passed: bool,
+    //None if the example parsed
+    pub error: Option<ParseCodeError>,
+}
+
+impl<'t, 'a> fmt::Display for EbnfTestResult<'t, 'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let test = self.test;
+        let directive = if test.fail { "test-fail" } else { "test" };
+        let status = if self.passed { "passed" } else { "FAILED" };
+        write!(
+            f,
+            "{status} %{directive} {} {:?} at line {}",
+            test.rule, test.input, test.line
+        )?;
+        match (&self.error, self.passed) {
+            (Some(err), false) => write!(f, ": {err}"),
+            (None, false) => write!(f, ": it parsed"),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<'a> EbnfParser<'a> {
+    //parses every example of the grammar with the Earley parser, in the order of the grammar
+    pub fn runtests(&self) -> Vec<EbnfTestResult<'_, 'a>> {
+        self.tests
+            .iter()
+            .map(|test| {
+                let error = self.parse(&test.input, test.rule).err();
+                //unknown rules and invalid regexes fail both kinds of tests
+                let syntaxerror = matches!(
+                    error,
+                    Some(ParseCodeError {
+                        errtype: ParseCodeErrorType::UnexpectedToken { .. }
+                            | ParseCodeErrorType::UnexpectedEof { .. },
+                        ..
+                    })
+                );
+                let passed = match test.fail {
+                    true => syntaxerror,
+                    false => error.is_none(),
+                };
+                EbnfTestResult {
+                    test,
+                    passed,
+                    error,
+                }
+            })
+            .collect()
+    }
+}
+
+//reads `rule "input"`, the input may use the escapes \n, \t, \r, \" and \\
+pub(crate) fn parsetest<'a>(
+    argument: &'a str,
+    fail: bool,
+    line: i32,
+    column: i32,
+) -> Result<EbnfTest<'a>, ParseEbnfError> {
+    let error = |errtype| ParseEbnfError {
+        line,
+        column,
+        errtype,
+    };
+    let (rule, input) = argument
+        .trim()
+        .split_once([' ', '\t'])
+        .ok_or(error(ParseEbnfErrorType::EmptyRule))?;
+    let input = input.trim();
+    let Some(quoted) = input.strip_prefix('"') else {
+        let found = input.chars().next().unwrap_or('"');
+        return Err(error(ParseEbnfErrorType::UnexpectedCharacter(found)));
+    };
+    let mut text = String::new();
+    let mut chars = quoted.chars();
+    loop {
+        match chars.next() {
+            None => return Err(error(ParseEbnfErrorType::UnclosedString)),
+            Some('"') => break,
+            Some('\\') => match chars.next() {
+                Some('n') => text.push('\n'),
+                Some('t') => text.push('\t'),
+                Some('r') => text.push('\r'),
+                Some(escaped @ ('"' | '\\')) => text.push(escaped),
+                Some(other) => {
+                    text.push('\\');
+                    text.push(other);
+                }
+                None => return Err(error(ParseEbnfErrorType::UnclosedString)),
+            },
+            Some(char) => text.push(char),
+        }
+    }
+    if let Some(extra) = chars.as_str().trim().chars().next() {
+        return Err(error(ParseEbnfErrorType::UnexpectedCharacter(extra)));
+    }
+    Ok(EbnfTest {
+        rule,
+        input: text,
+        fail,
+        line: line + 1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAMMAR: &str = r#"
+sum: number ("+" number)*;
+number: /[0-9]+/;
+%ignore /[ ]+/;
+%test sum "1 + 2";
+%test-fail sum "1 +";
+%test sum "1 2";
+%test-fail sum "3";
+%test-fail product "2 * 3";
+"#;
+
+    #[test]
+    fn examplespassandfailbytheirdirective() {
+        let parser = EbnfParser::from_str(GRAMMAR).expect("grammar");
+        let results: Vec<String> = parser
+            .runtests()
+            .iter()
+            .map(|result| result.to_string())
+            .collect();
+        assert_eq!(
+            results,
+            [
+                "passed %test sum \"1 + 2\" at line 5",
+                "passed %test-fail sum \"1 +\" at line 6",
+                "FAILED %test sum \"1 2\" at line 7: Unexpected '2' at line 1, column 3, expected one of: \"+\", end of input",
+                "FAILED %test-fail sum \"3\" at line 8: it parsed",
+                "FAILED %test-fail product \"2 * 3\" at line 9: Unknown Rule at line 0, column 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn examplesunescapetheirinput() {
+        let test = parsetest(r#"sum "a\"\n\\\x" "#, false, 3, 1).expect("test");
+        assert_eq!(
+            (test.rule, test.input.as_str(), test.line),
+            ("sum", "a\"\n\\\\x", 4)
+        );
+        assert!(parsetest(r#"sum "1"#, false, 0, 0).is_err());
+        assert!(parsetest(r#"sum "1" 2"#, false, 0, 0).is_err());
+        assert!(parsetest("sum", false, 0, 0).is_err());
+    }
+}
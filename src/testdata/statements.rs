@@ -0,0 +1,315 @@
+//generated from a grammar by the compiler crate, do not edit
+
+use regex::Regex;
+use std::{fmt, sync::OnceLock};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Rule {
+    Codebody,
+    Identifier,
+    Number,
+    Statement,
+}
+
+impl Rule {
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::Codebody => "codebody",
+            Rule::Identifier => "identifier",
+            Rule::Number => "number",
+            Rule::Statement => "statement",
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Node {
+    Rule {
+        rule: Rule,
+        children: Vec<Node>,
+        start: usize,
+        end: usize,
+    },
+    Token {
+        text: String,
+        start: usize,
+        end: usize,
+    },
+}
+
+impl Node {
+    pub fn start(&self) -> usize {
+        match self {
+            Node::Rule { start, .. } | Node::Token { start, .. } => *start,
+        }
+    }
+
+    pub fn end(&self) -> usize {
+        match self {
+            Node::Rule { end, .. } | Node::Token { end, .. } => *end,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    //None at the end of the input
+    pub found: Option<String>,
+    pub expected: Vec<&'static str>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.found {
+            Some(found) => write!(f, "Unexpected '{found}'")?,
+            None => write!(f, "Unexpected end of input")?,
+        }
+        write!(
+            f,
+            " at line {}, column {}, expected one of: {}",
+            self.line,
+            self.column,
+            self.expected.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Clone, Copy)]
+enum Action {
+    Shift(usize),
+    Reduce(usize),
+    Accept,
+}
+
+fn regexes() -> &'static (Vec<Regex>, Vec<Regex>) {
+    static REGEXES: OnceLock<(Vec<Regex>, Vec<Regex>)> = OnceLock::new();
+    REGEXES.get_or_init(|| {
+        let compile = |pattern: &str| {
+            Regex::new(&format!("^(?:{pattern})")).expect("patterns are checked by the generator")
+        };
+        (
+            TERMINALS.iter().map(|(pattern, _, _)| compile(pattern)).collect(),
+            IGNORE.iter().map(|pattern| compile(pattern)).collect(),
+        )
+    })
+}
+
+fn skipignored(input: &str, position: usize) -> usize {
+    let mut position = position;
+    'skipping: loop {
+        for exp in &regexes().1 {
+            if let Some(found) = exp.find(&input[position..]).filter(|found| found.end() > 0) {
+                position += found.end();
+                continue 'skipping;
+            }
+        }
+        return position;
+    }
+}
+
+//reserved words only match as whole words, and never as a regex terminal
+fn reserved(text: &str, isstring: bool, rest: &str) -> bool {
+    let wordchar = |char: char| char.is_alphanumeric() || char == '_';
+    RESERVED.contains(&text)
+        && (!isstring || (text.ends_with(wordchar) && rest[text.len()..].starts_with(wordchar)))
+}
+
+//the longest matching terminal the state has an action for, literal strings win ties
+fn lookahead(state: usize, input: &str, position: usize) -> Option<(usize, usize)> {
+    let mut best: Option<(usize, bool, usize)> = None;
+    let rest = &input[position..];
+    for &(terminal, _) in ACTIONS[state] {
+        let Some(exp) = regexes().0.get(terminal) else {
+            continue;
+        };
+        let isstring = TERMINALS[terminal].1;
+        let Some(found) = exp.find(rest).filter(|found| !reserved(found.as_str(), isstring, rest)) else {
+            continue;
+        };
+        if best.is_none_or(|(length, beststring, _)| (found.end(), isstring) > (length, beststring)) {
+            best = Some((found.end(), isstring, terminal));
+        }
+    }
+    match best {
+        Some((length, _, terminal)) => Some((terminal, length)),
+        None if position == input.len() => Some((TERMINALS.len(), 0)),
+        None => None,
+    }
+}
+
+//wraps the nodes built since `offset` into the node of a rule, helpers leave them in place
+fn node(lhs: usize, nodes: &mut Vec<Node>, offset: usize, position: usize) {
+    let Some(rule) = NONTERMINALS[lhs] else {
+        return;
+    };
+    let children = nodes.split_off(offset);
+    let (start, end) = match (children.first(), children.last()) {
+        (Some(first), Some(last)) => (first.start(), last.end()),
+        _ => (position, position),
+    };
+    nodes.push(Node::Rule {
+        rule,
+        children,
+        start,
+        end,
+    });
+}
+
+fn error(input: &str, position: usize, state: usize) -> ParseError {
+    let rest = &input[position..];
+    let found = rest.chars().next().map(|first| {
+        let length = if first.is_alphanumeric() || first == '_' {
+            rest.find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len())
+        } else {
+            first.len_utf8()
+        };
+        rest[..length].to_owned()
+    });
+    let mut expected: Vec<&'static str> = ACTIONS[state]
+        .iter()
+        .map(|&(terminal, _)| TERMINALS.get(terminal).map_or("end of input", |terminal| terminal.2))
+        .collect();
+    expected.sort();
+    expected.dedup();
+    let before = &input[..position];
+    ParseError {
+        line: before.matches('\n').count() + 1,
+        column: match before.rfind('\n') {
+            Some(newline) => before[newline + 1..].chars().count() + 1,
+            None => before.chars().count() + 1,
+        },
+        found,
+        expected,
+    }
+}
+
+pub fn parse(input: &str) -> Result<Node, ParseError> {
+    let mut position = skipignored(input, 0);
+    let mut states = vec![0];
+    //the built nodes in input order, and per symbol on the stack where its nodes start
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut offsets: Vec<usize> = Vec::new();
+    loop {
+        let state = states[states.len() - 1];
+        let action = lookahead(state, input, position).and_then(|(terminal, length)| {
+            ACTIONS[state]
+                .iter()
+                .find(|&&(lookahead, _)| lookahead == terminal)
+                .map(|&(_, action)| (action, length))
+        });
+        match action {
+            None => return Err(error(input, position, state)),
+            Some((Action::Shift(target), length)) => {
+                offsets.push(nodes.len());
+                nodes.push(Node::Token {
+                    text: input[position..position + length].to_owned(),
+                    start: position,
+                    end: position + length,
+                });
+                position = skipignored(input, position + length);
+                states.push(target);
+            }
+            Some((Action::Reduce(production), _)) => {
+                let (lhs, count) = PRODUCTIONS[production];
+                let offset = offsets.drain(offsets.len() - count..).next().unwrap_or(nodes.len());
+                states.truncate(states.len() - count);
+                node(lhs, &mut nodes, offset, position);
+                offsets.push(offset);
+                let state = states[states.len() - 1];
+                let target = GOTOS[state]
+                    .iter()
+                    .find(|&&(nonterminal, _)| nonterminal == lhs)
+                    .map(|&(_, target)| target)
+                    .expect("every reduction has a goto");
+                states.push(target);
+            }
+            Some((Action::Accept, _)) => {
+                return Ok(nodes.pop().expect("the start rule always builds a node"));
+            }
+        }
+    }
+}
+
+//pattern, whether it is a literal string, description
+const TERMINALS: &[(&str, bool, &str)] = &[
+    ("=", true, "\"=\""),
+    (";", true, "\";\""),
+    ("while", true, "\"while\""),
+    ("[a-z]+", false, "identifier"),
+    ("[0-9]+", false, "number"),
+];
+
+const IGNORE: &[&str] = &[
+    "(?:[ ]+)",
+];
+
+const RESERVED: &[&str] = &[
+    "while",
+];
+
+//rule node built by each nonterminal, None for flattened helpers
+const NONTERMINALS: &[Option<Rule>] = &[
+    Some(Rule::Codebody),
+    None,
+    Some(Rule::Statement),
+    Some(Rule::Identifier),
+    Some(Rule::Number),
+];
+
+//lhs and number of symbols
+const PRODUCTIONS: &[(usize, usize)] = &[
+    (1, 2),
+    (1, 0),
+    (0, 1),
+    (2, 4),
+    (2, 3),
+    (3, 1),
+    (4, 1),
+];
+
+//per state, lookahead terminal (TERMINALS.len() is the end of the input) and action
+const ACTIONS: &[&[(usize, Action)]] = &[
+    &[(2, Action::Shift(1)), (3, Action::Shift(2)), (5, Action::Reduce(1))],
+    &[(3, Action::Shift(2))],
+    &[(0, Action::Reduce(5)), (1, Action::Reduce(5))],
+    &[(5, Action::Accept)],
+    &[(5, Action::Reduce(2))],
+    &[(2, Action::Shift(1)), (3, Action::Shift(2)), (5, Action::Reduce(1))],
+    &[(0, Action::Shift(9))],
+    &[(1, Action::Shift(10))],
+    &[(5, Action::Reduce(0))],
+    &[(4, Action::Shift(11))],
+    &[(2, Action::Reduce(4)), (3, Action::Reduce(4)), (5, Action::Reduce(4))],
+    &[(1, Action::Reduce(6))],
+    &[(1, Action::Shift(13))],
+    &[(2, Action::Reduce(3)), (3, Action::Reduce(3)), (5, Action::Reduce(3))],
+];
+
+//per state, nonterminal and the state after reducing it
+const GOTOS: &[&[(usize, usize)]] = &[
+    &[(0, 3), (1, 4), (2, 5), (3, 6)],
+    &[(3, 7)],
+    &[],
+    &[],
+    &[],
+    &[(1, 8), (2, 5), (3, 6)],
+    &[],
+    &[],
+    &[],
+    &[(4, 12)],
+    &[],
+    &[],
+    &[],
+    &[],
+];
@@ -0,0 +1,205 @@
+use std::io::{self, Write};
+
+use crate::grammar::EbnfStatement;
+use crate::parser::EbnfItem;
+
+//receives the steps of an Earley parse, positions are byte offsets into the input
+pub trait ParseObserver {
+    //an item was added for an alternative of a statement expected at `position`
+    fn predicted(&mut self, _item: &EbnfItem, _position: u32) {}
+    //the terminal matched `text` starting at `position`
+    fn scanned(&mut self, _terminal: &EbnfStatement, _position: u32, _text: &str) {}
+    //the item, started at its starttoken, is complete at `position`
+    fn completed(&mut self, _item: &EbnfItem, _position: u32) {}
+    fn tried(&mut self, _terminal: &EbnfStatement, _position: u32) {}
+    fn failed(&mut self, _terminal: &EbnfStatement, _position: u32) {}
+}
+
+//writes one line per step, the first write error stops the trace and is kept
+pub struct TracePrinter<W> {
+    pub writer: W,
+    pub error: Option<io::Error>,
+}
+
+//writes one JSON object per line and step
+pub struct JsonTraceWriter<W> {
+    pub writer: W,
+    pub error: Option<io::Error>,
+}
+
+impl<W: Write> TracePrinter<W> {
+    pub fn new(writer: W) -> Self {
+        TracePrinter {
+            writer,
+            error: None,
+        }
+    }
+
+    fn line(&mut self, position: u32, step: &str, text: &str) {
+        if self.error.is_none()
+            && let Err(err) = writeln!(self.writer, "{position:>6} {step:<9} {text}")
+        {
+            self.error = Some(err);
+        }
+    }
+}
+
+impl<W: Write> ParseObserver for TracePrinter<W> {
+    fn predicted(&mut self, item: &EbnfItem, position: u32) {
+        self.line(position, "predicted", &item.to_string());
+    }
+
+    fn scanned(&mut self, terminal: &EbnfStatement, position: u32, text: &str) {
+        self.line(
+            position,
+            "scanned",
+            &format!("{terminal} {}", jsonstring(text)),
+        );
+    }
+
+    fn completed(&mut self, item: &EbnfItem, position: u32) {
+        let text = format!("{item} from {}", item.partial.starttoken);
+        self.line(position, "completed", &text);
+    }
+
+    fn tried(&mut self, terminal: &EbnfStatement, position: u32) {
+        self.line(position, "tried", &terminal.to_string());
+    }
+
+    fn failed(&mut self, terminal: &EbnfStatement, position: u32) {
+        self.line(position, "failed", &terminal.to_string());
+    }
+}
+
+impl<W: Write> JsonTraceWriter<W> {
+    pub fn new(writer: W) -> Self {
+        JsonTraceWriter {
+            writer,
+            error: None,
+        }
+    }
+
+    fn event(&mut self, event: &str, position: u32, fields: &[(&str, String)]) {
+        if self.error.is_some() {
+            return;
+        }
+        let mut line = format!("{{\"event\":\"{event}\",\"position\":{position}");
+        for (name, value) in fields {
+            line += &format!(",\"{name}\":{value}");
+        }
+        line += "}";
+        if let Err(err) = writeln!(self.writer, "{line}") {
+            self.error = Some(err);
+        }
+    }
+
+    fn item(&mut self, event: &str, item: &EbnfItem, position: u32) {
+        let fields = [
+            ("statement", jsonstring(&item.statement.to_string())),
+            ("item", jsonstring(&item.to_string())),
+            ("start", item.partial.starttoken.to_string()),
+            ("alternative", item.partial.alternative.to_string()),
+        ];
+        self.event(event, position, &fields);
+    }
+}
+
+impl<W: Write> ParseObserver for JsonTraceWriter<W> {
+    fn predicted(&mut self, item: &EbnfItem, position: u32) {
+        self.item("predicted", item, position);
+    }
+
+    fn scanned(&mut self, terminal: &EbnfStatement, position: u32, text: &str) {
+        let fields = [
+            ("terminal", jsonstring(&terminal.to_string())),
+            ("text", jsonstring(text)),
+        ];
+        self.event("scanned", position, &fields);
+    }
+
+    fn completed(&mut self, item: &EbnfItem, position: u32) {
+        self.item("completed", item, position);
+    }
+
+    fn tried(&mut self, terminal: &EbnfStatement, position: u32) {
+        let fields = [("terminal", jsonstring(&terminal.to_string()))];
+        self.event("tried", position, &fields);
+    }
+
+    fn failed(&mut self, terminal: &EbnfStatement, position: u32) {
+        let fields = [("terminal", jsonstring(&terminal.to_string()))];
+        self.event("failed", position, &fields);
+    }
+}
+
+//a quoted and escaped JSON string
+pub(crate) fn jsonstring(text: &str) -> String {
+    let mut out = String::from("\"");
+    for char in text.chars() {
+        match char {
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            '\n' => out += "\\n",
+            '\r' => out += "\\r",
+            '\t' => out += "\\t",
+            char if (char as u32) < 0x20 => out += &format!("\\u{:04x}", char as u32),
+            char => out.push(char),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::parser::EbnfParser;
+
+    fn traced<O: ParseObserver + 'static>(observer: O) -> Rc<RefCell<O>> {
+        let observer = Rc::new(RefCell::new(observer));
+        let mut parser =
+            EbnfParser::from_str(r#"pair: "(" word ")"; word: /[a-z"]+/;"#).expect("grammar");
+        parser.observer = Some(observer.clone());
+        assert!(parser.parse(r#"(a"b)"#, "pair").is_ok());
+        observer
+    }
+
+    #[test]
+    fn theprinterwritesonelineperstep() {
+        let printer = traced(TracePrinter::new(Vec::new()));
+        let trace = String::from_utf8(printer.borrow().writer.clone()).expect("UTF-8");
+        let lines: Vec<&str> = trace.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                r#"     0 tried     "(""#,
+                r#"     0 scanned   "(" "(""#,
+                r#"     1 predicted ° word"#,
+                r#"     1 tried     /[a-z"]+/"#,
+                r#"     1 scanned   /[a-z"]+/ "a\"b""#,
+                r#"     4 completed word° from 1"#,
+                r#"     4 tried     ")""#,
+                r#"     4 scanned   ")" ")""#,
+                r#"     5 completed "(" word ")" ° from 0"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn thejsonwriterescapesthetext() {
+        let writer = traced(JsonTraceWriter::new(Vec::new()));
+        let trace = String::from_utf8(writer.borrow().writer.clone()).expect("UTF-8");
+        let scanned: Vec<&str> = trace
+            .lines()
+            .filter(|line| line.starts_with(r#"{"event":"scanned""#))
+            .collect();
+        assert_eq!(scanned.len(), 3);
+        assert_eq!(
+            scanned[1],
+            r#"{"event":"scanned","position":1,"terminal":"/[a-z\"]+/","text":"a\"b"}"#
+        );
+        assert_eq!(jsonstring("a\tb\u{1}"), r#""a\tb\u0001""#);
+    }
+}
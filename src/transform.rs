@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+
+use crate::parser::EbnfParser;
+use crate::tree::EbnfTree;
+
+//a method of a transformer for the nodes of a rule or alias, it gets the transformed children
+pub type EbnfHook<T> = fn(&mut T, Vec<<T as Transformer>::Output>) -> <T as Transformer>::Output;
+
+//the hooks of a transformer by rule or alias name, a hook for one alternative of a rule comes
+//before the one for all of them
+pub struct EbnfHooks<T: Transformer> {
+    hooks: HashMap<(String, Option<usize>), EbnfHook<T>>,
+}
+
+impl<T: Transformer> EbnfHooks<T> {
+    pub fn rule(&mut self, name: &str, hook: EbnfHook<T>) -> &mut Self {
+        self.hooks.insert((name.to_owned(), None), hook);
+        self
+    }
+
+    //the top-level alternatives of a rule count from 0 in the order they are written
+    pub fn alternative(&mut self, name: &str, alternative: usize, hook: EbnfHook<T>) -> &mut Self {
+        self.hooks
+            .insert((name.to_owned(), Some(alternative)), hook);
+        self
+    }
+
+    fn get(&self, name: &str, alternative: usize) -> Option<EbnfHook<T>> {
+        self.hooks
+            .get(&(name.to_owned(), Some(alternative)))
+            .or_else(|| self.hooks.get(&(name.to_owned(), None)))
+            .copied()
+    }
+}
+
+//folds a parse tree bottom-up, every node gets the already transformed children
+pub trait Transformer: Sized {
+    type Output;
+
+    //registers the methods for the nodes of each rule, the nodes without one go to `unknown`
+    fn hooks(_hooks: &mut EbnfHooks<Self>) {}
+
+    //regex tokens, and string tokens of !rules
+    fn token(&mut self, text: &str, start: usize, end: usize) -> Self::Output;
+
+    fn unknown(&mut self, name: &str, children: Vec<Self::Output>) -> Self::Output;
+
+    //input skipped by error recovery
+    fn error(&mut self, text: &str, start: usize, end: usize) -> Self::Output {
+        self.token(text, start, end)
+    }
+}
+
+pub(crate) enum EbnfVisible<'t> {
+    //a ?rule with a single child, which takes its place
+    Inlined(&'t EbnfTree),
+    Node(&'t str, Vec<&'t EbnfTree>),
+}
+
+impl<'a> EbnfParser<'a> {
+    //string tokens are left out unless the rule is a !rule, a ?rule with one child is replaced
+    //by it and aliased alternatives are passed to the hooks under their alias
+    pub fn transform<T: Transformer>(&self, tree: &EbnfTree, transformer: &mut T) -> T::Output {
+        let mut hooks = EbnfHooks {
+            hooks: HashMap::new(),
+        };
+        T::hooks(&mut hooks);
+        self.fold(tree, transformer, &hooks)
+    }
+
+    fn fold<T: Transformer>(
+        &self,
+        tree: &EbnfTree,
+        transformer: &mut T,
+        hooks: &EbnfHooks<T>,
+    ) -> T::Output {
+        match tree {
+            EbnfTree::Token {
+                text, start, end, ..
+            } => transformer.token(text, *start, *end),
+            EbnfTree::Error { text, start, end } => transformer.error(text, *start, *end),
+            EbnfTree::Rule {
+                name,
+                alternative,
+                children,
+                ..
+            } => match self.visible(name, *alternative, children) {
+                EbnfVisible::Inlined(child) => self.fold(child, transformer, hooks),
+                EbnfVisible::Node(name, children) => {
+                    let outputs = children
+                        .into_iter()
+                        .map(|child| self.fold(child, transformer, hooks))
+                        .collect();
+                    match hooks.get(name, *alternative) {
+                        Some(hook) => hook(transformer, outputs),
+                        None => transformer.unknown(name, outputs),
+                    }
+                }
+            },
+        }
+    }
+
+    //a rule node the way transformers see it, under its alias and without filtered tokens
+    pub(crate) fn visible<'t>(
+        &'t self,
+        name: &'t str,
+        alternative: usize,
+        children: &'t [EbnfTree],
+    ) -> EbnfVisible<'t> {
+        let keep = self.keeptokens.contains(name);
+        let mut children: Vec<&EbnfTree> = children
+            .iter()
+            .filter(|child| keep || !matches!(child, EbnfTree::Token { string: true, .. }))
+            .collect();
+        let alias = self.aliases.get(&(name.to_owned(), alternative));
+        //aliased alternatives always get their own node
+        if alias.is_none() && self.inline.contains(name) && children.len() == 1 {
+            return EbnfVisible::Inlined(children.pop().expect("one child"));
+        }
+        EbnfVisible::Node(alias.map_or(name, |alias| alias.as_str()), children)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASSIGNMENTS: &str = r#"
+statements: statement*;
+?statement: assignment | call;
+!assignment: name "=" value ";";
+call: name "(" ")" ";" -> invocation;
+?value: name | number;
+name: /[a-z]+/;
+number: /[0-9]+/;
+%ignore /[ ]+/;
+"#;
+
+    //writes nodes as name[children], rules it knows get their own spelling
+    struct Printer;
+
+    impl Printer {
+        fn number(&mut self, children: Vec<String>) -> String {
+            format!("#{}", children.concat())
+        }
+    }
+
+    impl Transformer for Printer {
+        type Output = String;
+
+        fn token(&mut self, text: &str, _: usize, _: usize) -> String {
+            text.to_owned()
+        }
+
+        fn hooks(hooks: &mut EbnfHooks<Self>) {
+            hooks.rule("number", Printer::number);
+        }
+
+        fn unknown(&mut self, name: &str, children: Vec<String>) -> String {
+            format!("{name}[{}]", children.join(" "))
+        }
+    }
+
+    fn transformed(input: &str) -> String {
+        let parser = EbnfParser::from_str(ASSIGNMENTS).expect("grammar");
+        let tree = parser.parse(input, "statements").expect("parsed");
+        parser.transform(&tree, &mut Printer)
+    }
+
+    #[test]
+    fn inlinedrulesarereplacedbytheirchild() {
+        assert_eq!(
+            transformed("a = 1; b = c;"),
+            "statements[assignment[name[a] = #1 ;] assignment[name[b] = name[c] ;]]"
+        );
+    }
+
+    #[test]
+    fn aliasesnamethenodeandstringtokensareleftout() {
+        assert_eq!(transformed("f();"), "statements[invocation[name[f]]]");
+    }
+
+    //the sign has no children left, only the alternative tells `+` and `-` apart
+    struct Calculator;
+
+    impl Calculator {
+        fn number(&mut self, children: Vec<i64>) -> i64 {
+            children.iter().product()
+        }
+    }
+
+    impl Transformer for Calculator {
+        type Output = i64;
+
+        fn hooks(hooks: &mut EbnfHooks<Self>) {
+            hooks
+                .rule("number", Calculator::number)
+                .rule("sign", |_, _| 1)
+                .alternative("sign", 1, |_, _| -1);
+        }
+
+        fn token(&mut self, text: &str, _: usize, _: usize) -> i64 {
+            text.parse().expect("digits")
+        }
+
+        fn unknown(&mut self, name: &str, _: Vec<i64>) -> i64 {
+            panic!("no hook for {name}")
+        }
+    }
+
+    #[test]
+    fn hooksdispatchonthealternative() {
+        let parser =
+            EbnfParser::from_str(r#"number: sign? /[0-9]+/; sign: "+" | "-";"#).expect("grammar");
+        let (Ok(ll1), Ok(lalr)) = (parser.ll1("number"), parser.lalr("number")) else {
+            panic!("no conflicts");
+        };
+        for (input, value) in [("-5", -5), ("+5", 5), ("5", 5)] {
+            for tree in [
+                parser.parse(input, "number"),
+                parser.parsepeg(input, "number"),
+                ll1.parse(input),
+                lalr.parse(input),
+            ] {
+                let tree = tree.expect("parsed");
+                assert_eq!(parser.transform(&tree, &mut Calculator), value);
+            }
+        }
+    }
+}
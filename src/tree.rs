@@ -0,0 +1,213 @@
+use std::fmt;
+
+use crate::trace::jsonstring;
+
+#[derive(Clone)]
+pub enum EbnfTree {
+    Rule {
+        name: String,
+        //the top-level alternative of the rule the children were parsed with
+        alternative: usize,
+        children: Vec<EbnfTree>,
+        start: usize,
+        end: usize,
+    },
+    Token {
+        text: String,
+        //matched by a string terminal, transformers leave it out unless the rule is a !rule
+        string: bool,
+        start: usize,
+        end: usize,
+    },
+    //input skipped while recovering from a syntax error
+    Error {
+        text: String,
+        start: usize,
+        end: usize,
+    },
+}
+
+impl EbnfTree {
+    pub fn start(&self) -> usize {
+        match self {
+            EbnfTree::Rule { start, .. }
+            | EbnfTree::Token { start, .. }
+            | EbnfTree::Error { start, .. } => *start,
+        }
+    }
+
+    pub fn end(&self) -> usize {
+        match self {
+            EbnfTree::Rule { end, .. }
+            | EbnfTree::Token { end, .. }
+            | EbnfTree::Error { end, .. } => *end,
+        }
+    }
+
+    //one JSON object per node, rules have a name and children, tokens and errors a text
+    pub fn tojson(&self) -> String {
+        match self {
+            EbnfTree::Rule {
+                name,
+                children,
+                start,
+                end,
+                ..
+            } => {
+                let children: Vec<String> = children.iter().map(EbnfTree::tojson).collect();
+                format!(
+                    "{{\"rule\":{},\"start\":{start},\"end\":{end},\"children\":[{}]}}",
+                    jsonstring(name),
+                    children.join(",")
+                )
+            }
+            EbnfTree::Token {
+                text, start, end, ..
+            } => {
+                let text = jsonstring(text);
+                format!("{{\"token\":{text},\"start\":{start},\"end\":{end}}}")
+            }
+            EbnfTree::Error { text, start, end } => {
+                let text = jsonstring(text);
+                format!("{{\"error\":{text},\"start\":{start},\"end\":{end}}}")
+            }
+        }
+    }
+
+    //compact and without positions, meant for comparing trees in tests
+    pub fn tosexp(&self) -> String {
+        match self {
+            EbnfTree::Rule { name, children, .. } => {
+                let mut out = format!("({name}");
+                for child in children {
+                    out += " ";
+                    out += &child.tosexp();
+                }
+                out + ")"
+            }
+            EbnfTree::Token { text, .. } => jsonstring(text),
+            EbnfTree::Error { text, .. } => format!("(error {})", jsonstring(text)),
+        }
+    }
+
+    //a Graphviz digraph, rules are boxes and tokens ellipses in input order
+    pub fn todot(&self) -> String {
+        let mut out = String::from("digraph tree {\n    ordering=out;\n");
+        self.dotnode(&mut out, &mut 0);
+        out + "}\n"
+    }
+
+    fn dotnode(&self, out: &mut String, count: &mut usize) -> usize {
+        let id = *count;
+        *count += 1;
+        let (label, attributes) = match self {
+            EbnfTree::Rule { name, .. } => (name.clone(), "shape=box"),
+            EbnfTree::Token { text, .. } => (jsonstring(text), "shape=ellipse"),
+            EbnfTree::Error { text, .. } => (jsonstring(text), "shape=ellipse, color=red"),
+        };
+        //JSON string escapes are valid in DOT strings as well
+        out.push_str(&format!(
+            "    n{id} [label={}, {attributes}];\n",
+            jsonstring(&label)
+        ));
+        if let EbnfTree::Rule { children, .. } = self {
+            for child in children {
+                let child = child.dotnode(out, count);
+                out.push_str(&format!("    n{id} -> n{child};\n"));
+            }
+        }
+        id
+    }
+
+    fn pretty(&self, f: &mut fmt::Formatter, indent: usize) -> fmt::Result {
+        let padding = "  ".repeat(indent);
+        match self {
+            EbnfTree::Token { text, .. } => writeln!(f, "{padding}{text}"),
+            EbnfTree::Error { text, .. } => writeln!(f, "{padding}<error {text:?}>"),
+            EbnfTree::Rule { name, children, .. } => {
+                if let [EbnfTree::Token { text, .. }] = children.as_slice() {
+                    return writeln!(f, "{padding}{name}\t{text}");
+                }
+                writeln!(f, "{padding}{name}")?;
+                for child in children {
+                    child.pretty(f, indent + 1)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for EbnfTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.pretty(f, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //sum for `1+"2"` with the unparsable rest skipped
+    fn tree() -> EbnfTree {
+        let token = |text: &str, start| EbnfTree::Token {
+            text: text.to_owned(),
+            string: false,
+            start,
+            end: start + text.len(),
+        };
+        EbnfTree::Rule {
+            name: "sum".to_owned(),
+            alternative: 0,
+            children: vec![
+                token("1", 0),
+                token("+", 1),
+                token("\"2\"", 2),
+                EbnfTree::Error {
+                    text: "?".to_owned(),
+                    start: 5,
+                    end: 6,
+                },
+            ],
+            start: 0,
+            end: 6,
+        }
+    }
+
+    #[test]
+    fn jsonhasthespansofeverynode() {
+        assert_eq!(
+            tree().tojson(),
+            concat!(
+                r#"{"rule":"sum","start":0,"end":6,"children":["#,
+                r#"{"token":"1","start":0,"end":1},{"token":"+","start":1,"end":2},"#,
+                r#"{"token":"\"2\"","start":2,"end":5},{"error":"?","start":5,"end":6}]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn sexpsleaveoutthepositions() {
+        assert_eq!(tree().tosexp(), r#"(sum "1" "+" "\"2\"" (error "?"))"#);
+    }
+
+    #[test]
+    fn dotnumbersthenodesininputorder() {
+        assert_eq!(
+            tree().todot(),
+            concat!(
+                "digraph tree {\n    ordering=out;\n",
+                "    n0 [label=\"sum\", shape=box];\n",
+                r#"    n1 [label="\"1\"", shape=ellipse];"#,
+                "\n    n0 -> n1;\n",
+                r#"    n2 [label="\"+\"", shape=ellipse];"#,
+                "\n    n0 -> n2;\n",
+                r#"    n3 [label="\"\\\"2\\\"\"", shape=ellipse];"#,
+                "\n    n0 -> n3;\n",
+                r#"    n4 [label="\"?\"", shape=ellipse, color=red];"#,
+                "\n    n0 -> n4;\n",
+                "}\n"
+            )
+        );
+    }
+}